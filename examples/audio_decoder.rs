@@ -1,72 +1,64 @@
-use std::{thread::sleep, time::Duration};
-
 use wcodecs::{
-    codec::{AudioDecoder, AudioDecoderConfig},
-    data::audio_data::EncodedAudioChunk,
+    codec::AudioDecoder,
+    demux::{Demuxer, EncodedChunk, TrackConfig},
 };
 
 fn main() {
     // ffmpeg_next::init() is not needed because it is already done in wcodecs internally (if not already initialised).
 
+    let input_path = "./examples/samples/beep.mp3";
+    let mut demuxer = match Demuxer::open(input_path) {
+        Ok(demuxer) => demuxer,
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", input_path, e);
+            return;
+        }
+    };
+
+    let Some((track_index, config)) =
+        demuxer
+            .tracks()
+            .iter()
+            .find_map(|track| match &track.config {
+                TrackConfig::Audio(config) => Some((track.index, config.clone())),
+                TrackConfig::Video(_) => None,
+            })
+    else {
+        eprintln!("No audio track found.");
+        return;
+    };
+
     let mut decoder = AudioDecoder::new(
         |audio_data| {
             println!("Decoded AudioData: {:?}", audio_data);
         },
         |err| {
-            eprintln!("Decoder error: {:?}", err);
+            eprintln!("Decoder error: {err}");
         },
     );
 
-    let config = AudioDecoderConfig {
-        codec: "mp3".to_string(),
-        sample_rate: 44100,
-        number_of_channels: 2,
-    };
-
-    if let Err(e) = decoder.configure(config.clone()) {
+    if let Err(e) = decoder.configure(config) {
         eprintln!("Failed to configure decoder: {:?}", e);
         return;
     }
 
-    let input_path = "./examples/samples/beep.mp3";
-    let mut ictx = match ffmpeg_next::format::input(input_path) {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            eprintln!("Failed to open {}: {:?}", input_path, e);
-            return;
-        }
-    };
-
-    let audio_stream_index = ictx
-        .streams()
-        .best(ffmpeg_next::media::Type::Audio)
-        .map(|s| s.index())
-        .unwrap_or_else(|| {
-            eprintln!("No audio stream found.");
-            std::process::exit(1);
-        });
-
-    for (stream, packet) in ictx.packets() {
-        if stream.index() != audio_stream_index {
-            continue;
-        }
-
-        let data = match packet.data() {
-            Some(d) => d.to_vec(),
-            None => {
-                eprintln!("No data found in packet.");
-                continue;
+    for result in &mut demuxer {
+        let (index, chunk) = match result {
+            Ok(read) => read,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", input_path, e);
+                break;
             }
         };
-        let timestamp = packet.pts().unwrap_or(0);
-
-        let encoded_chunk = EncodedAudioChunk {
-            data,
-            timestamp,
-            is_key: true,
+        let EncodedChunk::Audio(chunk) = chunk else {
+            continue;
         };
+        if index != track_index {
+            continue;
+        }
 
-        if let Err(e) = decoder.decode(encoded_chunk) {
+        let timestamp = chunk.timestamp;
+        if let Err(e) = decoder.decode(chunk) {
             eprintln!(
                 "Failed to decode packet at timestamp {}: {:?}",
                 timestamp, e
@@ -74,15 +66,12 @@ fn main() {
         } else {
             println!("Sent packet at timestamp {}", timestamp);
         }
-
-        sleep(Duration::from_millis(50));
     }
 
-    if let Err(e) = decoder.flush() {
+    if let Err(e) = decoder.flush().and_then(|flushed| flushed.wait()) {
         eprintln!("Failed to flush decoder: {:?}", e);
     }
 
-    decoder.reset();
     decoder.close();
 
     println!("AudioDecoder closed.");
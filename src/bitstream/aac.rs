@@ -0,0 +1,305 @@
+use crate::codec::{Exception, ExceptionKind};
+
+use super::{BitReader, BitWriter};
+
+const SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+const OBJECT_TYPE_SBR: u8 = 5;
+const OBJECT_TYPE_PS: u8 = 29;
+
+/// The AudioSpecificConfig of ISO/IEC 14496-3, the `description` of AAC in MP4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioSpecificConfig {
+    /// The audio object type of the core coder, e.g. 2 for AAC-LC.
+    pub object_type: u8,
+    /// The sample rate of the core coder.
+    pub sample_rate: u32,
+    /// The channel configuration; 0 means the channels are defined in-band.
+    pub channel_configuration: u8,
+    /// The output sample rate of spectral band replication (HE-AAC), if signalled.
+    pub sbr_sample_rate: Option<u32>,
+    /// Whether parametric stereo (HE-AAC v2) is signalled.
+    pub ps: bool,
+}
+
+impl AudioSpecificConfig {
+    /// A config for a plain object type, e.g. 2 for AAC-LC.
+    pub fn new(object_type: u8, sample_rate: u32, channels: u32) -> Result<Self, Exception> {
+        // 31 is the escape value; the escaped types run from 32 to 95.
+        if matches!(object_type, 0 | 31 | 96..) {
+            return Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("invalid AAC audio object type {object_type}"),
+            ));
+        }
+        let channel_configuration = match channels {
+            1..=6 => channels as u8,
+            8 => 7,
+            _ => {
+                return Err(Exception::new(
+                    ExceptionKind::NotSupportedError,
+                    format!("no AAC channel configuration for {channels} channels"),
+                ))
+            }
+        };
+        Ok(Self {
+            object_type,
+            sample_rate,
+            channel_configuration,
+            sbr_sample_rate: None,
+            ps: false,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Exception> {
+        let mut reader = BitReader::new(data);
+        let mut object_type = read_object_type(&mut reader)?;
+        let sample_rate = read_sample_rate(&mut reader)?;
+        let channel_configuration = reader.bits(4)? as u8;
+        let mut config = Self {
+            object_type,
+            sample_rate,
+            channel_configuration,
+            sbr_sample_rate: None,
+            ps: false,
+        };
+
+        // Explicit hierarchical signalling: the extension comes first, then the core.
+        if matches!(object_type, OBJECT_TYPE_SBR | OBJECT_TYPE_PS) {
+            config.ps = object_type == OBJECT_TYPE_PS;
+            config.sbr_sample_rate = Some(read_sample_rate(&mut reader)?);
+            object_type = read_object_type(&mut reader)?;
+            config.object_type = object_type;
+        }
+
+        // Backward compatible signalling follows the GASpecificConfig, which can only be
+        // skipped for the AAC object types without an in-band channel layout.
+        if !matches!(object_type, 1..=4 | 6 | 7 | 17 | 19..=23) || channel_configuration == 0 {
+            return Ok(config);
+        }
+        let _frame_length_flag = reader.bit()?;
+        if reader.bit()? == 1 {
+            // coreCoderDelay
+            reader.skip(14);
+        }
+        let extension_flag = reader.bit()?;
+        if matches!(object_type, 6 | 20) {
+            // layerNr
+            reader.skip(3);
+        }
+        if extension_flag == 1 {
+            if object_type == 22 {
+                // numOfSubFrame, layer_length
+                reader.skip(16);
+            }
+            if matches!(object_type, 17 | 19..=23) {
+                // The three resilience flags.
+                reader.skip(3);
+            }
+            // extensionFlag3
+            reader.skip(1);
+        }
+
+        if config.sbr_sample_rate.is_none() && reader.bits_left() >= 16 && reader.bits(11)? == 0x2b7
+        {
+            let extension_type = read_object_type(&mut reader)?;
+            if extension_type == OBJECT_TYPE_SBR && reader.bit()? == 1 {
+                config.sbr_sample_rate = Some(read_sample_rate(&mut reader)?);
+                if reader.bits_left() >= 12 && reader.bits(11)? == 0x548 {
+                    config.ps = reader.bit()? == 1;
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Writes the config, with explicit hierarchical signalling of SBR and PS.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        match self.sbr_sample_rate {
+            Some(sbr_sample_rate) => {
+                let extension_type = if self.ps {
+                    OBJECT_TYPE_PS
+                } else {
+                    OBJECT_TYPE_SBR
+                };
+                write_object_type(&mut writer, extension_type);
+                write_sample_rate(&mut writer, self.sample_rate);
+                writer.bits(4, self.channel_configuration as u32);
+                write_sample_rate(&mut writer, sbr_sample_rate);
+                write_object_type(&mut writer, self.object_type);
+            }
+            None => {
+                write_object_type(&mut writer, self.object_type);
+                write_sample_rate(&mut writer, self.sample_rate);
+                writer.bits(4, self.channel_configuration as u32);
+            }
+        }
+        // GASpecificConfig: 1024-sample frames, no core coder, no extension.
+        writer.bits(3, 0);
+        writer.finish()
+    }
+
+    /// The 7-byte ADTS header, without CRC, of a frame of `payload_size` bytes of raw AAC.
+    pub fn adts_header(&self, payload_size: usize) -> Result<[u8; 7], Exception> {
+        let frame_size = payload_size + 7;
+        let sample_rate_index = SAMPLE_RATES
+            .iter()
+            .position(|&rate| rate == self.sample_rate);
+        let (Some(sample_rate_index), 1..=4, 0..=7, 0..=0x1fff) = (
+            sample_rate_index,
+            self.object_type,
+            self.channel_configuration,
+            frame_size,
+        ) else {
+            return Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                "AAC config cannot be expressed in an ADTS header",
+            ));
+        };
+        let mut writer = BitWriter::default();
+        writer.bits(12, 0xfff);
+        // MPEG-4, layer 0, no CRC.
+        writer.bits(4, 0b0001);
+        writer.bits(2, self.object_type as u32 - 1);
+        writer.bits(4, sample_rate_index as u32);
+        writer.bits(1, 0);
+        writer.bits(3, self.channel_configuration as u32);
+        writer.bits(4, 0);
+        writer.bits(13, frame_size as u32);
+        // Variable bitrate buffer fullness, one raw data block.
+        writer.bits(11, 0x7ff);
+        writer.bits(2, 0);
+        let mut header = [0; 7];
+        header.copy_from_slice(&writer.finish());
+        Ok(header)
+    }
+
+    /// The `mp4a.40.` codec string, naming the extension for HE-AAC.
+    pub fn codec_string(&self) -> String {
+        let object_type = match (self.sbr_sample_rate, self.ps) {
+            (Some(_), true) => OBJECT_TYPE_PS,
+            (Some(_), false) => OBJECT_TYPE_SBR,
+            _ => self.object_type,
+        };
+        format!("mp4a.40.{object_type}")
+    }
+
+    /// The sample rate of the decoded audio.
+    pub fn output_sample_rate(&self) -> u32 {
+        self.sbr_sample_rate.unwrap_or(self.sample_rate)
+    }
+
+    /// The number of decoded channels, if the channel configuration defines it.
+    pub fn output_channels(&self) -> Option<u32> {
+        match self.channel_configuration {
+            // Parametric stereo turns a mono core into stereo.
+            1 if self.ps => Some(2),
+            1..=6 => Some(self.channel_configuration as u32),
+            7 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+fn read_object_type(reader: &mut BitReader) -> Result<u8, Exception> {
+    match reader.bits(5)? {
+        31 => Ok(32 + reader.bits(6)? as u8),
+        object_type => Ok(object_type as u8),
+    }
+}
+
+fn write_object_type(writer: &mut BitWriter, object_type: u8) {
+    if object_type >= 32 {
+        writer.bits(5, 31);
+        writer.bits(6, (object_type - 32) as u32);
+    } else {
+        writer.bits(5, object_type as u32);
+    }
+}
+
+fn read_sample_rate(reader: &mut BitReader) -> Result<u32, Exception> {
+    match reader.bits(4)? as usize {
+        0x0f => reader.bits(24),
+        index => SAMPLE_RATES.get(index).copied().ok_or_else(|| {
+            Exception::new(
+                ExceptionKind::DataError,
+                format!("reserved AAC sampling frequency index {index}"),
+            )
+        }),
+    }
+}
+
+fn write_sample_rate(writer: &mut BitWriter, sample_rate: u32) {
+    match SAMPLE_RATES.iter().position(|&rate| rate == sample_rate) {
+        Some(index) => writer.bits(4, index as u32),
+        None => {
+            writer.bits(4, 0x0f);
+            writer.bits(24, sample_rate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_aac_lc() {
+        let config = AudioSpecificConfig::parse(&[0x12, 0x10]).unwrap();
+        assert_eq!(config, AudioSpecificConfig::new(2, 44_100, 2).unwrap());
+        assert_eq!(config.to_bytes(), [0x12, 0x10]);
+        assert_eq!(config.codec_string(), "mp4a.40.2");
+    }
+
+    #[test]
+    fn reads_both_kinds_of_he_aac_signalling() {
+        let explicit = AudioSpecificConfig::parse(&[0x2b, 0x11, 0x88, 0x00]).unwrap();
+        let mut expected = AudioSpecificConfig::new(2, 24_000, 2).unwrap();
+        expected.sbr_sample_rate = Some(48_000);
+        assert_eq!(explicit, expected);
+        assert_eq!(explicit.to_bytes(), [0x2b, 0x11, 0x88, 0x00]);
+        assert_eq!(explicit.codec_string(), "mp4a.40.5");
+
+        // The SBR and PS sync extensions after the GASpecificConfig.
+        let backward_compatible =
+            AudioSpecificConfig::parse(&[0x13, 0x10, 0x56, 0xe5, 0x9d, 0x48, 0x80]).unwrap();
+        expected.ps = true;
+        assert_eq!(backward_compatible, expected);
+        assert_eq!(backward_compatible.codec_string(), "mp4a.40.29");
+        assert_eq!(backward_compatible.output_sample_rate(), 48_000);
+        assert_eq!(backward_compatible.output_channels(), Some(2));
+    }
+
+    #[test]
+    fn escapes_object_types_from_32() {
+        let usac = AudioSpecificConfig::new(42, 48_000, 2).unwrap();
+        assert_eq!(usac.to_bytes(), [0xf9, 0x46, 0x40]);
+        assert_eq!(AudioSpecificConfig::parse(&usac.to_bytes()).unwrap(), usac);
+
+        let error = AudioSpecificConfig::new(31, 48_000, 2).unwrap_err();
+        assert_eq!(error.kind(), ExceptionKind::NotSupportedError);
+    }
+
+    #[test]
+    fn rejects_truncated_configs() {
+        for data in [&[][..], &[0x12], &[0xf9], &[0x17, 0x80, 0x00]] {
+            let error = AudioSpecificConfig::parse(data).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{data:02x?}");
+        }
+    }
+
+    #[test]
+    fn writes_adts_headers() {
+        let config = AudioSpecificConfig::new(2, 44_100, 2).unwrap();
+        assert_eq!(
+            config.adts_header(100).unwrap(),
+            [0xff, 0xf1, 0x50, 0x80, 0x0d, 0x7f, 0xfc]
+        );
+        assert!(config.adts_header(0x2000).is_err());
+        let usac = AudioSpecificConfig::new(42, 44_100, 2).unwrap();
+        assert!(usac.adts_header(100).is_err());
+    }
+}
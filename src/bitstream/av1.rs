@@ -0,0 +1,31 @@
+/// The `temporal_id` of the first OBU in `data` that has an extension header, i.e. the
+/// temporal layer of an AV1 temporal unit. `None` if no OBU has an extension header.
+pub fn av1_temporal_id(data: &[u8]) -> Option<u8> {
+    let mut rest = data;
+    while let [header, tail @ ..] = rest {
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        if has_extension {
+            return tail.first().map(|extension| extension >> 5);
+        }
+        if !has_size {
+            // The OBU runs to the end of the data.
+            return None;
+        }
+        let (size, read) = read_leb128(tail)?;
+        rest = tail.get(read + size..)?;
+    }
+    None
+}
+
+/// Reads an unsigned LEB128 value, returning it and the number of bytes read.
+fn read_leb128(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
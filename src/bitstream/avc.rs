@@ -0,0 +1,255 @@
+use crate::codec::{Exception, ExceptionKind};
+
+use super::{split_annexb, truncated, unescape_rbsp, BitReader};
+
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+
+/// The AVCDecoderConfigurationRecord (avcC) of ISO/IEC 14496-15, the `description` of
+/// H.264 in the "avc" format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcDecoderConfigurationRecord {
+    pub profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub level_indication: u8,
+    /// The size in bytes of the NAL unit lengths in chunks.
+    pub length_size: u8,
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+    /// `(chroma_format, bit_depth_luma, bit_depth_chroma)`, stored for the high profiles.
+    pub high_profile_info: Option<(u8, u8, u8)>,
+}
+
+impl AvcDecoderConfigurationRecord {
+    /// Builds the record from the parameter sets found in Annex B data, e.g. the extradata
+    /// of an encoder or a key chunk.
+    pub fn from_annexb(data: &[u8]) -> Result<Self, Exception> {
+        let units = split_annexb(data);
+        let of_type = |nal_type| -> Vec<Vec<u8>> {
+            units
+                .iter()
+                .filter(|unit| unit[0] & 0x1f == nal_type)
+                .map(|unit| unit.to_vec())
+                .collect()
+        };
+        Self::from_parameter_sets(of_type(NAL_SPS), of_type(NAL_PPS))
+    }
+
+    pub fn from_parameter_sets(
+        sequence_parameter_sets: Vec<Vec<u8>>,
+        picture_parameter_sets: Vec<Vec<u8>>,
+    ) -> Result<Self, Exception> {
+        let sps = sequence_parameter_sets.first().ok_or_else(|| {
+            Exception::new(ExceptionKind::DataError, "no H.264 sequence parameter set")
+        })?;
+        let rbsp = unescape_rbsp(sps);
+        if rbsp.len() < 4 {
+            return Err(truncated());
+        }
+        let profile = rbsp[1];
+        let high_profile_info = if matches!(profile, 100 | 110 | 122 | 144) {
+            let mut reader = BitReader::new(&rbsp[4..]);
+            let _seq_parameter_set_id = reader.ue()?;
+            let chroma_format = reader.ue()?;
+            if chroma_format == 3 {
+                reader.skip(1);
+            }
+            let bit_depth_luma = bit_depth(reader.ue()?)?;
+            let bit_depth_chroma = bit_depth(reader.ue()?)?;
+            Some((chroma_format as u8, bit_depth_luma, bit_depth_chroma))
+        } else {
+            None
+        };
+        Ok(Self {
+            profile_indication: profile,
+            profile_compatibility: rbsp[2],
+            level_indication: rbsp[3],
+            length_size: 4,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            high_profile_info,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Exception> {
+        if data.len() < 6 || data[0] != 1 {
+            return Err(Exception::new(
+                ExceptionKind::DataError,
+                "not an AVCDecoderConfigurationRecord",
+            ));
+        }
+        let mut rest = &data[5..];
+        let sequence_parameter_sets = read_parameter_sets(&mut rest, (data[5] & 0x1f) as usize, 1)?;
+        let count = *rest.first().ok_or_else(truncated)? as usize;
+        let picture_parameter_sets = read_parameter_sets(&mut rest, count, 1)?;
+        let high_profile_info = match rest {
+            [chroma, luma, chroma_depth, ..] if matches!(data[1], 100 | 110 | 122 | 144) => {
+                Some((chroma & 0x03, (luma & 0x07) + 8, (chroma_depth & 0x07) + 8))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            profile_indication: data[1],
+            profile_compatibility: data[2],
+            level_indication: data[3],
+            length_size: (data[4] & 0x03) + 1,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            high_profile_info,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![
+            1,
+            self.profile_indication,
+            self.profile_compatibility,
+            self.level_indication,
+            0xfc | (self.length_size.clamp(1, 4) - 1),
+            0xe0 | self.sequence_parameter_sets.len() as u8,
+        ];
+        write_parameter_sets(&mut out, &self.sequence_parameter_sets);
+        out.push(self.picture_parameter_sets.len() as u8);
+        write_parameter_sets(&mut out, &self.picture_parameter_sets);
+        if let Some((chroma_format, bit_depth_luma, bit_depth_chroma)) = self.high_profile_info {
+            out.push(0xfc | (chroma_format & 0x03));
+            out.push(0xf8 | (bit_depth_luma.saturating_sub(8) & 0x07));
+            out.push(0xf8 | (bit_depth_chroma.saturating_sub(8) & 0x07));
+            out.push(0);
+        }
+        out
+    }
+
+    /// The `avc1.PPCCLL` codec string.
+    pub fn codec_string(&self) -> String {
+        format!(
+            "avc1.{:02x}{:02x}{:02x}",
+            self.profile_indication, self.profile_compatibility, self.level_indication
+        )
+    }
+
+    /// The parameter sets as Annex B, to put in front of a key chunk.
+    pub fn to_annexb(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for unit in self
+            .sequence_parameter_sets
+            .iter()
+            .chain(&self.picture_parameter_sets)
+        {
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(unit);
+        }
+        out
+    }
+}
+
+/// The bit depth coded as `bit_depth_minus8` in an SPS, up to the 15 bits the 3-bit field
+/// of avcC and hvcC can hold.
+pub(super) fn bit_depth(bit_depth_minus8: u32) -> Result<u8, Exception> {
+    if bit_depth_minus8 > 7 {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            format!("invalid bit depth {bit_depth_minus8} + 8"),
+        ));
+    }
+    Ok(bit_depth_minus8 as u8 + 8)
+}
+
+/// Reads `count` parameter sets, skipping `skip` bytes first, each prefixed by a 16-bit
+/// length.
+pub(super) fn read_parameter_sets(
+    rest: &mut &[u8],
+    count: usize,
+    skip: usize,
+) -> Result<Vec<Vec<u8>>, Exception> {
+    *rest = rest.get(skip..).ok_or_else(truncated)?;
+    let mut sets = Vec::with_capacity(count);
+    for _ in 0..count {
+        let [high, low, ..] = **rest else {
+            return Err(truncated());
+        };
+        let length = u16::from_be_bytes([high, low]) as usize;
+        let set = rest.get(2..2 + length).ok_or_else(truncated)?;
+        sets.push(set.to_vec());
+        *rest = &rest[2 + length..];
+    }
+    Ok(sets)
+}
+
+pub(super) fn write_parameter_sets(out: &mut Vec<u8>, sets: &[Vec<u8>]) {
+    for set in sets {
+        out.extend_from_slice(&(set.len() as u16).to_be_bytes());
+        out.extend_from_slice(set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A High profile, level 3.1 SPS as written by x264, with emulation prevention bytes.
+    const SPS: [u8; 26] = [
+        0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x50, 0x05, 0xbb, 0x01, 0x10, 0x00, 0x00, 0x03,
+        0x00, 0x10, 0x00, 0x00, 0x03, 0x03, 0xc0, 0xf1, 0x83, 0x19, 0x60,
+    ];
+    const PPS: [u8; 6] = [0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+
+    fn avcc() -> Vec<u8> {
+        let mut avcc = vec![0x01, 0x64, 0x00, 0x1f, 0xff, 0xe1, 0x00, 0x1a];
+        avcc.extend_from_slice(&SPS);
+        avcc.extend_from_slice(&[0x01, 0x00, 0x06]);
+        avcc.extend_from_slice(&PPS);
+        avcc.extend_from_slice(&[0xfd, 0xf8, 0xf8, 0x00]);
+        avcc
+    }
+
+    #[test]
+    fn builds_the_record_from_annexb_parameter_sets() {
+        let mut annexb = vec![0, 0, 0, 1];
+        annexb.extend_from_slice(&SPS);
+        annexb.extend_from_slice(&[0, 0, 1]);
+        annexb.extend_from_slice(&PPS);
+
+        let record = AvcDecoderConfigurationRecord::from_annexb(&annexb).unwrap();
+        assert_eq!(record.high_profile_info, Some((1, 8, 8)));
+        assert_eq!(record.codec_string(), "avc1.64001f");
+        assert_eq!(record.to_bytes(), avcc());
+        assert_eq!(record.to_annexb()[4..4 + SPS.len()], SPS);
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let record = AvcDecoderConfigurationRecord::parse(&avcc()).unwrap();
+        assert_eq!(record.sequence_parameter_sets, [SPS.to_vec()]);
+        assert_eq!(record.picture_parameter_sets, [PPS.to_vec()]);
+        assert_eq!(record.length_size, 4);
+        assert_eq!(record.to_bytes(), avcc());
+    }
+
+    #[test]
+    fn rejects_truncated_records() {
+        let avcc = avcc();
+        // The trailing high profile fields are optional.
+        for length in 0..avcc.len() - 4 {
+            let error = AvcDecoderConfigurationRecord::parse(&avcc[..length]).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{length} bytes");
+        }
+        let sps = &SPS[..4];
+        assert!(
+            AvcDecoderConfigurationRecord::from_parameter_sets(vec![sps.to_vec()], vec![]).is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_bit_depths_the_record_cannot_hold() {
+        // bit_depth_luma_minus8 = 8, coded as 0001001.
+        let sps = vec![0x67, 0x64, 0x00, 0x1f, 0xa1, 0x30];
+        let error =
+            AvcDecoderConfigurationRecord::from_parameter_sets(vec![sps], vec![]).unwrap_err();
+        assert_eq!(error.kind(), ExceptionKind::DataError);
+
+        let mut record = AvcDecoderConfigurationRecord::parse(&avcc()).unwrap();
+        record.high_profile_info = Some((1, 0, 0));
+        assert_eq!(record.to_bytes()[avcc().len() - 3..], [0xf8, 0xf8, 0x00]);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::codec::{Exception, ExceptionKind};
+
+use super::{
+    avc::{bit_depth, read_parameter_sets, write_parameter_sets},
+    split_annexb, truncated, unescape_rbsp, BitReader,
+};
+
+const NAL_VPS: u8 = 32;
+const NAL_SPS: u8 = 33;
+const NAL_PPS: u8 = 34;
+
+/// The HEVCDecoderConfigurationRecord (hvcC) of ISO/IEC 14496-15, the `description` of
+/// HEVC in the "hevc" format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcDecoderConfigurationRecord {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// The 48 constraint indicator flags, in the low bits.
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub chroma_format: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    /// The size in bytes of the NAL unit lengths in chunks.
+    pub length_size: u8,
+    pub video_parameter_sets: Vec<Vec<u8>>,
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+}
+
+impl HevcDecoderConfigurationRecord {
+    /// Builds the record from the parameter sets found in Annex B data, e.g. the extradata
+    /// of an encoder or a key chunk.
+    pub fn from_annexb(data: &[u8]) -> Result<Self, Exception> {
+        let units = split_annexb(data);
+        let of_type = |nal_type| -> Vec<Vec<u8>> {
+            units
+                .iter()
+                .filter(|unit| (unit[0] >> 1) & 0x3f == nal_type)
+                .map(|unit| unit.to_vec())
+                .collect()
+        };
+        Self::from_parameter_sets(of_type(NAL_VPS), of_type(NAL_SPS), of_type(NAL_PPS))
+    }
+
+    pub fn from_parameter_sets(
+        video_parameter_sets: Vec<Vec<u8>>,
+        sequence_parameter_sets: Vec<Vec<u8>>,
+        picture_parameter_sets: Vec<Vec<u8>>,
+    ) -> Result<Self, Exception> {
+        let sps = sequence_parameter_sets.first().ok_or_else(|| {
+            Exception::new(ExceptionKind::DataError, "no HEVC sequence parameter set")
+        })?;
+        let rbsp = unescape_rbsp(sps);
+        // NAL unit header, then the fixed-size start of the SPS and the general
+        // profile_tier_level.
+        if rbsp.len() < 15 {
+            return Err(truncated());
+        }
+        let max_sub_layers_minus1 = (rbsp[2] >> 1) & 0x07;
+        let ptl = &rbsp[3..15];
+        let mut constraints = [0u8; 8];
+        constraints[2..].copy_from_slice(&ptl[5..11]);
+
+        let mut reader = BitReader::new(&rbsp[15..]);
+        let mut sub_layer_profile_present = Vec::new();
+        let mut sub_layer_level_present = Vec::new();
+        for _ in 0..max_sub_layers_minus1 {
+            sub_layer_profile_present.push(reader.bit()? == 1);
+            sub_layer_level_present.push(reader.bit()? == 1);
+        }
+        if max_sub_layers_minus1 > 0 {
+            reader.skip(2 * (8 - max_sub_layers_minus1 as usize));
+        }
+        for (profile, level) in sub_layer_profile_present
+            .iter()
+            .zip(&sub_layer_level_present)
+        {
+            if *profile {
+                reader.skip(88);
+            }
+            if *level {
+                reader.skip(8);
+            }
+        }
+        let _sps_seq_parameter_set_id = reader.ue()?;
+        let chroma_format = reader.ue()?;
+        if chroma_format == 3 {
+            reader.skip(1);
+        }
+        let _width = reader.ue()?;
+        let _height = reader.ue()?;
+        if reader.bit()? == 1 {
+            for _ in 0..4 {
+                reader.ue()?;
+            }
+        }
+        let bit_depth_luma = bit_depth(reader.ue()?)?;
+        let bit_depth_chroma = bit_depth(reader.ue()?)?;
+
+        Ok(Self {
+            general_profile_space: ptl[0] >> 6,
+            general_tier_flag: ptl[0] & 0x20 != 0,
+            general_profile_idc: ptl[0] & 0x1f,
+            general_profile_compatibility_flags: u32::from_be_bytes([
+                ptl[1], ptl[2], ptl[3], ptl[4],
+            ]),
+            general_constraint_indicator_flags: u64::from_be_bytes(constraints),
+            general_level_idc: ptl[11],
+            chroma_format: chroma_format as u8,
+            bit_depth_luma,
+            bit_depth_chroma,
+            num_temporal_layers: max_sub_layers_minus1 + 1,
+            temporal_id_nested: rbsp[2] & 1 == 1,
+            length_size: 4,
+            video_parameter_sets,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Exception> {
+        if data.len() < 23 || data[0] != 1 {
+            return Err(Exception::new(
+                ExceptionKind::DataError,
+                "not an HEVCDecoderConfigurationRecord",
+            ));
+        }
+        let mut constraints = [0u8; 8];
+        constraints[2..].copy_from_slice(&data[6..12]);
+        let mut record = Self {
+            general_profile_space: data[1] >> 6,
+            general_tier_flag: data[1] & 0x20 != 0,
+            general_profile_idc: data[1] & 0x1f,
+            general_profile_compatibility_flags: u32::from_be_bytes([
+                data[2], data[3], data[4], data[5],
+            ]),
+            general_constraint_indicator_flags: u64::from_be_bytes(constraints),
+            general_level_idc: data[12],
+            chroma_format: data[16] & 0x03,
+            bit_depth_luma: (data[17] & 0x07) + 8,
+            bit_depth_chroma: (data[18] & 0x07) + 8,
+            num_temporal_layers: (data[21] >> 3) & 0x07,
+            temporal_id_nested: data[21] & 0x04 != 0,
+            length_size: (data[21] & 0x03) + 1,
+            video_parameter_sets: Vec::new(),
+            sequence_parameter_sets: Vec::new(),
+            picture_parameter_sets: Vec::new(),
+        };
+
+        let mut rest = &data[23..];
+        for _ in 0..data[22] {
+            let [array_header, high, low, ..] = *rest else {
+                return Err(truncated());
+            };
+            let count = u16::from_be_bytes([high, low]) as usize;
+            let sets = read_parameter_sets(&mut rest, count, 3)?;
+            match array_header & 0x3f {
+                NAL_VPS => record.video_parameter_sets.extend(sets),
+                NAL_SPS => record.sequence_parameter_sets.extend(sets),
+                NAL_PPS => record.picture_parameter_sets.extend(sets),
+                _ => {}
+            }
+        }
+        Ok(record)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![
+            1,
+            (self.general_profile_space << 6)
+                | ((self.general_tier_flag as u8) << 5)
+                | self.general_profile_idc,
+        ];
+        out.extend_from_slice(&self.general_profile_compatibility_flags.to_be_bytes());
+        out.extend_from_slice(&self.general_constraint_indicator_flags.to_be_bytes()[2..]);
+        out.push(self.general_level_idc);
+        // min_spatial_segmentation_idc and parallelismType unknown.
+        out.extend_from_slice(&[0xf0, 0x00, 0xfc]);
+        out.push(0xfc | (self.chroma_format & 0x03));
+        out.push(0xf8 | (self.bit_depth_luma.saturating_sub(8) & 0x07));
+        out.push(0xf8 | (self.bit_depth_chroma.saturating_sub(8) & 0x07));
+        // avgFrameRate unknown.
+        out.extend_from_slice(&[0, 0]);
+        out.push(
+            ((self.num_temporal_layers & 0x07) << 3)
+                | ((self.temporal_id_nested as u8) << 2)
+                | (self.length_size.clamp(1, 4) - 1),
+        );
+        let arrays = [
+            (NAL_VPS, &self.video_parameter_sets),
+            (NAL_SPS, &self.sequence_parameter_sets),
+            (NAL_PPS, &self.picture_parameter_sets),
+        ];
+        out.push(arrays.iter().filter(|(_, sets)| !sets.is_empty()).count() as u8);
+        for (nal_type, sets) in arrays {
+            if sets.is_empty() {
+                continue;
+            }
+            // array_completeness: all parameter sets of this type are in the record.
+            out.push(0x80 | nal_type);
+            out.extend_from_slice(&(sets.len() as u16).to_be_bytes());
+            write_parameter_sets(&mut out, sets);
+        }
+        out
+    }
+
+    /// The `hvc1.` codec string.
+    pub fn codec_string(&self) -> String {
+        let profile_space = match self.general_profile_space {
+            1 => "A",
+            2 => "B",
+            3 => "C",
+            _ => "",
+        };
+        let tier = if self.general_tier_flag { 'H' } else { 'L' };
+        let mut constraints = self.general_constraint_indicator_flags.to_be_bytes()[2..].to_vec();
+        while constraints.len() > 1 && constraints.last() == Some(&0) {
+            constraints.pop();
+        }
+        let constraints: Vec<String> = constraints.iter().map(|b| format!("{b:02X}")).collect();
+        format!(
+            "hvc1.{profile_space}{}.{:X}.{tier}{}.{}",
+            self.general_profile_idc,
+            self.general_profile_compatibility_flags.reverse_bits(),
+            self.general_level_idc,
+            constraints.join(".")
+        )
+    }
+
+    /// The parameter sets as Annex B, to put in front of a key chunk.
+    pub fn to_annexb(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for unit in self
+            .video_parameter_sets
+            .iter()
+            .chain(&self.sequence_parameter_sets)
+            .chain(&self.picture_parameter_sets)
+        {
+            out.extend_from_slice(&[0, 0, 0, 1]);
+            out.extend_from_slice(unit);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VPS: [u8; 6] = [0x40, 0x01, 0x0c, 0x01, 0xff, 0xff];
+    /// A Main profile, level 3.1 SPS for 64x64 4:2:0 at 8 bits, with emulation prevention
+    /// bytes in the constraint flags.
+    const SPS: [u8; 23] = [
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x5d, 0xa0, 0x20, 0x81, 0x05, 0xc0,
+    ];
+    const PPS: [u8; 7] = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+
+    fn record() -> HevcDecoderConfigurationRecord {
+        HevcDecoderConfigurationRecord::from_parameter_sets(
+            vec![VPS.to_vec()],
+            vec![SPS.to_vec()],
+            vec![PPS.to_vec()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_the_record_from_annexb_parameter_sets() {
+        let mut annexb = Vec::new();
+        for unit in [&VPS[..], &SPS, &PPS] {
+            annexb.extend_from_slice(&[0, 0, 0, 1]);
+            annexb.extend_from_slice(unit);
+        }
+
+        let record = HevcDecoderConfigurationRecord::from_annexb(&annexb).unwrap();
+        assert_eq!(record, self::record());
+        assert_eq!(record.codec_string(), "hvc1.1.6.L93.90");
+        assert_eq!(
+            (
+                record.chroma_format,
+                record.bit_depth_luma,
+                record.bit_depth_chroma
+            ),
+            (1, 8, 8)
+        );
+        assert_eq!(record.to_annexb(), annexb);
+        assert_eq!(
+            record.to_bytes()[..23],
+            [
+                0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5d, 0xf0,
+                0x00, 0xfc, 0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0f, 0x03,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let record = record();
+        assert_eq!(
+            HevcDecoderConfigurationRecord::parse(&record.to_bytes()).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_records() {
+        let hvcc = record().to_bytes();
+        for length in 0..hvcc.len() {
+            let error = HevcDecoderConfigurationRecord::parse(&hvcc[..length]).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{length} bytes");
+        }
+        for length in 0..SPS.len() - 2 {
+            let sps = SPS[..length].to_vec();
+            assert!(
+                HevcDecoderConfigurationRecord::from_parameter_sets(vec![], vec![sps], vec![])
+                    .is_err(),
+                "{length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn writes_out_of_range_bit_depths_without_panicking() {
+        let mut record = record();
+        record.bit_depth_luma = 0;
+        record.bit_depth_chroma = 0;
+        assert_eq!(record.to_bytes()[17..19], [0xf8, 0xf8]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Helpers for codec bitstreams and the codec-specific `description` bytes of decoder
+//! configs.
+//!
+//! H.264 and HEVC come in two formats: Annex B, where NAL units are separated by start
+//! codes and parameter sets travel in-band, and the ISO BMFF ("avc"/"hevc") format, where
+//! each NAL unit is prefixed by its length and the parameter sets are in an avcC/hvcC
+//! record passed as `description`.
+//!
+//! The audio descriptions are the AudioSpecificConfig of AAC, the OpusHead of Opus, the
+//! "fLaC" magic and STREAMINFO block of FLAC and the Xiph-laced headers of Vorbis.
+
+mod aac;
+mod av1;
+mod avc;
+mod hevc;
+mod opus;
+mod xiph;
+
+pub use aac::*;
+pub use av1::*;
+pub use avc::*;
+pub use hevc::*;
+pub use opus::*;
+pub use xiph::*;
+
+use crate::codec::{Exception, ExceptionKind};
+
+/// The bitstream format of H.264 and HEVC chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitstreamFormat {
+    /// Length-prefixed NAL units, with the parameter sets in the `description`.
+    #[default]
+    LengthPrefixed,
+    /// NAL units separated by start codes, with the parameter sets in-band.
+    AnnexB,
+}
+
+/// Splits Annex B data into NAL units, without their start codes.
+pub fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                units.push(trim_trailing_zeros(&data[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        units.push(&data[start..]);
+    }
+    units.retain(|unit| !unit.is_empty());
+    units
+}
+
+/// Drops the zero bytes before the next start code, which belong to it.
+fn trim_trailing_zeros(unit: &[u8]) -> &[u8] {
+    let end = unit.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &unit[..end]
+}
+
+/// Splits length-prefixed data into NAL units.
+pub fn split_length_prefixed(data: &[u8], length_size: usize) -> Result<Vec<&[u8]>, Exception> {
+    if !(1..=4).contains(&length_size) {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            format!("invalid NAL unit length size {length_size}"),
+        ));
+    }
+    let mut units = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < length_size {
+            return Err(truncated());
+        }
+        let length = rest[..length_size]
+            .iter()
+            .fold(0usize, |length, &b| (length << 8) | b as usize);
+        rest = &rest[length_size..];
+        if rest.len() < length {
+            return Err(truncated());
+        }
+        units.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+    Ok(units)
+}
+
+/// Converts Annex B data to NAL units prefixed by their 4-byte length.
+pub fn annexb_to_length_prefixed(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    for unit in split_annexb(data) {
+        out.extend_from_slice(&(unit.len() as u32).to_be_bytes());
+        out.extend_from_slice(unit);
+    }
+    out
+}
+
+/// Converts length-prefixed NAL units to Annex B with 4-byte start codes.
+pub fn length_prefixed_to_annexb(data: &[u8], length_size: usize) -> Result<Vec<u8>, Exception> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    for unit in split_length_prefixed(data, length_size)? {
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(unit);
+    }
+    Ok(out)
+}
+
+/// Removes the emulation prevention bytes of a NAL unit, yielding its RBSP.
+fn unescape_rbsp(unit: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(unit.len());
+    let mut zeros = 0;
+    for &b in unit {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+fn truncated() -> Exception {
+    Exception::new(ExceptionKind::DataError, "truncated bitstream")
+}
+
+/// Reads bits MSB first, with the Exp-Golomb codes of H.264 and HEVC headers.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, Exception> {
+        let byte = self.data.get(self.position / 8).ok_or_else(truncated)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, Exception> {
+        (0..count).try_fold(0, |value, _| Ok((value << 1) | self.bit()?))
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    fn bits_left(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.position)
+    }
+
+    /// An unsigned Exp-Golomb code.
+    fn ue(&mut self) -> Result<u32, Exception> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(Exception::new(
+                    ExceptionKind::DataError,
+                    "invalid Exp-Golomb code",
+                ));
+            }
+        }
+        Ok((1 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+}
+
+/// Writes bits MSB first.
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl BitWriter {
+    /// Writes the low `count` bits of `value`.
+    fn bits(&mut self, count: u32, value: u32) {
+        for i in (0..count).rev() {
+            if self.position.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            if let Some(last) = self.data.last_mut() {
+                *last |= bit << (7 - self.position % 8);
+            }
+            self.position += 1;
+        }
+    }
+
+    /// The bytes written, padded with zero bits.
+    fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_annexb_at_both_start_code_lengths() {
+        let data = [
+            0xff, 0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 0, 1, 0x65, 0x88, 0, 0, 1,
+        ];
+        // Bytes before the first start code and empty units are dropped, as are the zeros
+        // of a 4-byte start code.
+        assert_eq!(
+            split_annexb(&data),
+            [&[0x67, 0x42][..], &[0x68, 0xce], &[0x65, 0x88]]
+        );
+        assert!(split_annexb(&[0, 0, 0]).is_empty());
+    }
+
+    #[test]
+    fn converts_between_annexb_and_length_prefixed() {
+        let annexb = [0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68];
+        let length_prefixed = [0, 0, 0, 2, 0x67, 0x42, 0, 0, 0, 1, 0x68];
+        assert_eq!(annexb_to_length_prefixed(&annexb), length_prefixed);
+        assert_eq!(
+            length_prefixed_to_annexb(&length_prefixed, 4).unwrap(),
+            [0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68]
+        );
+        assert_eq!(
+            split_length_prefixed(&[2, 0x67, 0x42, 1, 0x68], 1).unwrap(),
+            [&[0x67, 0x42][..], &[0x68]]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_length_prefixed_units() {
+        let length_prefixed = [0, 0, 0, 2, 0x67, 0x42];
+        for length in 1..length_prefixed.len() {
+            let error = split_length_prefixed(&length_prefixed[..length], 4).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{length} bytes");
+        }
+        assert!(split_length_prefixed(&length_prefixed, 5).is_err());
+    }
+
+    #[test]
+    fn removes_emulation_prevention_bytes() {
+        assert_eq!(
+            unescape_rbsp(&[0x67, 0, 0, 3, 1, 0, 0, 3, 0, 3]),
+            [0x67, 0, 0, 1, 0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn reads_exp_golomb_codes() {
+        // 1, 010, 011, 00100, then a truncated 0001.
+        let mut reader = BitReader::new(&[0b1010_0110, 0b0100_0001]);
+        let codes: Vec<_> = (0..4).map(|_| reader.ue().unwrap()).collect();
+        assert_eq!(codes, [0, 1, 2, 3]);
+        assert_eq!(reader.ue().unwrap_err().kind(), ExceptionKind::DataError);
+    }
+}
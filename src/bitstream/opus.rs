@@ -0,0 +1,210 @@
+use crate::codec::{Exception, ExceptionKind};
+
+use super::truncated;
+
+/// The streams and coupled streams of mapping family 1, by channel count.
+const VORBIS_STREAMS: [(u8, u8); 8] = [
+    (1, 0),
+    (1, 1),
+    (2, 1),
+    (2, 2),
+    (3, 2),
+    (4, 2),
+    (4, 3),
+    (5, 3),
+];
+
+/// The channel mapping of family 1, from Vorbis channel order to coded channels.
+const VORBIS_MAPPINGS: [&[u8]; 8] = [
+    &[0],
+    &[0, 1],
+    &[0, 2, 1],
+    &[0, 1, 2, 3],
+    &[0, 4, 1, 2, 3],
+    &[0, 4, 1, 2, 3, 5],
+    &[0, 4, 1, 2, 3, 5, 6],
+    &[0, 6, 1, 2, 3, 4, 5, 7],
+];
+
+/// The identification header of RFC 7845, the `description` of Opus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusHead {
+    pub channel_count: u8,
+    /// Samples at 48 kHz to discard from the start of the decoded stream.
+    pub pre_skip: u16,
+    /// The sample rate of the original input, for information only.
+    pub input_sample_rate: u32,
+    /// Gain to apply to the decoded output, in Q7.8 dB.
+    pub output_gain: i16,
+    /// The channel mapping; `None` for family 0, mono or stereo in one stream.
+    pub channel_mapping: Option<ChannelMapping>,
+}
+
+/// The channel mapping table of an `OpusHead` for mapping families other than 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMapping {
+    /// 1 for the Vorbis channel order of up to 8 channels, 255 for unordered channels.
+    pub family: u8,
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    /// The coded channel of each output channel.
+    pub mapping: Vec<u8>,
+}
+
+impl OpusHead {
+    /// A header for `channel_count` channels, with mapping family 0 for mono and stereo,
+    /// 1 for up to 8 channels and 255 beyond.
+    pub fn new(channel_count: u8, input_sample_rate: u32) -> Result<Self, Exception> {
+        let channel_mapping = match channel_count {
+            0 => {
+                return Err(Exception::new(
+                    ExceptionKind::NotSupportedError,
+                    "Opus needs at least one channel",
+                ))
+            }
+            1 | 2 => None,
+            3..=8 => {
+                let index = channel_count as usize - 1;
+                let (stream_count, coupled_count) = VORBIS_STREAMS[index];
+                Some(ChannelMapping {
+                    family: 1,
+                    stream_count,
+                    coupled_count,
+                    mapping: VORBIS_MAPPINGS[index].to_vec(),
+                })
+            }
+            _ => Some(ChannelMapping {
+                family: 255,
+                stream_count: channel_count,
+                coupled_count: 0,
+                mapping: (0..channel_count).collect(),
+            }),
+        };
+        Ok(Self {
+            channel_count,
+            // The encoder delay of libopus at its default settings.
+            pre_skip: 312,
+            input_sample_rate,
+            output_gain: 0,
+            channel_mapping,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, Exception> {
+        if data.len() < 19 || !data.starts_with(b"OpusHead") {
+            return Err(Exception::new(ExceptionKind::DataError, "not an OpusHead"));
+        }
+        if data[8] >> 4 != 0 {
+            return Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("unsupported OpusHead version {}", data[8]),
+            ));
+        }
+        let channel_count = data[9];
+        let family = data[18];
+        let channel_mapping = if family == 0 {
+            if !(1..=2).contains(&channel_count) {
+                return Err(Exception::new(
+                    ExceptionKind::DataError,
+                    format!("mapping family 0 cannot carry {channel_count} channels"),
+                ));
+            }
+            None
+        } else {
+            let table = data
+                .get(19..21 + channel_count as usize)
+                .ok_or_else(truncated)?;
+            let (stream_count, coupled_count) = (table[0], table[1]);
+            if stream_count == 0 || coupled_count > stream_count {
+                return Err(Exception::new(
+                    ExceptionKind::DataError,
+                    "invalid Opus stream counts",
+                ));
+            }
+            Some(ChannelMapping {
+                family,
+                stream_count,
+                coupled_count,
+                mapping: table[2..].to_vec(),
+            })
+        };
+        Ok(Self {
+            channel_count,
+            pre_skip: u16::from_le_bytes([data[10], data[11]]),
+            input_sample_rate: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            output_gain: i16::from_le_bytes([data[16], data[17]]),
+            channel_mapping,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = b"OpusHead".to_vec();
+        out.push(1);
+        out.push(self.channel_count);
+        out.extend_from_slice(&self.pre_skip.to_le_bytes());
+        out.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        out.extend_from_slice(&self.output_gain.to_le_bytes());
+        match &self.channel_mapping {
+            None => out.push(0),
+            Some(mapping) => {
+                out.push(mapping.family);
+                out.push(mapping.stream_count);
+                out.push(mapping.coupled_count);
+                out.extend_from_slice(&mapping.mapping);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo, 312 samples of pre-skip, 48 kHz input, no gain, mapping family 0.
+    const STEREO: [u8; 19] = [
+        b'O', b'p', b'u', b's', b'H', b'e', b'a', b'd', 1, 2, 0x38, 0x01, 0x80, 0xbb, 0x00, 0x00,
+        0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn round_trips_family_0() {
+        let head = OpusHead::parse(&STEREO).unwrap();
+        assert_eq!(head, OpusHead::new(2, 48_000).unwrap());
+        assert_eq!(head.to_bytes(), STEREO);
+    }
+
+    #[test]
+    fn round_trips_family_1() {
+        let head = OpusHead::new(6, 44_100).unwrap();
+        let bytes = head.to_bytes();
+        assert_eq!(bytes[18..], [1, 4, 2, 0, 4, 1, 2, 3, 5]);
+        assert_eq!(OpusHead::parse(&bytes).unwrap(), head);
+    }
+
+    #[test]
+    fn rejects_truncated_and_invalid_headers() {
+        for length in 0..STEREO.len() {
+            let error = OpusHead::parse(&STEREO[..length]).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{length} bytes");
+        }
+        let surround = OpusHead::new(6, 48_000).unwrap().to_bytes();
+        for length in 19..surround.len() {
+            assert!(
+                OpusHead::parse(&surround[..length]).is_err(),
+                "{length} bytes"
+            );
+        }
+
+        let mut three_channels = STEREO;
+        three_channels[9] = 3;
+        assert!(OpusHead::parse(&three_channels).is_err());
+        let mut version_2 = STEREO;
+        version_2[8] = 0x20;
+        let error = OpusHead::parse(&version_2).unwrap_err();
+        assert_eq!(error.kind(), ExceptionKind::NotSupportedError);
+        let mut no_streams = surround.clone();
+        no_streams[19] = 0;
+        assert!(OpusHead::parse(&no_streams).is_err());
+    }
+}
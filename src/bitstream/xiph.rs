@@ -0,0 +1,122 @@
+use crate::codec::{Exception, ExceptionKind};
+
+use super::truncated;
+
+/// The magic that starts a FLAC stream and its `description`.
+pub const FLAC_MAGIC: &[u8; 4] = b"fLaC";
+
+/// The size of a FLAC STREAMINFO block, without its header.
+pub const FLAC_STREAM_INFO_SIZE: usize = 34;
+
+/// The STREAMINFO block of a FLAC `description`, which is "fLaC" followed by metadata
+/// blocks starting with STREAMINFO. A bare STREAMINFO block is accepted as well.
+pub fn flac_stream_info(description: &[u8]) -> Result<&[u8], Exception> {
+    let Some(blocks) = description.strip_prefix(FLAC_MAGIC) else {
+        if description.len() == FLAC_STREAM_INFO_SIZE {
+            return Ok(description);
+        }
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            "FLAC description does not start with \"fLaC\"",
+        ));
+    };
+    let header = blocks.get(..4).ok_or_else(truncated)?;
+    let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+    if header[0] & 0x7F != 0 || size < FLAC_STREAM_INFO_SIZE {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            "FLAC description does not start with a STREAMINFO block",
+        ));
+    }
+    blocks
+        .get(4..4 + FLAC_STREAM_INFO_SIZE)
+        .ok_or_else(truncated)
+}
+
+/// Makes a FLAC `description` out of a STREAMINFO block.
+pub fn flac_description(stream_info: &[u8]) -> Vec<u8> {
+    let mut description = FLAC_MAGIC.to_vec();
+    // The only metadata block, of type STREAMINFO (0).
+    description.push(0x80);
+    description.extend_from_slice(&(stream_info.len() as u32).to_be_bytes()[1..]);
+    description.extend_from_slice(stream_info);
+    description
+}
+
+/// Splits Xiph-laced data, such as the Vorbis `description` holding the identification,
+/// comment and setup headers, into its packets.
+///
+/// The first byte is the number of packets minus one, followed by the sizes of every
+/// packet but the last, each as a run of 255s ended by a smaller byte that are summed.
+pub fn split_xiph_lacing(data: &[u8]) -> Result<Vec<&[u8]>, Exception> {
+    let (&count, mut rest) = data.split_first().ok_or_else(truncated)?;
+    let mut sizes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut size = 0usize;
+        loop {
+            let (&byte, remaining) = rest.split_first().ok_or_else(truncated)?;
+            rest = remaining;
+            size += byte as usize;
+            if byte < 255 {
+                break;
+            }
+        }
+        sizes.push(size);
+    }
+
+    let mut packets = Vec::with_capacity(sizes.len() + 1);
+    for size in sizes {
+        if rest.len() < size {
+            return Err(truncated());
+        }
+        let (packet, remaining) = rest.split_at(size);
+        packets.push(packet);
+        rest = remaining;
+    }
+    packets.push(rest);
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_xiph_lacing() {
+        // Three packets of 30, 265 and 4 bytes.
+        let mut data = vec![2, 30, 255, 10];
+        data.extend(std::iter::repeat_n(1, 30));
+        data.extend(std::iter::repeat_n(2, 265));
+        data.extend(std::iter::repeat_n(3, 4));
+
+        let packets = split_xiph_lacing(&data).unwrap();
+        let sizes: Vec<_> = packets.iter().map(|packet| packet.len()).collect();
+        assert_eq!(sizes, [30, 265, 4]);
+        assert!(packets[1].iter().all(|&b| b == 2));
+
+        // Anything short of the laced sizes is truncated; the last packet may be empty.
+        for length in 0..4 + 30 + 265 {
+            let error = split_xiph_lacing(&data[..length]).unwrap_err();
+            assert_eq!(error.kind(), ExceptionKind::DataError, "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn finds_the_flac_stream_info() {
+        let stream_info: Vec<u8> = (0..FLAC_STREAM_INFO_SIZE as u8).collect();
+        let description = flac_description(&stream_info);
+        assert_eq!(description[..8], [b'f', b'L', b'a', b'C', 0x80, 0, 0, 34]);
+        assert_eq!(flac_stream_info(&description).unwrap(), stream_info);
+        assert_eq!(flac_stream_info(&stream_info).unwrap(), stream_info);
+
+        for length in 0..description.len() {
+            assert!(
+                flac_stream_info(&description[..length]).is_err(),
+                "{length} bytes"
+            );
+        }
+        let mut padding_first = description.clone();
+        padding_first[4] = 0x01;
+        assert!(flac_stream_info(&padding_first).is_err());
+    }
+}
@@ -0,0 +1,551 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::{
+    core::{buffer_pool::BufferPool, promise::Promise},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+};
+
+use super::{
+    AudioDecoder, AudioDecoderConfig, AudioEncoder, AudioEncoderConfig, EncodedVideoChunk,
+    EncodedVideoChunkMetadata, Exception, State, VideoDecoder, VideoDecoderConfig, VideoEncoder,
+    VideoEncoderConfig, VideoEncoderEncodeOptions, VideoFrame,
+};
+
+struct OutputState<T> {
+    items: VecDeque<Result<T, Exception>>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Collects what the output and error callbacks of a codec produce, in order, for a
+/// `Stream` to hand out.
+struct OutputQueue<T> {
+    state: Arc<Mutex<OutputState<T>>>,
+}
+
+impl<T> Clone for OutputQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> OutputQueue<T> {
+    fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(OutputState {
+                items: VecDeque::new(),
+                waker: None,
+                closed: false,
+            })),
+        }
+    }
+
+    fn push(&self, item: Result<T, Exception>) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.closed {
+                return;
+            }
+            state.items.push_back(item);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Queues an error from the error callback.
+    ///
+    /// Only the codec's jobs report errors there, and those errors close the codec with no
+    /// callback after them, so they end the stream. Checking the codec state for that
+    /// instead would race with the callback.
+    fn push_error(&self, err: Exception) {
+        self.push(Err(err));
+        self.close();
+    }
+
+    /// Ends the stream once the already queued items have been taken.
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<Result<T, Exception>>> {
+        let Ok(mut state) = self.state.lock() else {
+            return Poll::Ready(None);
+        };
+        if let Some(item) = state.items.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// What the async wrappers need of the codec they drive.
+trait Codec {
+    fn state(&self) -> State;
+    fn flush(&mut self) -> Result<Promise<()>, Exception>;
+    fn close(&mut self);
+}
+
+macro_rules! impl_codec {
+    ($($codec:ty),*) => {$(
+        impl Codec for $codec {
+            fn state(&self) -> State {
+                <$codec>::state(self)
+            }
+
+            fn flush(&mut self) -> Result<Promise<()>, Exception> {
+                <$codec>::flush(self)
+            }
+
+            fn close(&mut self) {
+                <$codec>::close(self)
+            }
+        }
+    )*};
+}
+
+impl_codec!(AudioDecoder, AudioEncoder, VideoDecoder, VideoEncoder);
+
+/// Closes `codec` and ends the stream of `outputs`.
+fn close<T>(codec: &mut impl Codec, outputs: &OutputQueue<T>) {
+    if codec.state() != State::Closed {
+        codec.close();
+    }
+    outputs.close();
+}
+
+/// `Sink::poll_close` of the wrappers: flushes a configured codec, then closes it and ends
+/// the stream after the last output.
+fn poll_close<T>(
+    codec: &mut impl Codec,
+    outputs: &OutputQueue<T>,
+    closing: &mut Option<Promise<()>>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), Exception>> {
+    if codec.state() != State::Configured {
+        close(codec, outputs);
+        return Poll::Ready(Ok(()));
+    }
+    let flush = match closing {
+        Some(flush) => flush,
+        None => closing.insert(codec.flush()?),
+    };
+    match Pin::new(flush).poll(cx) {
+        Poll::Pending => Poll::Pending,
+        Poll::Ready(result) => {
+            *closing = None;
+            close(codec, outputs);
+            Poll::Ready(result)
+        }
+    }
+}
+
+/// An `AudioDecoder` driven through `Sink<EncodedAudioChunk>` and
+/// `Stream<Item = Result<AudioData, Exception>>`.
+///
+/// Errors reported to the decoder's error callback show up in the stream, ordered with the
+/// decoded frames. Closing the sink flushes the decoder and ends the stream after the last
+/// frame. Works on any executor.
+pub struct AsyncAudioDecoder {
+    decoder: AudioDecoder,
+    outputs: OutputQueue<AudioData>,
+    closing: Option<Promise<()>>,
+}
+
+impl Default for AsyncAudioDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncAudioDecoder {
+    pub fn new() -> Self {
+        let outputs = OutputQueue::new();
+        let on_output = outputs.clone();
+        let on_error = outputs.clone();
+        let decoder = AudioDecoder::new(
+            move |data| on_output.push(Ok(data)),
+            move |err| on_error.push_error(err),
+        );
+        Self {
+            decoder,
+            outputs,
+            closing: None,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.decoder.state()
+    }
+
+    /// See `AudioDecoder::set_buffer_pool`. A pool that blocks when exhausted blocks a
+    /// worker thread of the decoder, not the executor.
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.decoder.set_buffer_pool(pool);
+    }
+
+    pub fn configure(&mut self, config: AudioDecoderConfig) -> Result<(), Exception> {
+        self.decoder.configure(config)
+    }
+
+    pub fn decode(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        self.decoder.decode(chunk)
+    }
+
+    /// Resolves once every pending chunk has been decoded and its frames are in the stream.
+    pub async fn flush(&mut self) -> Result<(), Exception> {
+        self.decoder.flush()?.await
+    }
+
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        self.decoder.reset()
+    }
+
+    /// Closes the decoder and ends the stream.
+    pub fn close(&mut self) {
+        close(&mut self.decoder, &self.outputs);
+    }
+}
+
+impl Sink<EncodedAudioChunk> for AsyncAudioDecoder {
+    type Error = Exception;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: EncodedAudioChunk) -> Result<(), Exception> {
+        let this = self.get_mut();
+        this.decoder.decode(item)
+    }
+
+    /// Chunks are handed to the decoder in `start_send`, so there is nothing to do here.
+    ///
+    /// This does not flush the decoder, as that would require the next chunk to be a key
+    /// chunk; use `flush` or close the sink for that.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        let this = self.get_mut();
+        poll_close(&mut this.decoder, &this.outputs, &mut this.closing, cx)
+    }
+}
+
+impl Stream for AsyncAudioDecoder {
+    type Item = Result<AudioData, Exception>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.outputs.poll_next(cx)
+    }
+}
+
+/// An `AudioEncoder` driven through `Sink<AudioData>` and
+/// `Stream<Item = Result<(EncodedAudioChunk, EncodedAudioChunkMetadata), Exception>>`.
+///
+/// Behaves like `AsyncAudioDecoder`: closing the sink flushes the encoder and ends the stream
+/// after the last chunk.
+pub struct AsyncAudioEncoder {
+    encoder: AudioEncoder,
+    outputs: OutputQueue<(EncodedAudioChunk, EncodedAudioChunkMetadata)>,
+    closing: Option<Promise<()>>,
+}
+
+impl Default for AsyncAudioEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncAudioEncoder {
+    pub fn new() -> Self {
+        let outputs = OutputQueue::new();
+        let on_output = outputs.clone();
+        let on_error = outputs.clone();
+        let encoder = AudioEncoder::new(
+            move |chunk, metadata| on_output.push(Ok((chunk, metadata))),
+            move |err| on_error.push_error(err),
+        );
+        Self {
+            encoder,
+            outputs,
+            closing: None,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.encoder.state()
+    }
+
+    pub fn configure(&mut self, config: AudioEncoderConfig) -> Result<(), Exception> {
+        self.encoder.configure(config)
+    }
+
+    pub fn encode(&mut self, data: AudioData) -> Result<(), Exception> {
+        self.encoder.encode(data)
+    }
+
+    /// Resolves once every pending frame has been encoded and its chunks are in the stream.
+    pub async fn flush(&mut self) -> Result<(), Exception> {
+        self.encoder.flush()?.await
+    }
+
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        self.encoder.reset()
+    }
+
+    /// Closes the encoder and ends the stream.
+    pub fn close(&mut self) {
+        close(&mut self.encoder, &self.outputs);
+    }
+}
+
+impl Sink<AudioData> for AsyncAudioEncoder {
+    type Error = Exception;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: AudioData) -> Result<(), Exception> {
+        let this = self.get_mut();
+        this.encoder.encode(item)
+    }
+
+    /// Data is handed to the encoder in `start_send`, so there is nothing to do here; use
+    /// `flush` or close the sink to drain the encoder.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        let this = self.get_mut();
+        poll_close(&mut this.encoder, &this.outputs, &mut this.closing, cx)
+    }
+}
+
+impl Stream for AsyncAudioEncoder {
+    type Item = Result<(EncodedAudioChunk, EncodedAudioChunkMetadata), Exception>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.outputs.poll_next(cx)
+    }
+}
+
+/// A `VideoDecoder` driven through `Sink<EncodedVideoChunk>` and
+/// `Stream<Item = Result<VideoFrame, Exception>>`.
+///
+/// Behaves like `AsyncAudioDecoder`: closing the sink flushes the decoder and ends the stream
+/// after the last frame.
+pub struct AsyncVideoDecoder {
+    decoder: VideoDecoder,
+    outputs: OutputQueue<VideoFrame>,
+    closing: Option<Promise<()>>,
+}
+
+impl Default for AsyncVideoDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncVideoDecoder {
+    pub fn new() -> Self {
+        let outputs = OutputQueue::new();
+        let on_output = outputs.clone();
+        let on_error = outputs.clone();
+        let decoder = VideoDecoder::new(
+            move |frame| on_output.push(Ok(frame)),
+            move |err| on_error.push_error(err),
+        );
+        Self {
+            decoder,
+            outputs,
+            closing: None,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.decoder.state()
+    }
+
+    /// See `VideoDecoder::set_buffer_pool`. A pool that blocks when exhausted blocks a
+    /// worker thread of the decoder, not the executor.
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.decoder.set_buffer_pool(pool);
+    }
+
+    pub fn configure(&mut self, config: VideoDecoderConfig) -> Result<(), Exception> {
+        self.decoder.configure(config)
+    }
+
+    pub fn decode(&mut self, chunk: EncodedVideoChunk) -> Result<(), Exception> {
+        self.decoder.decode(chunk)
+    }
+
+    /// Resolves once every pending chunk has been decoded and its frames are in the stream.
+    pub async fn flush(&mut self) -> Result<(), Exception> {
+        self.decoder.flush()?.await
+    }
+
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        self.decoder.reset()
+    }
+
+    /// Closes the decoder and ends the stream.
+    pub fn close(&mut self) {
+        close(&mut self.decoder, &self.outputs);
+    }
+}
+
+impl Sink<EncodedVideoChunk> for AsyncVideoDecoder {
+    type Error = Exception;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: EncodedVideoChunk) -> Result<(), Exception> {
+        let this = self.get_mut();
+        this.decoder.decode(item)
+    }
+
+    /// Chunks are handed to the decoder in `start_send`, so there is nothing to do here.
+    ///
+    /// This does not flush the decoder, as that would require the next chunk to be a key
+    /// chunk; use `flush` or close the sink for that.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        let this = self.get_mut();
+        poll_close(&mut this.decoder, &this.outputs, &mut this.closing, cx)
+    }
+}
+
+impl Stream for AsyncVideoDecoder {
+    type Item = Result<VideoFrame, Exception>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.outputs.poll_next(cx)
+    }
+}
+
+/// A `VideoEncoder` driven through `Sink<VideoFrame>` and
+/// `Stream<Item = Result<(EncodedVideoChunk, EncodedVideoChunkMetadata), Exception>>`.
+///
+/// Frames sent to the sink are encoded with the default options; use `encode` to force key
+/// frames or pass a quantizer. Closing the sink flushes the encoder and ends the stream after
+/// the last chunk.
+pub struct AsyncVideoEncoder {
+    encoder: VideoEncoder,
+    outputs: OutputQueue<(EncodedVideoChunk, EncodedVideoChunkMetadata)>,
+    closing: Option<Promise<()>>,
+}
+
+impl Default for AsyncVideoEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncVideoEncoder {
+    pub fn new() -> Self {
+        let outputs = OutputQueue::new();
+        let on_output = outputs.clone();
+        let on_error = outputs.clone();
+        let encoder = VideoEncoder::new(
+            move |chunk, metadata| on_output.push(Ok((chunk, metadata))),
+            move |err| on_error.push_error(err),
+        );
+        Self {
+            encoder,
+            outputs,
+            closing: None,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.encoder.state()
+    }
+
+    pub fn configure(&mut self, config: VideoEncoderConfig) -> Result<(), Exception> {
+        self.encoder.configure(config)
+    }
+
+    pub fn encode(
+        &mut self,
+        frame: VideoFrame,
+        options: VideoEncoderEncodeOptions,
+    ) -> Result<(), Exception> {
+        self.encoder.encode(frame, options)
+    }
+
+    /// Resolves once every pending frame has been encoded and its chunks are in the stream.
+    pub async fn flush(&mut self) -> Result<(), Exception> {
+        self.encoder.flush()?.await
+    }
+
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        self.encoder.reset()
+    }
+
+    /// Closes the encoder and ends the stream.
+    pub fn close(&mut self) {
+        close(&mut self.encoder, &self.outputs);
+    }
+}
+
+impl Sink<VideoFrame> for AsyncVideoEncoder {
+    type Error = Exception;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: VideoFrame) -> Result<(), Exception> {
+        let this = self.get_mut();
+        this.encoder
+            .encode(item, VideoEncoderEncodeOptions::default())
+    }
+
+    /// Frames are handed to the encoder in `start_send`, so there is nothing to do here; use
+    /// `flush` or close the sink to drain the encoder.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Exception>> {
+        let this = self.get_mut();
+        poll_close(&mut this.encoder, &this.outputs, &mut this.closing, cx)
+    }
+}
+
+impl Stream for AsyncVideoEncoder {
+    type Item = Result<(EncodedVideoChunk, EncodedVideoChunkMetadata), Exception>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.outputs.poll_next(cx)
+    }
+}
@@ -1,30 +1,30 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc::Receiver, Arc};
 
 use crate::{
     core::{
-        control::{
-            AudioDecodeMessage, AudioEncodeMessage, AudioFlushMessage, ControlMessage,
-            DecodeMessage, FlushMessage,
-        },
-        internal_slots::CodecInternalSlots,
-        work_queue::MAX_WORKERS,
+        backend::{self, AudioDecoderBackend, AudioEncoderBackend},
+        buffer_pool::BufferPool,
+        control::{CodecHandle, ControlMessageKind},
+        promise::Promise,
+        queue_size::QueueSize,
     },
-    data::audio_data::{AudioData, EncodedAudioChunk},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
 };
 
-use super::{AudioConfigMessage, AudioDecoderConfig, ConfigMessage, Exception, State};
+use super::{
+    channel::{self, CodecEvent},
+    AudioDecoderConfig, AudioEncoderConfig, Exception, ExceptionKind, State,
+};
 
 /// Decodes `EncodedAudioChunk` objects.
 ///
 /// https://developer.mozilla.org/en-US/docs/Web/API/AudioDecoder
 pub struct AudioDecoder {
-    internal_slots: CodecInternalSlots,
-    decode_queue_size: u32,
-    state: State,
-    codec_impl: Arc<Mutex<Option<ffmpeg_next::decoder::Audio>>>,
-    output_callback: Arc<dyn Fn(AudioData) + Send + Sync>,
-    error_callback: Arc<dyn Fn(Exception) + Send + Sync>,
+    codec: CodecHandle<AudioDecoderBackend>,
+    decode_queue_size: Arc<QueueSize>,
+    max_decode_queue_size: Option<u32>,
     key_chunk_required: bool,
+    buffer_pool: Option<BufferPool>,
 }
 
 impl AudioDecoder {
@@ -32,154 +32,339 @@ impl AudioDecoder {
         output_callback: impl Fn(AudioData) + Send + Sync + 'static,
         error_callback: impl Fn(Exception) + Send + Sync + 'static,
     ) -> Self {
-        let internal_slots = CodecInternalSlots::new(MAX_WORKERS);
         Self {
-            internal_slots,
-            decode_queue_size: 0,
-            state: State::Unconfigured,
-            codec_impl: Arc::new(Mutex::new(None)),
-            output_callback: Arc::new(output_callback),
-            error_callback: Arc::new(error_callback),
+            codec: CodecHandle::new(output_callback, error_callback, ExceptionKind::DecodeError),
+            decode_queue_size: Arc::new(QueueSize::new()),
+            max_decode_queue_size: None,
             key_chunk_required: true,
+            buffer_pool: None,
         }
     }
 
+    /// Creates a decoder that sends its outputs, completed flushes and errors, in order, to
+    /// the returned receiver instead of invoking callbacks.
+    pub fn with_channel() -> (Self, Receiver<CodecEvent<AudioData>>) {
+        Self::with_event_channel(None)
+    }
+
+    /// Like `with_channel`, but holds at most `capacity` undelivered events.
+    ///
+    /// When the receiver falls behind, decoding stalls and `decode` blocks until fewer than
+    /// `capacity` chunks are pending, so the receiver must be drained from another thread.
+    pub fn with_bounded_channel(capacity: usize) -> (Self, Receiver<CodecEvent<AudioData>>) {
+        Self::with_event_channel(Some(capacity))
+    }
+
+    fn with_event_channel(capacity: Option<usize>) -> (Self, Receiver<CodecEvent<AudioData>>) {
+        let (sender, receiver) = channel::event_channel(capacity);
+        let (outputs, errors) = (sender.clone(), sender.clone());
+        let mut decoder = Self::new(
+            move |data| outputs.send(CodecEvent::Output(data)),
+            move |err| errors.send(CodecEvent::Error(err)),
+        );
+        decoder
+            .codec
+            .set_flush_callback(move || sender.send(CodecEvent::Flushed));
+        decoder.max_decode_queue_size = capacity.map(|capacity| capacity.max(1) as u32);
+        (decoder, receiver)
+    }
+
+    pub fn state(&self) -> State {
+        self.codec.internal_slots.state()
+    }
+
+    /// The number of pending decode requests.
+    pub fn decode_queue_size(&self) -> u32 {
+        self.decode_queue_size.get()
+    }
+
+    /// Draws the buffers of the decoder's outputs from `pool` from the next `configure` on,
+    /// which bounds the number of outputs alive at a time and reuses their memory.
+    ///
+    /// Backends that do not allocate their outputs, e.g. mocks that pass buffers through,
+    /// ignore the pool.
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.buffer_pool = Some(pool);
+    }
+
     pub fn is_config_supported(&self, config: &AudioDecoderConfig) -> bool {
         config.is_valid()
     }
 
-    // Initialises the underlying decoder with given config.
+    /// Initialises the underlying decoder with given config.
+    ///
+    /// Reconfiguring a configured decoder keeps chunks that were already submitted: they are
+    /// decoded with the previous config, its remaining frames are drained, and only then the
+    /// new decoder takes over. The next chunk must be a key chunk.
     pub fn configure(&mut self, config: AudioDecoderConfig) -> Result<(), Exception> {
         if !self.is_config_supported(&config) {
-            return Err(Exception::TypeError);
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                "invalid audio decoder config",
+            ));
         }
-        if self.state == State::Closed {
-            return Err(Exception::InvalidStateError);
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "cannot configure a closed decoder",
+            ));
         }
 
-        self.state = State::Configured;
+        self.codec.internal_slots.set_state(State::Configured);
         self.key_chunk_required = true;
 
-        let config_message = AudioConfigMessage {
-            config,
-            work_queue: self.internal_slots.work_queue.clone(),
-            error_callback: self.error_callback.clone(),
-            codec_impl: self.codec_impl.clone(),
-        };
-        self.internal_slots
-            .enqueue_control_message(ControlMessage::Config(ConfigMessage::AudioConfig(
-                config_message,
-            )));
-        self.internal_slots.process_control_message_queue();
+        let buffer_pool = self.buffer_pool.clone();
+        let create = move || backend::create_audio_decoder(&config, buffer_pool.as_ref());
+        self.codec
+            .enqueue(ControlMessageKind::Configure(Box::new(create)));
 
         Ok(())
     }
 
     /// Decodes an encoded audio chunk.
     pub fn decode(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
-        if self.state != State::Configured {
-            (self.error_callback)(Exception::InvalidStateError);
-            return Err(Exception::InvalidStateError);
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is not configured",
+            ));
         }
         if self.key_chunk_required && !chunk.is_key {
-            (self.error_callback)(Exception::DecodeError);
-            return Err(Exception::DecodeError);
+            return Err(Exception::new(
+                ExceptionKind::DecodeError,
+                "a key chunk is required",
+            ));
         }
         self.key_chunk_required = false;
-        self.decode_queue_size += 1;
-
-        let decode_message = AudioDecodeMessage {
-            chunk,
-            work_queue: self.internal_slots.work_queue.clone(),
-            output_callback: self.output_callback.clone(),
-            error_callback: self.error_callback.clone(),
-            codec_impl: self.codec_impl.clone(),
-        };
-
-        self.internal_slots
-            .enqueue_control_message(ControlMessage::Decode(DecodeMessage::AudioDecode(
-                decode_message,
-            )));
-        self.internal_slots.process_control_message_queue();
-
-        self.decode_queue_size -= 1;
+        if let Some(max_size) = self.max_decode_queue_size {
+            self.decode_queue_size.wait_below(max_size);
+        }
+        self.decode_queue_size.increment();
+
+        self.codec.enqueue(ControlMessageKind::Send {
+            input: chunk,
+            queue_size: self.decode_queue_size.clone(),
+        });
+
         Ok(())
     }
 
-    // Flush the decoder and drain remaining frames.
-    pub fn flush(&mut self) -> Result<(), Exception> {
-        if self.state != State::Configured {
-            return Err(Exception::InvalidStateError);
+    /// Flush the decoder and drain remaining frames.
+    ///
+    /// The returned promise settles once every frame has been handed to the output callback;
+    /// it can be awaited or waited on. The next chunk must be a key chunk.
+    pub fn flush(&mut self) -> Result<Promise<()>, Exception> {
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is not configured",
+            ));
         }
-        let flush_message = AudioFlushMessage {
-            work_queue: self.internal_slots.work_queue.clone(),
-            error_callback: self.error_callback.clone(),
-            output_callback: self.output_callback.clone(),
-            codec_impl: self.codec_impl.clone(),
-        };
-        self.internal_slots
-            .enqueue_control_message(ControlMessage::Flush(FlushMessage::AudioFlush(
-                flush_message,
-            )));
-        self.internal_slots.process_control_message_queue();
+        self.key_chunk_required = true;
 
-        Ok(())
+        let promise = Promise::new();
+        self.codec
+            .enqueue(ControlMessageKind::Flush(promise.clone()));
+
+        Ok(promise)
     }
 
-    /// Resets the decoder and clears the queue.
-    pub fn reset(&mut self) {
-        if self.state == State::Closed {
-            (self.error_callback)(Exception::InvalidStateError);
-            return;
-        }
-        self.state = State::Unconfigured;
-        self.decode_queue_size = 0;
-        self.internal_slots.control_message_queue.clear();
-        {
-            let mut dec_lock = self.codec_impl.lock().unwrap();
-            *dec_lock = None;
+    /// Resets the decoder, aborting all pending work.
+    ///
+    /// Chunks not decoded yet are dropped without output and pending flushes are rejected
+    /// with `AbortError`.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is closed",
+            ));
         }
+        self.codec.internal_slots.set_state(State::Unconfigured);
+        self.codec.abort(&Exception::new(
+            ExceptionKind::AbortError,
+            "decoder was reset",
+        ));
+        self.decode_queue_size.clear();
+        Ok(())
     }
 
-    /// Closes the decoder; aborts any pending work.
+    /// Closes the decoder; aborts any pending work. No callback fires after this returns.
+    /// Closing a closed decoder does nothing.
     pub fn close(&mut self) {
-        self.reset();
-        self.state = State::Closed;
+        // Fails only for a closed decoder.
+        let _ = self.reset();
+        self.codec.internal_slots.set_state(State::Closed);
     }
 }
 
+/// An encoded audio chunk with the metadata the encoder emitted it with.
+pub type AudioChunk = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
 /// Encodes `AudioData` objects.
 ///
 /// https://developer.mozilla.org/en-US/docs/Web/API/AudioEncoder
 pub struct AudioEncoder {
-    internal_slots: CodecInternalSlots,
+    codec: CodecHandle<AudioEncoderBackend>,
+    encode_queue_size: Arc<QueueSize>,
+    max_encode_queue_size: Option<u32>,
 }
 
 impl AudioEncoder {
-    pub fn new() -> Self {
-        let internal_slots = CodecInternalSlots::new(MAX_WORKERS);
-        Self { internal_slots }
+    pub fn new(
+        output_callback: impl Fn(EncodedAudioChunk, EncodedAudioChunkMetadata) + Send + Sync + 'static,
+        error_callback: impl Fn(Exception) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            codec: CodecHandle::new(
+                move |(chunk, metadata)| output_callback(chunk, metadata),
+                error_callback,
+                ExceptionKind::EncodingError,
+            ),
+            encode_queue_size: Arc::new(QueueSize::new()),
+            max_encode_queue_size: None,
+        }
     }
 
-    pub fn is_config_supported() {}
+    /// Creates an encoder that sends its chunks with their metadata, completed flushes and
+    /// errors, in order, to the returned receiver instead of invoking callbacks.
+    pub fn with_channel() -> (Self, Receiver<CodecEvent<AudioChunk>>) {
+        Self::with_event_channel(None)
+    }
+
+    /// Like `with_channel`, but holds at most `capacity` undelivered events.
+    ///
+    /// When the receiver falls behind, encoding stalls and `encode` blocks until fewer than
+    /// `capacity` inputs are pending, so the receiver must be drained from another thread.
+    pub fn with_bounded_channel(capacity: usize) -> (Self, Receiver<CodecEvent<AudioChunk>>) {
+        Self::with_event_channel(Some(capacity))
+    }
 
-    pub fn configure() {}
+    fn with_event_channel(capacity: Option<usize>) -> (Self, Receiver<CodecEvent<AudioChunk>>) {
+        let (sender, receiver) = channel::event_channel(capacity);
+        let (outputs, errors) = (sender.clone(), sender.clone());
+        let mut encoder = Self::new(
+            move |chunk, metadata| outputs.send(CodecEvent::Output((chunk, metadata))),
+            move |err| errors.send(CodecEvent::Error(err)),
+        );
+        encoder
+            .codec
+            .set_flush_callback(move || sender.send(CodecEvent::Flushed));
+        encoder.max_encode_queue_size = capacity.map(|capacity| capacity.max(1) as u32);
+        (encoder, receiver)
+    }
 
-    pub fn encode(&mut self, data: String) {
-        let message = AudioEncodeMessage {
-            data,
-            work_queue: self.internal_slots.work_queue.clone(),
-        };
-        self.internal_slots
-            .enqueue_control_message(ControlMessage::Encode(
-                crate::core::control::EncodeMessage::AudioEncode(message),
+    pub fn state(&self) -> State {
+        self.codec.internal_slots.state()
+    }
+
+    /// The number of pending encode requests.
+    pub fn encode_queue_size(&self) -> u32 {
+        self.encode_queue_size.get()
+    }
+
+    pub fn is_config_supported(&self, config: &AudioEncoderConfig) -> bool {
+        config.is_valid()
+    }
+
+    /// Initialises the underlying encoder with given config.
+    ///
+    /// Reconfiguring a configured encoder keeps data that was already submitted: it is
+    /// encoded and drained with the previous config before the new encoder takes over. The
+    /// first chunk of the new encoder carries its decoder config in the metadata.
+    pub fn configure(&mut self, config: AudioEncoderConfig) -> Result<(), Exception> {
+        if !self.is_config_supported(&config) {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                "invalid audio encoder config",
+            ));
+        }
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "cannot configure a closed encoder",
+            ));
+        }
+
+        self.codec.internal_slots.set_state(State::Configured);
+
+        let create = move || backend::create_audio_encoder(&config);
+        self.codec
+            .enqueue(ControlMessageKind::Configure(Box::new(create)));
+
+        Ok(())
+    }
+
+    /// Encodes audio data.
+    pub fn encode(&mut self, data: AudioData) -> Result<(), Exception> {
+        if data.is_closed() {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                "AudioData is closed",
             ));
-        self.internal_slots.process_control_message_queue();
+        }
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is not configured",
+            ));
+        }
+        if let Some(max_size) = self.max_encode_queue_size {
+            self.encode_queue_size.wait_below(max_size);
+        }
+        self.encode_queue_size.increment();
+
+        self.codec.enqueue(ControlMessageKind::Send {
+            input: data,
+            queue_size: self.encode_queue_size.clone(),
+        });
+
+        Ok(())
     }
 
-    pub fn flush() {}
+    /// Encodes all pending data and emits the remaining chunks.
+    ///
+    /// The returned promise settles once every chunk has been handed to the output callback.
+    pub fn flush(&mut self) -> Result<Promise<()>, Exception> {
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is not configured",
+            ));
+        }
+
+        let promise = Promise::new();
+        self.codec
+            .enqueue(ControlMessageKind::Flush(promise.clone()));
 
-    pub fn reset() {}
+        Ok(promise)
+    }
 
-    pub fn close() {}
+    /// Resets the encoder, aborting all pending work.
+    ///
+    /// Data not encoded yet is dropped without output and pending flushes are rejected with
+    /// `AbortError`.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is closed",
+            ));
+        }
+        self.codec.internal_slots.set_state(State::Unconfigured);
+        self.codec.abort(&Exception::new(
+            ExceptionKind::AbortError,
+            "encoder was reset",
+        ));
+        self.encode_queue_size.clear();
+        Ok(())
+    }
+
+    /// Closes the encoder; aborts any pending work. No callback fires after this returns.
+    /// Closing a closed encoder does nothing.
+    pub fn close(&mut self) {
+        // Fails only for a closed encoder.
+        let _ = self.reset();
+        self.codec.internal_slots.set_state(State::Closed);
+    }
 }
@@ -0,0 +1,51 @@
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+
+use super::Exception;
+
+/// What a codec created by a `with_channel` or `with_bounded_channel` constructor sends to
+/// its receiver, in the order the callbacks would have run.
+#[derive(Debug)]
+pub enum CodecEvent<T> {
+    /// An output, as the output callback would have received it.
+    Output(T),
+    /// A flush has completed: every output of the inputs submitted before it came first.
+    /// Flushes aborted by a reset or close, or failing with an error, send none.
+    Flushed,
+    /// An error that closed the codec, as the error callback would have received it.
+    Error(Exception),
+}
+
+/// The sending half of a codec's event channel.
+#[derive(Clone)]
+pub(crate) enum EventSender<T> {
+    Unbounded(Sender<CodecEvent<T>>),
+    Bounded(SyncSender<CodecEvent<T>>),
+}
+
+impl<T> EventSender<T> {
+    /// Sends `event`, blocking while a bounded channel is full. Events for a dropped
+    /// receiver are discarded.
+    pub fn send(&self, event: CodecEvent<T>) {
+        let _ = match self {
+            Self::Unbounded(sender) => sender.send(event),
+            Self::Bounded(sender) => sender.send(event),
+        };
+    }
+}
+
+/// An event channel holding at most `capacity` undelivered events, or any number without a
+/// capacity.
+pub(crate) fn event_channel<T>(
+    capacity: Option<usize>,
+) -> (EventSender<T>, Receiver<CodecEvent<T>>) {
+    match capacity {
+        Some(capacity) => {
+            let (sender, receiver) = mpsc::sync_channel(capacity);
+            (EventSender::Bounded(sender), receiver)
+        }
+        None => {
+            let (sender, receiver) = mpsc::channel();
+            (EventSender::Unbounded(sender), receiver)
+        }
+    }
+}
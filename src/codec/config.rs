@@ -1,35 +1,291 @@
-use std::sync::{Arc, Mutex};
+use crate::bitstream::BitstreamFormat;
 
-use crate::core::work_queue::WorkQueue;
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioDecoderConfig {
+    pub codec: String,
+    pub sample_rate: u32,
+    pub number_of_channels: u32,
+    /// Codec-specific setup data, e.g. the AudioSpecificConfig for AAC in MP4.
+    pub description: Option<Vec<u8>>,
+}
 
-use super::Exception;
+impl AudioDecoderConfig {
+    pub fn is_valid(&self) -> bool {
+        !self.codec.is_empty() && self.sample_rate > 0 && self.number_of_channels > 0
+    }
+}
 
-#[derive(Clone)]
-pub enum ConfigMessage {
-    AudioConfig(AudioConfigMessage),
-    //TODO:
-    // VideoConfig,
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoDecoderConfig {
+    pub codec: String,
+    pub coded_width: Option<u32>,
+    pub coded_height: Option<u32>,
+    /// Codec-specific setup data, e.g. the avcC box for H.264 in MP4. Without it, H.264 and
+    /// HEVC chunks are expected in Annex B format.
+    pub description: Option<Vec<u8>>,
+    pub hardware_acceleration: HardwareAcceleration,
 }
 
-#[derive(Clone)]
-pub struct AudioConfigMessage {
-    pub config: AudioDecoderConfig,
-    pub work_queue: Arc<WorkQueue>,
-    pub error_callback: Arc<dyn Fn(Exception) + Send + Sync>,
-    pub codec_impl: Arc<Mutex<Option<ffmpeg_next::decoder::Audio>>>,
+impl VideoDecoderConfig {
+    pub fn is_valid(&self) -> bool {
+        let size_is_valid = match (self.coded_width, self.coded_height) {
+            (Some(width), Some(height)) => width > 0 && height > 0,
+            (None, None) => true,
+            _ => false,
+        };
+        !self.codec.is_empty() && size_is_valid
+    }
 }
 
-#[derive(Clone)]
-pub struct AudioDecoderConfig {
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioEncoderConfig {
     pub codec: String,
     pub sample_rate: u32,
     pub number_of_channels: u32,
-    //TODO:
-    // pub description: ?
+    /// Target bitrate in bits per second; the encoder default when unset.
+    pub bitrate: Option<u64>,
+    /// Opus specific options.
+    pub opus: Option<OpusEncoderConfig>,
+    /// AAC specific options.
+    pub aac: Option<AacEncoderConfig>,
 }
 
-impl AudioDecoderConfig {
+impl AudioEncoderConfig {
     pub fn is_valid(&self) -> bool {
-        !self.codec.is_empty() && self.sample_rate > 0 && self.number_of_channels > 0
+        !self.codec.is_empty()
+            && self.sample_rate > 0
+            && self.number_of_channels > 0
+            && self.bitrate != Some(0)
+            && self.opus.as_ref().is_none_or(OpusEncoderConfig::is_valid)
     }
 }
+
+/// https://w3c.github.io/webcodecs/opus_codec_registration.html#opus-encoder-config
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpusEncoderConfig {
+    /// The duration of each frame in microseconds: 2500, 5000, 10000, 20000, 40000 or 60000.
+    pub frame_duration: u64,
+    /// Encoder complexity from 0 (fastest) to 10; the encoder default when unset.
+    pub complexity: Option<u32>,
+    /// Expected packet loss in percent, which the encoder spends redundancy on.
+    pub packetlossperc: u32,
+    /// Whether to add in-band forward error correction.
+    pub useinbandfec: bool,
+    /// Whether to use discontinuous transmission, sending almost nothing during silence.
+    pub usedtx: bool,
+    pub application: OpusApplication,
+}
+
+impl Default for OpusEncoderConfig {
+    fn default() -> Self {
+        Self {
+            frame_duration: 20_000,
+            complexity: None,
+            packetlossperc: 0,
+            useinbandfec: false,
+            usedtx: false,
+            application: OpusApplication::default(),
+        }
+    }
+}
+
+impl OpusEncoderConfig {
+    pub fn is_valid(&self) -> bool {
+        matches!(
+            self.frame_duration,
+            2_500 | 5_000 | 10_000 | 20_000 | 40_000 | 60_000
+        ) && self.complexity.is_none_or(|complexity| complexity <= 10)
+            && self.packetlossperc <= 100
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpusApplication {
+    /// Favours speech intelligibility.
+    Voip,
+    /// Favours faithfulness to the input.
+    #[default]
+    Audio,
+    /// Minimises the coding delay.
+    Lowdelay,
+}
+
+/// https://w3c.github.io/webcodecs/aac_codec_registration.html#aac-encoder-config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AacEncoderConfig {
+    pub format: AacBitstreamFormat,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AacBitstreamFormat {
+    /// Raw AAC frames, with the AudioSpecificConfig as the `description`.
+    #[default]
+    Aac,
+    /// Each chunk starts with an ADTS header, so there is no `description`.
+    Adts,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VideoEncoderConfig {
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    /// Target bitrate in bits per second; the encoder default when unset.
+    pub bitrate: Option<u64>,
+    /// Expected frame rate in frames per second.
+    pub framerate: Option<f64>,
+    pub bitrate_mode: VideoEncoderBitrateMode,
+    pub latency_mode: LatencyMode,
+    /// The SVC mode, e.g. "L1T2" for one spatial and two temporal layers.
+    pub scalability_mode: Option<String>,
+    pub hardware_acceleration: HardwareAcceleration,
+    /// Whether the alpha channel of frames is encoded or dropped.
+    pub alpha: AlphaOption,
+    /// What the frames show, e.g. "motion", "detail" or "text", for the encoder to tune for.
+    pub content_hint: Option<String>,
+    /// The maximum number of frames from one key frame to the next; the encoder default when
+    /// unset.
+    pub keyframe_interval: Option<u32>,
+    /// H.264 specific options.
+    pub avc: Option<AvcEncoderConfig>,
+    /// HEVC specific options.
+    pub hevc: Option<HevcEncoderConfig>,
+}
+
+impl VideoEncoderConfig {
+    /// A config with the default options for `codec` at the given size.
+    pub fn new(codec: impl Into<String>, width: u32, height: u32) -> Self {
+        Self {
+            codec: codec.into(),
+            width,
+            height,
+            bitrate: None,
+            framerate: None,
+            bitrate_mode: VideoEncoderBitrateMode::default(),
+            latency_mode: LatencyMode::default(),
+            scalability_mode: None,
+            hardware_acceleration: HardwareAcceleration::default(),
+            alpha: AlphaOption::default(),
+            content_hint: None,
+            keyframe_interval: None,
+            avc: None,
+            hevc: None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.codec.is_empty()
+            && self.width > 0
+            && self.height > 0
+            && self.bitrate != Some(0)
+            && self
+                .framerate
+                .is_none_or(|rate| rate.is_finite() && rate > 0.0)
+            && self.keyframe_interval != Some(0)
+            // Only the L1T modes have an encoder.
+            && self.temporal_layers().is_some()
+    }
+
+    /// The number of temporal layers of the scalability mode, if it is one of the L1T modes.
+    pub fn temporal_layers(&self) -> Option<u32> {
+        match self.scalability_mode.as_deref() {
+            None | Some("L1T1") => Some(1),
+            Some("L1T2") => Some(2),
+            Some("L1T3") => Some(3),
+            Some(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VideoEncoderBitrateMode {
+    /// Keeps the bitrate close to the target at all times.
+    Constant,
+    /// Lets the bitrate follow the complexity of the content, averaging the target.
+    #[default]
+    Variable,
+    /// Ignores the bitrate and encodes each frame with the quantizer passed to `encode`.
+    Quantizer,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Optimises for quality; the encoder may hold back frames.
+    #[default]
+    Quality,
+    /// Emits a chunk for every frame as soon as possible, e.g. for video calls.
+    Realtime,
+}
+
+/// Whether a codec runs on a hardware device (VAAPI, CUDA, QSV, ...) or in software.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HardwareAcceleration {
+    /// As `PreferHardware`.
+    #[default]
+    NoPreference,
+    /// Hardware when a device takes the config, software otherwise.
+    PreferHardware,
+    /// Software only, which gives the same output on every machine.
+    PreferSoftware,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaOption {
+    Keep,
+    #[default]
+    Discard,
+}
+
+/// Per-frame options for `VideoEncoder::encode`.
+///
+/// https://w3c.github.io/webcodecs/#dictdef-videoencoderencodeoptions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VideoEncoderEncodeOptions {
+    /// Whether the frame must be encoded as a key frame, e.g. to answer a picture loss
+    /// indication.
+    pub key_frame: bool,
+    /// Options used when the codec is VP9.
+    pub vp9: Option<QuantizerEncodeOptions>,
+    /// Options used when the codec is AV1.
+    pub av1: Option<QuantizerEncodeOptions>,
+    /// Options used when the codec is H.264.
+    pub avc: Option<QuantizerEncodeOptions>,
+}
+
+impl VideoEncoderEncodeOptions {
+    /// The quantizer requested for `codec`, and the largest quantizer the codec allows.
+    pub fn quantizer(&self, codec: &str) -> Option<(u32, u32)> {
+        let (options, max) = match codec.split('.').next().unwrap_or_default() {
+            "vp09" => (&self.vp9, 63),
+            "av01" => (&self.av1, 63),
+            "avc1" | "avc3" => (&self.avc, 51),
+            _ => return None,
+        };
+        options
+            .as_ref()
+            .and_then(|options| options.quantizer)
+            .map(|quantizer| (quantizer, max))
+    }
+}
+
+/// The codec specific part of `VideoEncoderEncodeOptions` for VP9, AV1 and H.264.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantizerEncodeOptions {
+    /// The quantizer of the frame in `Quantizer` bitrate mode: 0 to 63 for VP9 and AV1, 0 to
+    /// 51 for H.264.
+    pub quantizer: Option<u32>,
+}
+
+/// https://w3c.github.io/webcodecs/avc_codec_registration.html#avc-encoder-config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AvcEncoderConfig {
+    /// `LengthPrefixed` is the "avc" format, `AnnexB` the "annexb" format.
+    pub format: BitstreamFormat,
+}
+
+/// https://w3c.github.io/webcodecs/hevc_codec_registration.html#hevc-encoder-config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HevcEncoderConfig {
+    /// `LengthPrefixed` is the "hevc" format, `AnnexB` the "annexb" format.
+    pub format: BitstreamFormat,
+}
@@ -1,11 +1,119 @@
-use std::fmt::Debug;
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    sync::Arc,
+};
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum Exception {
+/// The kind of an `Exception`, named after the `DOMException` it corresponds to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ExceptionKind {
     TypeError,
     InvalidStateError,
     NotSupportedError,
     InternalError,
     DecodeError,
+    EncodingError,
     AbortError,
+    QuotaExceededError,
+    DataError,
+}
+
+impl ExceptionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExceptionKind::TypeError => "TypeError",
+            ExceptionKind::InvalidStateError => "InvalidStateError",
+            ExceptionKind::NotSupportedError => "NotSupportedError",
+            ExceptionKind::InternalError => "InternalError",
+            ExceptionKind::DecodeError => "DecodeError",
+            ExceptionKind::EncodingError => "EncodingError",
+            ExceptionKind::AbortError => "AbortError",
+            ExceptionKind::QuotaExceededError => "QuotaExceededError",
+            ExceptionKind::DataError => "DataError",
+        }
+    }
+}
+
+impl Display for ExceptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error reported by codecs, either returned from a method or passed to the error callback.
+///
+/// Carries the kind of failure, a human-readable message and, where there is one, the
+/// underlying error (e.g. the `ffmpeg_next::Error`) as its `source()`.
+#[derive(Clone)]
+pub struct Exception {
+    kind: ExceptionKind,
+    message: String,
+    source: Option<Arc<dyn Error + Send + Sync>>,
+}
+
+impl Exception {
+    pub fn new(kind: ExceptionKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Attaches the error that caused this exception.
+    pub fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn kind(&self) -> ExceptionKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<ExceptionKind> for Exception {
+    fn from(kind: ExceptionKind) -> Self {
+        Self::new(kind, "")
+    }
+}
+
+impl PartialEq<ExceptionKind> for Exception {
+    fn eq(&self, other: &ExceptionKind) -> bool {
+        self.kind == *other
+    }
+}
+
+impl Debug for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Exception");
+        debug
+            .field("kind", &self.kind)
+            .field("message", &self.message);
+        if let Some(source) = &self.source {
+            debug.field("source", source);
+        }
+        debug.finish()
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.kind, self.message)
+        }
+    }
+}
+
+impl Error for Exception {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn Error + 'static))
+    }
 }
@@ -1,11 +1,17 @@
+#[cfg(feature = "async")]
+mod async_codec;
 mod audio;
+mod channel;
 mod config;
 mod error;
 mod image;
 mod state;
 mod video;
 
+#[cfg(feature = "async")]
+pub use async_codec::*;
 pub use audio::*;
+pub use channel::CodecEvent;
 pub use config::*;
 pub use error::*;
 pub use image::*;
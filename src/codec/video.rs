@@ -1,58 +1,588 @@
+use std::{
+    borrow::Cow,
+    sync::{mpsc::Receiver, Arc},
+};
+
+use crate::{
+    core::{
+        backend::{self, VideoDecoderBackend, VideoEncoderBackend},
+        buffer_pool::BufferPool,
+        control::{CodecHandle, ControlMessageKind},
+        promise::Promise,
+        queue_size::QueueSize,
+    },
+    data::buffer::SharedBuffer,
+};
+
+use super::{
+    channel::{self, CodecEvent},
+    Exception, ExceptionKind, State, VideoDecoderConfig, VideoEncoderConfig,
+    VideoEncoderEncodeOptions,
+};
+
 /// Decodes `EncodedVideoChunk` objects.
 ///
 /// https://developer.mozilla.org/en-US/docs/Web/API/VideoDecoder
-pub struct VideoDecoder {}
+pub struct VideoDecoder {
+    codec: CodecHandle<VideoDecoderBackend>,
+    decode_queue_size: Arc<QueueSize>,
+    max_decode_queue_size: Option<u32>,
+    key_chunk_required: bool,
+    buffer_pool: Option<BufferPool>,
+}
 
 impl VideoDecoder {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        output_callback: impl Fn(VideoFrame) + Send + Sync + 'static,
+        error_callback: impl Fn(Exception) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            codec: CodecHandle::new(output_callback, error_callback, ExceptionKind::DecodeError),
+            decode_queue_size: Arc::new(QueueSize::new()),
+            max_decode_queue_size: None,
+            key_chunk_required: true,
+            buffer_pool: None,
+        }
+    }
+
+    /// Creates a decoder that sends its frames, completed flushes and errors, in order, to
+    /// the returned receiver instead of invoking callbacks.
+    pub fn with_channel() -> (Self, Receiver<CodecEvent<VideoFrame>>) {
+        Self::with_event_channel(None)
+    }
+
+    /// Like `with_channel`, but holds at most `capacity` undelivered events.
+    ///
+    /// When the receiver falls behind, decoding stalls and `decode` blocks until fewer than
+    /// `capacity` chunks are pending, so the receiver must be drained from another thread.
+    pub fn with_bounded_channel(capacity: usize) -> (Self, Receiver<CodecEvent<VideoFrame>>) {
+        Self::with_event_channel(Some(capacity))
+    }
+
+    fn with_event_channel(capacity: Option<usize>) -> (Self, Receiver<CodecEvent<VideoFrame>>) {
+        let (sender, receiver) = channel::event_channel(capacity);
+        let (outputs, errors) = (sender.clone(), sender.clone());
+        let mut decoder = Self::new(
+            move |frame| outputs.send(CodecEvent::Output(frame)),
+            move |err| errors.send(CodecEvent::Error(err)),
+        );
+        decoder
+            .codec
+            .set_flush_callback(move || sender.send(CodecEvent::Flushed));
+        decoder.max_decode_queue_size = capacity.map(|capacity| capacity.max(1) as u32);
+        (decoder, receiver)
     }
 
-    pub fn is_config_supported() {}
+    pub fn state(&self) -> State {
+        self.codec.internal_slots.state()
+    }
+
+    /// The number of pending decode requests.
+    pub fn decode_queue_size(&self) -> u32 {
+        self.decode_queue_size.get()
+    }
+
+    /// Draws the buffers of the decoder's outputs from `pool` from the next `configure` on,
+    /// which bounds the number of outputs alive at a time and reuses their memory.
+    ///
+    /// Backends that do not allocate their outputs, e.g. mocks that pass buffers through,
+    /// ignore the pool.
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.buffer_pool = Some(pool);
+    }
 
-    pub fn configure() {}
+    pub fn is_config_supported(&self, config: &VideoDecoderConfig) -> bool {
+        config.is_valid()
+    }
+
+    /// Initialises the underlying decoder with given config.
+    ///
+    /// With a `description`, H.264 and HEVC chunks must be length-prefixed ("avc"/"hevc"
+    /// format); without one they must be Annex B. The next chunk must be a key chunk.
+    pub fn configure(&mut self, config: VideoDecoderConfig) -> Result<(), Exception> {
+        if !self.is_config_supported(&config) {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                "invalid video decoder config",
+            ));
+        }
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "cannot configure a closed decoder",
+            ));
+        }
+
+        self.codec.internal_slots.set_state(State::Configured);
+        self.key_chunk_required = true;
 
-    pub fn decode() {}
+        let buffer_pool = self.buffer_pool.clone();
+        let create = move || backend::create_video_decoder(&config, buffer_pool.as_ref());
+        self.codec
+            .enqueue(ControlMessageKind::Configure(Box::new(create)));
+
+        Ok(())
+    }
 
-    pub fn flush() {}
+    /// Decodes an encoded video chunk.
+    pub fn decode(&mut self, chunk: EncodedVideoChunk) -> Result<(), Exception> {
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is not configured",
+            ));
+        }
+        if self.key_chunk_required && !chunk.is_key {
+            return Err(Exception::new(
+                ExceptionKind::DecodeError,
+                "a key chunk is required",
+            ));
+        }
+        self.key_chunk_required = false;
+        if let Some(max_size) = self.max_decode_queue_size {
+            self.decode_queue_size.wait_below(max_size);
+        }
+        self.decode_queue_size.increment();
 
-    pub fn reset() {}
+        self.codec.enqueue(ControlMessageKind::Send {
+            input: chunk,
+            queue_size: self.decode_queue_size.clone(),
+        });
 
-    pub fn close() {}
+        Ok(())
+    }
+
+    /// Flush the decoder and drain remaining frames.
+    ///
+    /// The returned promise settles once every frame has been handed to the output callback.
+    /// The next chunk must be a key chunk.
+    pub fn flush(&mut self) -> Result<Promise<()>, Exception> {
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is not configured",
+            ));
+        }
+        self.key_chunk_required = true;
+
+        let promise = Promise::new();
+        self.codec
+            .enqueue(ControlMessageKind::Flush(promise.clone()));
+
+        Ok(promise)
+    }
+
+    /// Resets the decoder, aborting all pending work.
+    ///
+    /// Chunks not decoded yet are dropped without output and pending flushes are rejected
+    /// with `AbortError`.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "decoder is closed",
+            ));
+        }
+        self.codec.internal_slots.set_state(State::Unconfigured);
+        self.codec.abort(&Exception::new(
+            ExceptionKind::AbortError,
+            "decoder was reset",
+        ));
+        self.decode_queue_size.clear();
+        Ok(())
+    }
+
+    /// Closes the decoder; aborts any pending work. No callback fires after this returns.
+    /// Closing a closed decoder does nothing.
+    pub fn close(&mut self) {
+        // Fails only for a closed decoder.
+        let _ = self.reset();
+        self.codec.internal_slots.set_state(State::Closed);
+    }
 }
 
+/// An encoded video chunk with the metadata the encoder emitted it with.
+pub type VideoChunk = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
 /// Encodes `VideoFrame` objects.
 ///
-/// https://developer.mozilla.org/en-US/docs/Web/API/VideoFrame
-pub struct VideoEncoder {}
+/// https://developer.mozilla.org/en-US/docs/Web/API/VideoEncoder
+pub struct VideoEncoder {
+    codec: CodecHandle<VideoEncoderBackend>,
+    encode_queue_size: Arc<QueueSize>,
+    max_encode_queue_size: Option<u32>,
+    /// The codec string of the last config, to pick the codec specific encode options.
+    active_codec: Option<String>,
+}
 
 impl VideoEncoder {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        output_callback: impl Fn(EncodedVideoChunk, EncodedVideoChunkMetadata) + Send + Sync + 'static,
+        error_callback: impl Fn(Exception) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            codec: CodecHandle::new(
+                move |(chunk, metadata)| output_callback(chunk, metadata),
+                error_callback,
+                ExceptionKind::EncodingError,
+            ),
+            encode_queue_size: Arc::new(QueueSize::new()),
+            max_encode_queue_size: None,
+            active_codec: None,
+        }
+    }
+
+    /// Creates an encoder that sends its chunks with their metadata, completed flushes and
+    /// errors, in order, to the returned receiver instead of invoking callbacks.
+    pub fn with_channel() -> (Self, Receiver<CodecEvent<VideoChunk>>) {
+        Self::with_event_channel(None)
+    }
+
+    /// Like `with_channel`, but holds at most `capacity` undelivered events.
+    ///
+    /// When the receiver falls behind, encoding stalls and `encode` blocks until fewer than
+    /// `capacity` frames are pending, so the receiver must be drained from another thread.
+    pub fn with_bounded_channel(capacity: usize) -> (Self, Receiver<CodecEvent<VideoChunk>>) {
+        Self::with_event_channel(Some(capacity))
+    }
+
+    fn with_event_channel(capacity: Option<usize>) -> (Self, Receiver<CodecEvent<VideoChunk>>) {
+        let (sender, receiver) = channel::event_channel(capacity);
+        let (outputs, errors) = (sender.clone(), sender.clone());
+        let mut encoder = Self::new(
+            move |chunk, metadata| outputs.send(CodecEvent::Output((chunk, metadata))),
+            move |err| errors.send(CodecEvent::Error(err)),
+        );
+        encoder
+            .codec
+            .set_flush_callback(move || sender.send(CodecEvent::Flushed));
+        encoder.max_encode_queue_size = capacity.map(|capacity| capacity.max(1) as u32);
+        (encoder, receiver)
+    }
+
+    pub fn state(&self) -> State {
+        self.codec.internal_slots.state()
+    }
+
+    /// The number of pending encode requests.
+    pub fn encode_queue_size(&self) -> u32 {
+        self.encode_queue_size.get()
+    }
+
+    pub fn is_config_supported(&self, config: &VideoEncoderConfig) -> bool {
+        config.is_valid()
+    }
+
+    /// Initialises the underlying encoder with given config.
+    ///
+    /// Reconfiguring a configured encoder keeps frames that were already submitted: they are
+    /// encoded and drained with the previous config before the new encoder takes over. The
+    /// first chunk of the new encoder is a key chunk and carries its decoder config in the
+    /// metadata.
+    pub fn configure(&mut self, config: VideoEncoderConfig) -> Result<(), Exception> {
+        if !self.is_config_supported(&config) {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                "invalid video encoder config",
+            ));
+        }
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "cannot configure a closed encoder",
+            ));
+        }
+
+        self.codec.internal_slots.set_state(State::Configured);
+        self.active_codec = Some(config.codec.clone());
+
+        let create = move || backend::create_video_encoder(&config);
+        self.codec
+            .enqueue(ControlMessageKind::Configure(Box::new(create)));
+
+        Ok(())
     }
 
-    pub fn is_config_supported() {}
+    /// Encodes a video frame.
+    ///
+    /// With `options.key_frame` the frame is encoded as a key frame. The quantizer in
+    /// `options` for the configured codec is used in `Quantizer` bitrate mode and ignored
+    /// otherwise. The libvpx, libaom and SVT-AV1 encoders keep the quantizer of the first
+    /// frame after configure or flush; another one before the next flush closes the encoder
+    /// with a `NotSupportedError`.
+    pub fn encode(
+        &mut self,
+        frame: VideoFrame,
+        options: VideoEncoderEncodeOptions,
+    ) -> Result<(), Exception> {
+        if frame.is_closed() {
+            return Err(Exception::new(ExceptionKind::TypeError, "frame is closed"));
+        }
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is not configured",
+            ));
+        }
+        let codec = self.active_codec.as_deref().unwrap_or_default();
+        if let Some((quantizer, max)) = options.quantizer(codec) {
+            if quantizer > max {
+                return Err(Exception::new(
+                    ExceptionKind::TypeError,
+                    format!("quantizer {quantizer} is out of range 0..={max}"),
+                ));
+            }
+        }
+        if let Some(max_size) = self.max_encode_queue_size {
+            self.encode_queue_size.wait_below(max_size);
+        }
+        self.encode_queue_size.increment();
 
-    pub fn configure() {}
+        self.codec.enqueue(ControlMessageKind::Send {
+            input: (frame, options),
+            queue_size: self.encode_queue_size.clone(),
+        });
 
-    pub fn encode() {}
+        Ok(())
+    }
+
+    /// Encodes all pending frames and emits the remaining chunks.
+    ///
+    /// The returned promise settles once every chunk has been handed to the output callback.
+    /// The first chunk after a flush is a key chunk.
+    pub fn flush(&mut self) -> Result<Promise<()>, Exception> {
+        if self.state() != State::Configured {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is not configured",
+            ));
+        }
 
-    pub fn flush() {}
+        let promise = Promise::new();
+        self.codec
+            .enqueue(ControlMessageKind::Flush(promise.clone()));
 
-    pub fn reset() {}
+        Ok(promise)
+    }
+
+    /// Resets the encoder, aborting all pending work.
+    ///
+    /// Frames not encoded yet are dropped without output and pending flushes are rejected
+    /// with `AbortError`.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        if self.state() == State::Closed {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "encoder is closed",
+            ));
+        }
+        self.codec.internal_slots.set_state(State::Unconfigured);
+        self.codec.abort(&Exception::new(
+            ExceptionKind::AbortError,
+            "encoder was reset",
+        ));
+        self.encode_queue_size.clear();
+        Ok(())
+    }
 
-    pub fn close() {}
+    /// Closes the encoder; aborts any pending work. No callback fires after this returns.
+    /// Closing a closed encoder does nothing.
+    pub fn close(&mut self) {
+        // Fails only for a closed encoder.
+        let _ = self.reset();
+        self.codec.internal_slots.set_state(State::Closed);
+    }
 }
 
 /// Represents codec-specific encoded video bytes.
 ///
 /// https://developer.mozilla.org/en-US/docs/Web/API/EncodedVideoChunk
-pub struct EncodedVideoChunk {}
+#[derive(Debug, Clone)]
+pub struct EncodedVideoChunk {
+    pub data: SharedBuffer,
+    /// The presentation timestamp in microseconds.
+    pub timestamp: i64,
+    /// The decode timestamp in microseconds, if known. Encoders that reorder frames, e.g.
+    /// for B-frames, emit chunks in decode order with this behind `timestamp`; containers
+    /// need it, decoders ignore it.
+    pub decode_timestamp: Option<i64>,
+    /// The duration in microseconds, if known.
+    pub duration: Option<u64>,
+    pub is_key: bool,
+}
+
+/// Information about an `EncodedVideoChunk` passed along with it to the encoder's output
+/// callback.
+///
+/// https://developer.mozilla.org/en-US/docs/Web/API/VideoEncoder/VideoEncoder#metadata
+#[derive(Debug, Clone, Default)]
+pub struct EncodedVideoChunkMetadata {
+    /// Set on the first chunk after the encoder (re)configures, and whenever the
+    /// configuration needed to decode the chunks changes.
+    pub decoder_config: Option<VideoDecoderConfig>,
+    /// Set when the config has a `scalability_mode`, on every chunk whose temporal layer the
+    /// encoder reports.
+    pub svc: Option<SvcOutputMetadata>,
+}
+
+/// https://w3c.github.io/webcodecs/#dictdef-svcoutputmetadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SvcOutputMetadata {
+    /// The temporal layer of the chunk; chunks of higher layers can be dropped without
+    /// breaking the decoding of lower ones.
+    pub temporal_layer_id: u32,
+}
 
 /// Represents a frame of unencoded video data.
 ///
+/// Clones share the pixel buffer rather than copying it.
+///
 /// https://developer.mozilla.org/en-US/docs/Web/API/VideoFrame
-pub struct VideoFrame {}
+#[derive(Debug, Clone)]
+pub struct VideoFrame {
+    /// The pixel format, e.g. "I420" or "RGBA".
+    pub format: String,
+    pub coded_width: u32,
+    pub coded_height: u32,
+    /// The presentation timestamp in microseconds.
+    pub timestamp: i64,
+    /// The duration in microseconds, if known.
+    pub duration: Option<u64>,
+    /// The planes one after another, each tightly packed.
+    pub data: SharedBuffer,
+}
+
+impl VideoFrame {
+    pub fn new(
+        format: String,
+        coded_width: u32,
+        coded_height: u32,
+        timestamp: i64,
+        data: impl Into<SharedBuffer>,
+    ) -> Self {
+        VideoFrame {
+            format,
+            coded_width,
+            coded_height,
+            timestamp,
+            duration: None,
+            data: data.into(),
+        }
+    }
+
+    /// Creates a frame from pixels in a caller's buffer, checking that the buffer holds every
+    /// plane of the format at the coded size.
+    ///
+    /// Fails with `TypeError` for an unknown format, an empty size or a buffer that is too
+    /// small.
+    pub fn try_new(init: VideoFrameBufferInit<'_>) -> Result<Self, Exception> {
+        let invalid = |message: String| Exception::new(ExceptionKind::TypeError, message);
+        if init.coded_width == 0 || init.coded_height == 0 {
+            return Err(invalid(format!(
+                "invalid coded size {}x{}",
+                init.coded_width, init.coded_height
+            )));
+        }
+        let layout = plane_layout(&init.format, init.coded_width, init.coded_height)
+            .ok_or_else(|| invalid(format!("unknown VideoFrame format {:?}", init.format)))?;
+        let size: usize = layout.iter().map(|(row, rows)| row * rows).sum();
+        if init.data.len() < size {
+            return Err(invalid(format!(
+                "buffer of {} bytes is too small for a {}x{} {} frame",
+                init.data.len(),
+                init.coded_width,
+                init.coded_height,
+                init.format
+            )));
+        }
+
+        let data = match init.data {
+            Cow::Owned(mut data) if init.transfer => {
+                // Shortening keeps the allocation, so the pixels are not copied.
+                data.truncate(size);
+                data
+            }
+            data => data[..size].to_vec(),
+        };
+        let mut frame = Self::new(
+            init.format,
+            init.coded_width,
+            init.coded_height,
+            init.timestamp,
+            data,
+        );
+        frame.duration = init.duration;
+        Ok(frame)
+    }
+
+    /// Releases this reference to the pixels without waiting for the frame to be dropped.
+    /// Afterwards it has no format or size, and codecs reject it.
+    ///
+    /// Clones keep their own reference, so the memory is freed once every clone is closed
+    /// or dropped.
+    pub fn close(&mut self) {
+        self.format.clear();
+        self.coded_width = 0;
+        self.coded_height = 0;
+        self.duration = None;
+        self.data = SharedBuffer::new();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.format.is_empty()
+    }
+}
+
+/// The arguments of `VideoFrame::try_new`.
+///
+/// https://w3c.github.io/webcodecs/#dictdef-videoframebufferinit
+#[derive(Debug, Clone)]
+pub struct VideoFrameBufferInit<'a> {
+    /// The pixel format, e.g. "I420" or "RGBA".
+    pub format: String,
+    pub coded_width: u32,
+    pub coded_height: u32,
+    /// The presentation timestamp in microseconds.
+    pub timestamp: i64,
+    /// The duration in microseconds, if known.
+    pub duration: Option<u64>,
+    /// The planes one after another, each tightly packed. Bytes past the last plane are
+    /// ignored.
+    pub data: Cow<'a, [u8]>,
+    /// Whether an owned `data` buffer is taken over without copying rather than copied,
+    /// like listing it in `transfer` in the spec. Borrowed data is always copied.
+    pub transfer: bool,
+}
+
+/// The bytes per row and the rows of each plane of a tightly packed frame, `None` for
+/// unknown formats.
+pub fn plane_layout(format: &str, width: u32, height: u32) -> Option<Vec<(usize, usize)>> {
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let layout = match format {
+        "I420" => vec![
+            (width, height),
+            (chroma_width, chroma_height),
+            (chroma_width, chroma_height),
+        ],
+        "I420A" => vec![
+            (width, height),
+            (chroma_width, chroma_height),
+            (chroma_width, chroma_height),
+            (width, height),
+        ],
+        "I422" => vec![
+            (width, height),
+            (chroma_width, height),
+            (chroma_width, height),
+        ],
+        "I444" => vec![(width, height); 3],
+        "NV12" => vec![(width, height), (2 * chroma_width, chroma_height)],
+        "RGBA" | "RGBX" | "BGRA" | "BGRX" => vec![(4 * width, height)],
+        _ => return None,
+    };
+    Some(layout)
+}
 
 /// Represents the color space of a video frame.
 ///
@@ -0,0 +1,282 @@
+use ffmpeg_next::codec::Id;
+
+use crate::{
+    bitstream::{AudioSpecificConfig, OpusHead},
+    codec::{AudioDecoderConfig, Exception, ExceptionKind},
+    core::{
+        backend::CodecBackend,
+        buffer_pool::{BufferPool, PooledBuffer},
+        ffmpeg,
+        ffmpeg_backend::FfmpegCodec,
+    },
+    data::{
+        audio_data::{AudioData, EncodedAudioChunk},
+        buffer::SharedBuffer,
+    },
+};
+
+/// An ffmpeg audio decoder, which outputs `f32-planar` `AudioData`.
+pub struct AudioDecoderImpl {
+    decoder: ffmpeg_next::decoder::Audio,
+    /// The timestamp of the next frame, for frames that carry no timestamp of their own.
+    next_timestamp: f64,
+    /// The pool the sample buffers of outputs are drawn from, if any.
+    pool: Option<BufferPool>,
+}
+
+impl AudioDecoderImpl {
+    pub fn new(config: &AudioDecoderConfig, pool: Option<BufferPool>) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+
+        let codec = find_audio_decoder(&config.codec).ok_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no decoder found for codec {:?}", config.codec),
+            )
+        })?;
+
+        let mut context = ffmpeg_next::codec::Context::new_with_codec(codec);
+        ffmpeg::set_audio_parameters(&mut context, config.sample_rate, config.number_of_channels);
+        if let Some(description) = audio_decoder_description(codec.id(), config)? {
+            ffmpeg::set_extradata(&mut context, &description);
+        }
+        let mut decoder = context.decoder();
+        // Chunk timestamps are in microseconds, so frames come out in microseconds too.
+        decoder.set_packet_time_base(ffmpeg::MICROSECONDS);
+        let decoder = decoder.audio().map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("failed to open decoder for codec {:?}", config.codec),
+            )
+            .with_source(e)
+        })?;
+
+        Ok(Self {
+            decoder,
+            next_timestamp: 0.0,
+            pool,
+        })
+    }
+
+    pub fn decode(&mut self, chunk: &EncodedAudioChunk) -> Result<(), Exception> {
+        // An empty packet would tell ffmpeg that the stream ended instead.
+        if chunk.data.is_empty() {
+            return Err(Exception::new(
+                ExceptionKind::DecodeError,
+                format!("empty chunk at timestamp {}", chunk.timestamp),
+            ));
+        }
+        // ffmpeg reads past the end of packets, so they need padding the chunk's buffer
+        // does not have, and the bytes are copied.
+        let mut packet = ffmpeg_next::Packet::copy(&chunk.data);
+        packet.set_pts(Some(chunk.timestamp));
+        self.next_timestamp = chunk.timestamp as f64;
+
+        self.decoder.send_packet(&packet).map_err(|e| {
+            Exception::new(
+                ExceptionKind::DecodeError,
+                format!("failed to decode chunk at timestamp {}", chunk.timestamp),
+            )
+            .with_source(e)
+        })
+    }
+
+    /// The next decoded frame, `None` when the decoder needs more chunks.
+    pub fn receive_frame(&mut self) -> Result<Option<AudioData>, Exception> {
+        let mut frame = ffmpeg_next::frame::Audio::empty();
+        match self.decoder.receive_frame(&mut frame) {
+            Ok(()) => {}
+            Err(ffmpeg_next::Error::Eof) => return Ok(None),
+            Err(ffmpeg_next::Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                return Ok(None)
+            }
+            Err(e) => {
+                return Err(
+                    Exception::new(ExceptionKind::DecodeError, "failed to decode frame")
+                        .with_source(e),
+                )
+            }
+        }
+        let timestamp = frame
+            .timestamp()
+            .or(frame.pts())
+            .map_or(self.next_timestamp, |ts| ts as f64);
+        let audio_data = convert_audio_frame(frame, timestamp, self.pool.as_ref())?;
+        self.next_timestamp = timestamp + audio_data.duration;
+        Ok(Some(audio_data))
+    }
+
+    /// Lets the decoder output every frame it holds.
+    pub fn drain(&mut self) -> Result<(), Exception> {
+        self.decoder.send_eof().map_err(|e| {
+            Exception::new(ExceptionKind::DecodeError, "failed to drain decoder").with_source(e)
+        })
+    }
+
+    /// Readies the decoder for the next key chunk.
+    pub fn reset(&mut self) {
+        self.decoder.flush();
+    }
+}
+
+impl CodecBackend for FfmpegCodec<AudioDecoderImpl> {
+    type Config = AudioDecoderConfig;
+    type Input = EncodedAudioChunk;
+    type Output = AudioData;
+
+    fn configure(&mut self, config: &AudioDecoderConfig) -> Result<(), Exception> {
+        self.open(AudioDecoderImpl::new(config, self.pool())?);
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        self.opened()?.decode(&chunk)
+    }
+
+    fn receive(&mut self) -> Result<Option<AudioData>, Exception> {
+        self.opened()?.receive_frame()
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.opened()?.drain()
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.opened()?.reset();
+        Ok(())
+    }
+
+    fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.set_pool(pool);
+    }
+}
+
+/// The extradata for the decoder: the config's `description`, checked up front for AAC and
+/// Opus, or a generated OpusHead for multichannel Opus, which ffmpeg cannot decode without
+/// a channel mapping.
+fn audio_decoder_description(
+    id: Id,
+    config: &AudioDecoderConfig,
+) -> Result<Option<Vec<u8>>, Exception> {
+    match (id, &config.description) {
+        (Id::AAC, Some(description)) => {
+            AudioSpecificConfig::parse(description)?;
+        }
+        (Id::OPUS, Some(description)) => {
+            OpusHead::parse(description)?;
+        }
+        (Id::OPUS, None) if config.number_of_channels > 2 => {
+            let channels = u8::try_from(config.number_of_channels).map_err(|_| {
+                Exception::new(
+                    ExceptionKind::NotSupportedError,
+                    format!("Opus cannot carry {} channels", config.number_of_channels),
+                )
+            })?;
+            return Ok(Some(
+                OpusHead::new(channels, config.sample_rate)?.to_bytes(),
+            ));
+        }
+        _ => {}
+    }
+    Ok(config.description.clone())
+}
+
+/// Looks up an ffmpeg decoder by WebCodecs codec string or ffmpeg decoder name.
+pub fn find_audio_decoder(codec: &str) -> Option<ffmpeg_next::Codec> {
+    let name = match codec {
+        codec if codec.starts_with("mp4a.") => match codec {
+            "mp4a.69" | "mp4a.6B" | "mp4a.40.34" => "mp3",
+            _ => "aac",
+        },
+        codec => codec,
+    };
+    ffmpeg_next::codec::decoder::find_by_name(name)
+}
+
+/// Converts a decoded frame to `f32-planar` `AudioData`, in a buffer from `pool` if there is
+/// one. Without a pool, mono `f32` frames are shared rather than copied.
+///
+/// Frames with more channels are always copied: `AudioData` keeps its planes one after
+/// another in a single buffer, while ffmpeg gives each plane a buffer of its own.
+fn convert_audio_frame(
+    frame: ffmpeg_next::frame::Audio,
+    timestamp: f64,
+    pool: Option<&BufferPool>,
+) -> Result<AudioData, Exception> {
+    let target_format = ffmpeg_next::format::Sample::F32(ffmpeg_next::format::sample::Type::Planar);
+    let converted_frame = if frame.format() != target_format {
+        let mut channel_layout = frame.channel_layout();
+        if channel_layout.is_empty() {
+            channel_layout = ffmpeg_next::ChannelLayout::default(frame.channels().into());
+        }
+        let resample_error = |e: ffmpeg_next::Error| {
+            Exception::new(
+                ExceptionKind::DecodeError,
+                format!("failed to convert {:?} samples", frame.format()),
+            )
+            .with_source(e)
+        };
+        let mut resampler = ffmpeg_next::software::resampling::Context::get(
+            frame.format(),
+            channel_layout,
+            frame.rate(),
+            target_format,
+            channel_layout,
+            frame.rate(),
+        )
+        .map_err(resample_error)?;
+        let mut cf = ffmpeg_next::frame::Audio::empty();
+        resampler.run(&frame, &mut cf).map_err(resample_error)?;
+        cf
+    } else {
+        frame
+    };
+
+    let num_frames = converted_frame.samples();
+    let sample_rate = converted_frame.rate();
+    let channels = converted_frame.channels();
+    if sample_rate == 0 || channels == 0 {
+        return Err(Exception::new(
+            ExceptionKind::DecodeError,
+            "decoded frame has no sample rate or channels",
+        ));
+    }
+    let plane_size = num_frames * std::mem::size_of::<f32>();
+    let data = match pool {
+        // Outputs in the pool's buffers are what bounds the audio a decoder has outstanding.
+        Some(_) => copy_planes(&converted_frame, pool)?,
+        // Only a mono frame is laid out like `AudioData`.
+        None => {
+            match ffmpeg::FrameBuffer::new(converted_frame, &vec![plane_size; channels as usize]) {
+                Ok(buffer) => SharedBuffer::from_owner(buffer),
+                Err(frame) => copy_planes(&frame, None)?,
+            }
+        }
+    };
+    Ok(AudioData::new(
+        "f32-planar".to_string(),
+        sample_rate as f64,
+        channels as u32,
+        num_frames as u32,
+        timestamp,
+        data,
+    ))
+}
+
+/// Copies the samples of every plane of an `f32-planar` frame one after another.
+fn copy_planes(
+    frame: &ffmpeg_next::frame::Audio,
+    pool: Option<&BufferPool>,
+) -> Result<SharedBuffer, Exception> {
+    let size = frame.samples() * frame.planes() * std::mem::size_of::<f32>();
+    let mut buffer = PooledBuffer::acquire(pool, size)?;
+    for plane in 0..frame.planes() {
+        for sample in frame.plane::<f32>(plane) {
+            buffer.extend_from_slice(&sample.to_ne_bytes());
+        }
+    }
+    Ok(buffer.freeze())
+}
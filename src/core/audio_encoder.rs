@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+
+use ffmpeg_next::{
+    codec::Id,
+    format::{sample::Type as SampleType, Sample},
+};
+
+use crate::{
+    bitstream::{AudioSpecificConfig, OpusHead},
+    codec::{
+        AacBitstreamFormat, AudioDecoderConfig, AudioEncoderConfig, Exception, ExceptionKind,
+        OpusApplication,
+    },
+    core::{backend::CodecBackend, ffmpeg, ffmpeg_backend::FfmpegCodec, pcm::f32_planes},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+};
+
+const PLANAR_F32: Sample = Sample::F32(SampleType::Planar);
+
+/// An ffmpeg audio encoder together with the samples waiting for a full encoder frame.
+///
+/// One `AudioData` can fill several encoder frames, so chunks are collected until they are
+/// received.
+pub struct AudioEncoderImpl {
+    encoder: ffmpeg_next::encoder::Audio,
+    codec: ffmpeg_next::Codec,
+    config: AudioEncoderConfig,
+    resampler: Option<ffmpeg_next::software::resampling::Context>,
+    /// Pending samples of each channel.
+    pending: Vec<Vec<f32>>,
+    /// Timestamp of the first pending sample, in samples.
+    next_pts: Option<i64>,
+    /// The decoder config last reported in output metadata.
+    active_output_config: Option<AudioDecoderConfig>,
+    /// Chunks that have not been received yet.
+    ready: VecDeque<(EncodedAudioChunk, EncodedAudioChunkMetadata)>,
+}
+
+impl AudioEncoderImpl {
+    pub fn new(config: &AudioEncoderConfig) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+
+        let codec = find_audio_encoder(&config.codec).ok_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no encoder found for codec {:?}", config.codec),
+            )
+        })?;
+        let encoder = open_audio_encoder(codec, config)?;
+
+        Ok(Self {
+            encoder,
+            codec,
+            config: config.clone(),
+            resampler: None,
+            pending: vec![Vec::new(); config.number_of_channels as usize],
+            next_pts: None,
+            active_output_config: None,
+            ready: VecDeque::new(),
+        })
+    }
+
+    /// Queues the samples of `data` and encodes every full encoder frame.
+    pub fn encode(&mut self, data: &AudioData) -> Result<(), Exception> {
+        if data.sample_rate as u32 != self.config.sample_rate
+            || data.number_of_channels != self.config.number_of_channels
+        {
+            return Err(Exception::new(
+                ExceptionKind::EncodingError,
+                format!(
+                    "AudioData with {} channels at {} Hz does not match the encoder config",
+                    data.number_of_channels, data.sample_rate
+                ),
+            ));
+        }
+        let planes = f32_planes(data)?;
+
+        if self.next_pts.is_none() {
+            let rate = self.config.sample_rate as f64;
+            self.next_pts = Some((data.timestamp * rate / 1_000_000.0).round() as i64);
+        }
+        for (pending, plane) in self.pending.iter_mut().zip(planes) {
+            pending.extend_from_slice(&plane);
+        }
+
+        let frame_size = match self.encoder.frame_size() as usize {
+            // The encoder accepts frames of any size.
+            0 => self.pending_samples().max(1),
+            frame_size => frame_size,
+        };
+        while self.pending_samples() >= frame_size {
+            self.send_pending(frame_size)?;
+            self.receive_chunks()?;
+        }
+        Ok(())
+    }
+
+    /// The next chunk, `None` when the encoder needs more samples.
+    pub fn receive_chunk(&mut self) -> Option<(EncodedAudioChunk, EncodedAudioChunkMetadata)> {
+        self.ready.pop_front()
+    }
+
+    /// Encodes the remaining samples and collects every chunk still held by the encoder.
+    pub fn drain(&mut self) -> Result<(), Exception> {
+        if self.pending_samples() > 0 {
+            self.send_pending(self.pending_samples())?;
+        }
+        self.encoder.send_eof().map_err(|e| {
+            Exception::new(ExceptionKind::EncodingError, "failed to drain encoder").with_source(e)
+        })?;
+        self.receive_chunks()
+    }
+
+    /// Reopens the encoder once it has been drained, so that encoding can continue.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        self.encoder = open_audio_encoder(self.codec, &self.config)?;
+        self.next_pts = None;
+        for pending in &mut self.pending {
+            pending.clear();
+        }
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn pending_samples(&self) -> usize {
+        self.pending.first().map_or(0, Vec::len)
+    }
+
+    /// Sends the first `samples` pending samples to the encoder as one frame.
+    fn send_pending(&mut self, samples: usize) -> Result<(), Exception> {
+        let channel_layout =
+            ffmpeg_next::ChannelLayout::default(self.config.number_of_channels as i32);
+        let mut frame = ffmpeg_next::frame::Audio::new(PLANAR_F32, samples, channel_layout);
+        frame.set_rate(self.config.sample_rate);
+        for (ch, pending) in self.pending.iter_mut().enumerate() {
+            frame
+                .plane_mut::<f32>(ch)
+                .copy_from_slice(&pending[..samples]);
+            pending.drain(..samples);
+        }
+
+        let mut frame = if self.encoder.format() == PLANAR_F32 {
+            frame
+        } else {
+            if self.resampler.is_none() {
+                self.resampler = Some(
+                    ffmpeg_next::software::resampling::Context::get(
+                        PLANAR_F32,
+                        channel_layout,
+                        self.config.sample_rate,
+                        self.encoder.format(),
+                        channel_layout,
+                        self.config.sample_rate,
+                    )
+                    .map_err(|e| {
+                        Exception::new(
+                            ExceptionKind::EncodingError,
+                            format!("cannot convert samples to {:?}", self.encoder.format()),
+                        )
+                        .with_source(e)
+                    })?,
+                );
+            }
+            let mut converted = ffmpeg_next::frame::Audio::empty();
+            if let Some(resampler) = self.resampler.as_mut() {
+                resampler.run(&frame, &mut converted).map_err(|e| {
+                    Exception::new(ExceptionKind::EncodingError, "failed to convert samples")
+                        .with_source(e)
+                })?;
+            }
+            converted
+        };
+
+        let pts = self.next_pts.unwrap_or(0);
+        frame.set_pts(Some(pts));
+        self.next_pts = Some(pts + samples as i64);
+
+        self.encoder.send_frame(&frame).map_err(|e| {
+            Exception::new(ExceptionKind::EncodingError, "failed to encode frame").with_source(e)
+        })
+    }
+
+    fn receive_chunks(&mut self) -> Result<(), Exception> {
+        let mut packet = ffmpeg_next::Packet::empty();
+        loop {
+            match self.encoder.receive_packet(&mut packet) {
+                Ok(()) => {}
+                Err(ffmpeg_next::Error::Eof) => return Ok(()),
+                Err(ffmpeg_next::Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                    return Ok(())
+                }
+                Err(e) => {
+                    return Err(Exception::new(
+                        ExceptionKind::EncodingError,
+                        "failed to encode frame",
+                    )
+                    .with_source(e))
+                }
+            }
+
+            let mut data = packet.data().unwrap_or_default().to_vec();
+            if self.aac_format() == Some(AacBitstreamFormat::Adts) {
+                let config = self.audio_specific_config()?;
+                let mut frame = config.adts_header(data.len())?.to_vec();
+                frame.append(&mut data);
+                data = frame;
+            }
+            let rate = self.config.sample_rate as i64;
+            let chunk = EncodedAudioChunk {
+                data: data.into(),
+                timestamp: packet.pts().unwrap_or(0) * 1_000_000 / rate,
+                duration: u64::try_from(packet.duration() * 1_000_000 / rate).ok(),
+                is_key: true,
+            };
+            let metadata = self.metadata();
+            self.ready.push_back((chunk, metadata));
+        }
+    }
+
+    /// Metadata for the next chunk, carrying the decoder config if it has not been reported.
+    fn metadata(&mut self) -> EncodedAudioChunkMetadata {
+        let decoder_config = AudioDecoderConfig {
+            codec: self.config.codec.clone(),
+            sample_rate: self.config.sample_rate,
+            number_of_channels: self.config.number_of_channels,
+            description: self.description(),
+        };
+        if self.active_output_config.as_ref() == Some(&decoder_config) {
+            return EncodedAudioChunkMetadata::default();
+        }
+        self.active_output_config = Some(decoder_config.clone());
+        EncodedAudioChunkMetadata {
+            decoder_config: Some(decoder_config),
+        }
+    }
+
+    /// The encoder's extradata, or for AAC and Opus encoders that have none, a generated
+    /// AudioSpecificConfig or OpusHead. The "adts" format carries it in-band instead.
+    fn description(&self) -> Option<Vec<u8>> {
+        if self.aac_format() == Some(AacBitstreamFormat::Adts) {
+            return None;
+        }
+        if let Some(extradata) = ffmpeg::extradata(&self.encoder) {
+            return Some(extradata);
+        }
+        match self.codec.id() {
+            Id::AAC => self
+                .audio_specific_config()
+                .ok()
+                .map(|config| config.to_bytes()),
+            Id::OPUS => u8::try_from(self.config.number_of_channels)
+                .ok()
+                .and_then(|channels| OpusHead::new(channels, self.config.sample_rate).ok())
+                .map(|head| head.to_bytes()),
+            _ => None,
+        }
+    }
+
+    /// The AudioSpecificConfig of an AAC encoder, from its extradata if it has any.
+    fn audio_specific_config(&self) -> Result<AudioSpecificConfig, Exception> {
+        match ffmpeg::extradata(&self.encoder) {
+            Some(extradata) => AudioSpecificConfig::parse(&extradata),
+            None => AudioSpecificConfig::new(
+                aac_object_type(&self.config.codec),
+                self.config.sample_rate,
+                self.config.number_of_channels,
+            ),
+        }
+    }
+
+    /// The bitstream format of AAC configs, `None` for other codecs.
+    fn aac_format(&self) -> Option<AacBitstreamFormat> {
+        (self.codec.id() == Id::AAC).then(|| {
+            self.config
+                .aac
+                .as_ref()
+                .map(|aac| aac.format)
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl CodecBackend for FfmpegCodec<AudioEncoderImpl> {
+    type Config = AudioEncoderConfig;
+    type Input = AudioData;
+    type Output = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
+    fn configure(&mut self, config: &AudioEncoderConfig) -> Result<(), Exception> {
+        self.open(AudioEncoderImpl::new(config)?);
+        Ok(())
+    }
+
+    fn send(&mut self, data: AudioData) -> Result<(), Exception> {
+        self.opened()?.encode(&data)
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        Ok(self.opened()?.receive_chunk())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.opened()?.drain()
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.opened()?.reset()
+    }
+}
+
+/// The audio object type of an `mp4a.40.` codec string, AAC-LC by default.
+fn aac_object_type(codec: &str) -> u8 {
+    codec
+        .strip_prefix("mp4a.40.")
+        .and_then(|object_type| object_type.parse().ok())
+        .unwrap_or(2)
+}
+
+/// Looks up an ffmpeg encoder by WebCodecs codec string or ffmpeg encoder name.
+pub fn find_audio_encoder(codec: &str) -> Option<ffmpeg_next::Codec> {
+    let name = match codec {
+        "opus" => "libopus",
+        "mp3" => "libmp3lame",
+        "vorbis" => "libvorbis",
+        codec if codec.starts_with("mp4a.") => "aac",
+        codec => codec,
+    };
+    ffmpeg_next::encoder::find_by_name(name)
+}
+
+fn open_audio_encoder(
+    codec: ffmpeg_next::Codec,
+    config: &AudioEncoderConfig,
+) -> Result<ffmpeg_next::encoder::Audio, Exception> {
+    let unsupported = |e: ffmpeg_next::Error| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("failed to open encoder for codec {:?}", config.codec),
+        )
+        .with_source(e)
+    };
+
+    // Prefer planar f32, which is what `AudioData` is converted to.
+    let formats: Vec<Sample> = codec
+        .audio()
+        .ok()
+        .and_then(|audio| audio.formats())
+        .map(|formats| formats.collect())
+        .unwrap_or_default();
+    let format = match formats.first() {
+        Some(first) if !formats.contains(&PLANAR_F32) => *first,
+        _ => PLANAR_F32,
+    };
+
+    let context = ffmpeg_next::codec::Context::new_with_codec(codec);
+    let mut encoder = context.encoder().audio().map_err(unsupported)?;
+    encoder.set_rate(config.sample_rate as i32);
+    encoder.set_channel_layout(ffmpeg_next::ChannelLayout::default(
+        config.number_of_channels as i32,
+    ));
+    encoder.set_format(format);
+    encoder.set_time_base((1, config.sample_rate as i32));
+    if let Some(bitrate) = config.bitrate {
+        encoder.set_bit_rate(bitrate as usize);
+    }
+    encoder
+        .open_as_with(codec, encoder_options(codec, config))
+        .map_err(unsupported)
+}
+
+/// The ffmpeg options for the codec specific parts of `config`.
+fn encoder_options(
+    codec: ffmpeg_next::Codec,
+    config: &AudioEncoderConfig,
+) -> ffmpeg_next::Dictionary<'static> {
+    let mut options = ffmpeg_next::Dictionary::new();
+    if let (Id::OPUS, Some(opus)) = (codec.id(), &config.opus) {
+        let application = match opus.application {
+            OpusApplication::Voip => "voip",
+            OpusApplication::Audio => "audio",
+            OpusApplication::Lowdelay => "lowdelay",
+        };
+        options.set("application", application);
+        // libopus takes the frame duration in milliseconds.
+        options.set(
+            "frame_duration",
+            &(opus.frame_duration as f64 / 1000.0).to_string(),
+        );
+        options.set("packet_loss", &opus.packetlossperc.to_string());
+        options.set("fec", if opus.useinbandfec { "1" } else { "0" });
+        options.set("dtx", if opus.usedtx { "1" } else { "0" });
+        if let Some(complexity) = opus.complexity {
+            options.set("compression_level", &complexity.to_string());
+        }
+    }
+    options
+}
@@ -0,0 +1,193 @@
+//! Codec backends: the implementations behind `AudioDecoder`, `AudioEncoder`,
+//! `VideoDecoder` and `VideoEncoder`.
+//!
+//! On configure, a codec asks the registered `BackendProvider`s in turn for a backend for
+//! its codec string and configures the first one it gets. Its work queue then sends every
+//! input to the backend and hands each output the backend has ready to the output callback.
+//!
+//! Registered by default are the PCM backend, which handles the PCM, u-law and a-law codec
+//! strings in every build, then the ffmpeg backend when the `ffmpeg` feature is enabled
+//! and the pure-Rust audio backend when the `pure-rust` feature is. Other backends, e.g.
+//! platform codecs or test mocks, are added with `register_backend`.
+
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use super::buffer_pool::BufferPool;
+
+use crate::{
+    codec::{
+        AudioDecoderConfig, AudioEncoderConfig, EncodedVideoChunk, EncodedVideoChunkMetadata,
+        Exception, ExceptionKind, VideoDecoderConfig, VideoEncoderConfig,
+        VideoEncoderEncodeOptions, VideoFrame,
+    },
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+};
+
+/// A codec implementation, driven by the work queue of one codec instance.
+pub trait CodecBackend: Send {
+    type Config;
+    type Input;
+    type Output;
+
+    /// Sets the backend up for `config`, before any input is sent.
+    fn configure(&mut self, config: &Self::Config) -> Result<(), Exception>;
+
+    /// Sends one input; the outputs it produces become available from `receive`.
+    fn send(&mut self, input: Self::Input) -> Result<(), Exception>;
+
+    /// The next output, or `None` until more input is sent or the backend is flushed.
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception>;
+
+    /// Marks the end of the input so far; every output still held becomes available from
+    /// `receive`.
+    fn flush(&mut self) -> Result<(), Exception>;
+
+    /// Discards everything the backend holds and readies it for new input, which starts
+    /// with a key chunk for decoders and is encoded from a key frame for encoders.
+    fn reset(&mut self) -> Result<(), Exception>;
+
+    /// Gives a decoder the pool to draw the buffers of its outputs from, before it is
+    /// configured. Backends that do not allocate their outputs ignore it.
+    fn set_buffer_pool(&mut self, _pool: BufferPool) {}
+}
+
+pub type AudioDecoderBackend =
+    dyn CodecBackend<Config = AudioDecoderConfig, Input = EncodedAudioChunk, Output = AudioData>;
+
+pub type AudioEncoderBackend = dyn CodecBackend<
+    Config = AudioEncoderConfig,
+    Input = AudioData,
+    Output = (EncodedAudioChunk, EncodedAudioChunkMetadata),
+>;
+
+pub type VideoDecoderBackend =
+    dyn CodecBackend<Config = VideoDecoderConfig, Input = EncodedVideoChunk, Output = VideoFrame>;
+
+pub type VideoEncoderBackend = dyn CodecBackend<
+    Config = VideoEncoderConfig,
+    Input = (VideoFrame, VideoEncoderEncodeOptions),
+    Output = (EncodedVideoChunk, EncodedVideoChunkMetadata),
+>;
+
+/// Creates backends for the codec strings it supports.
+///
+/// Every method returns an unconfigured backend, or `None` for codec strings the provider
+/// does not handle, in which case the next provider is asked.
+pub trait BackendProvider: Send + Sync {
+    /// A name for diagnostics, e.g. "ffmpeg".
+    fn name(&self) -> &str;
+
+    fn audio_decoder(&self, _codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        None
+    }
+
+    fn audio_encoder(&self, _codec: &str) -> Option<Box<AudioEncoderBackend>> {
+        None
+    }
+
+    fn video_decoder(&self, _codec: &str) -> Option<Box<VideoDecoderBackend>> {
+        None
+    }
+
+    fn video_encoder(&self, _codec: &str) -> Option<Box<VideoEncoderBackend>> {
+        None
+    }
+}
+
+fn providers() -> &'static RwLock<Vec<Arc<dyn BackendProvider>>> {
+    static PROVIDERS: OnceLock<RwLock<Vec<Arc<dyn BackendProvider>>>> = OnceLock::new();
+    PROVIDERS.get_or_init(|| {
+        let providers: Vec<Arc<dyn BackendProvider>> = vec![
+            Arc::new(super::pcm_backend::PcmBackend),
+            #[cfg(feature = "ffmpeg")]
+            Arc::new(super::ffmpeg_backend::FfmpegBackend),
+            #[cfg(feature = "pure-rust")]
+            Arc::new(super::rust_backend::RustBackend),
+        ];
+        RwLock::new(providers)
+    })
+}
+
+/// Registers `provider` ahead of every provider registered before it, so that it takes
+/// precedence for the codec strings it supports. Codecs configured afterwards use it.
+pub fn register_backend(provider: Arc<dyn BackendProvider>) {
+    providers()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(0, provider);
+}
+
+/// The names of the registered providers, in the order they are asked.
+pub fn backend_names() -> Vec<String> {
+    providers()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|provider| provider.name().to_string())
+        .collect()
+}
+
+/// Creates a backend for `codec` with the first provider that has one, and configures it.
+fn create<B: CodecBackend + ?Sized>(
+    codec: &str,
+    kind: &str,
+    config: &B::Config,
+    buffer_pool: Option<&BufferPool>,
+    make: impl Fn(&dyn BackendProvider) -> Option<Box<B>>,
+) -> Result<Box<B>, Exception> {
+    // Providers are not called under the lock, so they may register others.
+    let providers = providers()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let mut backend = providers
+        .iter()
+        .find_map(|provider| make(provider.as_ref()))
+        .ok_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no {kind} found for codec {codec:?}"),
+            )
+        })?;
+    if let Some(pool) = buffer_pool {
+        backend.set_buffer_pool(pool.clone());
+    }
+    backend.configure(config)?;
+    Ok(backend)
+}
+
+/// Creates an audio decoder, which draws its outputs from `buffer_pool` if there is one.
+pub fn create_audio_decoder(
+    config: &AudioDecoderConfig,
+    buffer_pool: Option<&BufferPool>,
+) -> Result<Box<AudioDecoderBackend>, Exception> {
+    create(&config.codec, "decoder", config, buffer_pool, |provider| {
+        provider.audio_decoder(&config.codec)
+    })
+}
+
+pub fn create_audio_encoder(
+    config: &AudioEncoderConfig,
+) -> Result<Box<AudioEncoderBackend>, Exception> {
+    create(&config.codec, "encoder", config, None, |provider| {
+        provider.audio_encoder(&config.codec)
+    })
+}
+
+/// Creates a video decoder, which draws its outputs from `buffer_pool` if there is one.
+pub fn create_video_decoder(
+    config: &VideoDecoderConfig,
+    buffer_pool: Option<&BufferPool>,
+) -> Result<Box<VideoDecoderBackend>, Exception> {
+    create(&config.codec, "decoder", config, buffer_pool, |provider| {
+        provider.video_decoder(&config.codec)
+    })
+}
+
+pub fn create_video_encoder(
+    config: &VideoEncoderConfig,
+) -> Result<Box<VideoEncoderBackend>, Exception> {
+    create(&config.codec, "encoder", config, None, |provider| {
+        provider.video_encoder(&config.codec)
+    })
+}
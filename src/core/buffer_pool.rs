@@ -0,0 +1,202 @@
+//! A pool of output buffers for decoders, which bounds the frames a decoder has outstanding
+//! and reuses their memory once they are dropped.
+//!
+//! A decoder given a pool with `AudioDecoder::set_buffer_pool` or
+//! `VideoDecoder::set_buffer_pool` draws the buffer of every `AudioData` or `VideoFrame` it
+//! outputs from the pool. The buffer goes back to the pool when the last clone of the output
+//! is dropped or closed, and the next output reuses its allocation. Once the pool's
+//! `max_outstanding` buffers are all in use, the decoder either fails with
+//! `QuotaExceededError` or waits for one to be released, like browsers that stop decoding
+//! while the application holds on to too many frames.
+
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    codec::{Exception, ExceptionKind},
+    data::buffer::SharedBuffer,
+};
+
+/// What a decoder does when every buffer of its pool is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PoolExhaustion {
+    /// Fail with `QuotaExceededError`, which closes the decoder.
+    #[default]
+    Fail,
+    /// Wait until an output is dropped or closed. Decoding stalls meanwhile, as do `reset`
+    /// and `close`, so outputs must be released from another thread than the one waiting.
+    Block,
+}
+
+/// Output buffers shared by the decoders given the pool; clones refer to the same pool.
+#[derive(Clone)]
+pub struct BufferPool {
+    shared: Arc<PoolShared>,
+}
+
+struct PoolShared {
+    max_outstanding: usize,
+    exhaustion: PoolExhaustion,
+    state: Mutex<PoolState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// Buffers handed out and not released yet.
+    outstanding: usize,
+    /// Released buffers kept for reuse, at most `max_outstanding - outstanding` of them.
+    idle: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// A pool of at most `max_outstanding` buffers in use at a time, at least one.
+    pub fn new(max_outstanding: usize, exhaustion: PoolExhaustion) -> Self {
+        Self {
+            shared: Arc::new(PoolShared {
+                max_outstanding: max_outstanding.max(1),
+                exhaustion,
+                state: Mutex::new(PoolState::default()),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn max_outstanding(&self) -> usize {
+        self.shared.max_outstanding
+    }
+
+    pub fn exhaustion(&self) -> PoolExhaustion {
+        self.shared.exhaustion
+    }
+
+    /// The number of buffers in use, by outputs or by decoders filling them.
+    pub fn outstanding(&self) -> usize {
+        self.shared.lock().outstanding
+    }
+
+    /// The number of released buffers kept for reuse.
+    pub fn idle(&self) -> usize {
+        self.shared.lock().idle.len()
+    }
+
+    /// An empty buffer with room for at least `capacity` bytes, reusing a released one if
+    /// there is any.
+    ///
+    /// When `max_outstanding` buffers are in use, fails with `QuotaExceededError` or waits
+    /// for one to be released, depending on the pool's `PoolExhaustion`.
+    pub fn acquire(&self, capacity: usize) -> Result<PooledBuffer, Exception> {
+        let mut state = self.shared.lock();
+        while state.outstanding >= self.shared.max_outstanding {
+            match self.shared.exhaustion {
+                PoolExhaustion::Fail => {
+                    return Err(Exception::new(
+                        ExceptionKind::QuotaExceededError,
+                        format!(
+                            "all {} buffers of the pool are in use",
+                            self.shared.max_outstanding
+                        ),
+                    ))
+                }
+                PoolExhaustion::Block => {
+                    state = self
+                        .shared
+                        .released
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+        }
+        state.outstanding += 1;
+        // A buffer that is large enough saves a reallocation.
+        let index = state
+            .idle
+            .iter()
+            .position(|data| data.capacity() >= capacity)
+            .or(state.idle.len().checked_sub(1));
+        let mut data = index.map_or_else(Vec::new, |index| state.idle.swap_remove(index));
+        drop(state);
+        data.reserve(capacity);
+        Ok(PooledBuffer(Lease {
+            data,
+            pool: Some(self.shared.clone()),
+        }))
+    }
+}
+
+impl PoolShared {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn release(&self, mut data: Vec<u8>) {
+        let mut state = self.lock();
+        state.outstanding -= 1;
+        data.clear();
+        state.idle.push(data);
+        drop(state);
+        self.released.notify_one();
+    }
+}
+
+/// A buffer for a decoder to fill, from a pool or allocated on its own for decoders without
+/// one. `freeze` turns it into the `SharedBuffer` of an output.
+pub struct PooledBuffer(Lease);
+
+/// A buffer that goes back to its pool when dropped.
+struct Lease {
+    data: Vec<u8>,
+    pool: Option<Arc<PoolShared>>,
+}
+
+impl PooledBuffer {
+    /// An empty buffer with room for `capacity` bytes, from `pool` if there is one.
+    pub fn acquire(pool: Option<&BufferPool>, capacity: usize) -> Result<Self, Exception> {
+        match pool {
+            Some(pool) => pool.acquire(capacity),
+            None => Ok(Self(Lease {
+                data: Vec::with_capacity(capacity),
+                pool: None,
+            })),
+        }
+    }
+
+    /// The filled buffer, which returns to the pool once every reference to it is dropped.
+    pub fn freeze(mut self) -> SharedBuffer {
+        if self.0.pool.is_none() {
+            return mem::take(&mut self.0.data).into();
+        }
+        SharedBuffer::from_owner(self.0)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0.data
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0.data
+    }
+}
+
+impl AsRef<[u8]> for Lease {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release(mem::take(&mut self.data));
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use crate::{
-    codec::{AudioConfigMessage, ConfigMessage, Exception},
-    core::work_queue::WorkQueue,
-    data::audio_data::{AudioData, EncodedAudioChunk},
+    codec::{Exception, ExceptionKind, State},
+    core::{
+        backend::CodecBackend, internal_slots::CodecInternalSlots, promise::Promise,
+        queue_size::QueueSize, work_queue::MAX_WORKERS,
+    },
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, ThreadId},
 };
-use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub enum Outcome {
@@ -11,206 +17,396 @@ pub enum Outcome {
     NotProcessed,
 }
 
-pub trait ControlMessageTrait {
+pub trait ControlMessageTrait: Send {
     fn process(&mut self) -> Outcome;
-}
 
-pub enum ControlMessage {
-    Config(ConfigMessage),
-    Decode(DecodeMessage),
-    Encode(EncodeMessage),
-    Flush(FlushMessage),
+    /// Drops the message unprocessed because of a reset or close with `reason`.
+    fn abort(&mut self, _reason: &Exception) {}
 }
 
-pub enum DecodeMessage {
-    AudioDecode(AudioDecodeMessage),
-    //TODO:
-    // VideoDecode(VideoDecodeMessage)
+/// The parts of a codec instance that its control messages, and the jobs they enqueue, work
+/// on. Cloning yields a handle to the same codec.
+pub struct CodecHandle<B: CodecBackend + ?Sized> {
+    pub internal_slots: CodecInternalSlots,
+    pub codec_impl: Arc<Mutex<Option<Box<B>>>>,
+    pub output_callback: Arc<dyn Fn(B::Output) + Send + Sync>,
+    pub error_callback: Arc<dyn Fn(Exception) + Send + Sync>,
+    /// The kind of error a panic in a job is reported as: `DecodeError` for decoders and
+    /// `EncodingError` for encoders.
+    pub panic_kind: ExceptionKind,
+    /// Invoked, after the last output, by every flush that completes.
+    flush_callback: Option<Arc<dyn Fn() + Send + Sync>>,
+    delivery: Arc<Delivery>,
 }
 
-pub enum EncodeMessage {
-    AudioEncode(AudioEncodeMessage),
+impl<B: CodecBackend + ?Sized> CodecHandle<B> {
+    pub fn new(
+        output_callback: impl Fn(B::Output) + Send + Sync + 'static,
+        error_callback: impl Fn(Exception) + Send + Sync + 'static,
+        panic_kind: ExceptionKind,
+    ) -> Self {
+        Self {
+            internal_slots: CodecInternalSlots::new(MAX_WORKERS),
+            codec_impl: Arc::new(Mutex::new(None)),
+            output_callback: Arc::new(output_callback),
+            error_callback: Arc::new(error_callback),
+            panic_kind,
+            flush_callback: None,
+            delivery: Arc::default(),
+        }
+    }
+
+    /// Sets the callback invoked, after the last output, by every flush that completes.
+    /// Messages enqueued before keep the previous one.
+    pub fn set_flush_callback(&mut self, flush_callback: impl Fn() + Send + Sync + 'static) {
+        self.flush_callback = Some(Arc::new(flush_callback));
+    }
+
+    /// Enqueues a control message of this codec and processes the control message queue.
+    pub fn enqueue(&self, kind: ControlMessageKind<B>)
+    where
+        B: 'static,
+        B::Input: Send + 'static,
+    {
+        self.internal_slots
+            .enqueue_control_message(ControlMessage::new(self, kind));
+    }
+
+    /// Aborts all pending work with `reason` and releases the backend.
+    ///
+    /// Waits for a running job to finish; any job after it is stale and does nothing, so no
+    /// callback fires once this returns. Called from a callback, the job that runs it stops
+    /// before its next callback instead.
+    pub fn abort(&self, reason: &Exception) {
+        self.internal_slots.abort(reason);
+        *lock_codec(&self.codec_impl) = None;
+        self.delivery.wait();
+    }
 }
 
-pub enum FlushMessage {
-    AudioFlush(AudioFlushMessage),
-    //TODO:
-    // VideoDecode(VideoDecodeMessage)
+impl<B: CodecBackend + ?Sized> Clone for CodecHandle<B> {
+    fn clone(&self) -> Self {
+        Self {
+            internal_slots: self.internal_slots.clone(),
+            codec_impl: self.codec_impl.clone(),
+            output_callback: self.output_callback.clone(),
+            error_callback: self.error_callback.clone(),
+            panic_kind: self.panic_kind,
+            flush_callback: self.flush_callback.clone(),
+            delivery: self.delivery.clone(),
+        }
+    }
 }
 
-pub struct AudioDecodeMessage {
-    pub chunk: EncodedAudioChunk,
-    pub work_queue: Arc<WorkQueue>,
-    pub output_callback: Arc<dyn Fn(AudioData) + Send + Sync>,
-    pub error_callback: Arc<dyn Fn(Exception) + Send + Sync>,
-    pub codec_impl: Arc<Mutex<Option<ffmpeg_next::decoder::Audio>>>,
+/// Creates the backend of a configure message. Runs on the work queue.
+pub type BackendFactory<B> = Box<dyn FnOnce() -> Result<Box<B>, Exception> + Send>;
+
+pub enum ControlMessageKind<B: CodecBackend + ?Sized> {
+    /// Drains the current backend, if any, and replaces it with the one the factory creates.
+    Configure(BackendFactory<B>),
+    /// Sends an input to the backend. `queue_size` is the decode or encode queue size that
+    /// counts it.
+    Send {
+        input: B::Input,
+        queue_size: Arc<QueueSize>,
+    },
+    /// Drains the backend and settles the promise once every output has been emitted.
+    Flush(Promise<()>),
 }
 
-pub struct AudioEncodeMessage {
-    pub data: String,
-    pub work_queue: Arc<WorkQueue>,
+/// A control message of a decoder or encoder whose backend is a `B`.
+pub struct ControlMessage<B: CodecBackend + ?Sized> {
+    codec: CodecHandle<B>,
+    /// Taken when the message is processed.
+    kind: Option<ControlMessageKind<B>>,
 }
 
-pub struct AudioFlushMessage {
-    pub work_queue: Arc<WorkQueue>,
-    pub output_callback: Arc<dyn Fn(AudioData) + Send + Sync>,
-    pub error_callback: Arc<dyn Fn(Exception) + Send + Sync>,
-    pub codec_impl: Arc<Mutex<Option<ffmpeg_next::decoder::Audio>>>,
+impl<B: CodecBackend + ?Sized> ControlMessage<B> {
+    pub fn new(codec: &CodecHandle<B>, kind: ControlMessageKind<B>) -> Self {
+        Self {
+            codec: codec.clone(),
+            kind: Some(kind),
+        }
+    }
 }
 
-impl ControlMessageTrait for AudioConfigMessage {
+impl<B> ControlMessageTrait for ControlMessage<B>
+where
+    B: CodecBackend + ?Sized + 'static,
+    B::Input: Send + 'static,
+{
     fn process(&mut self) -> Outcome {
-        let config = self.config.clone();
-        let work_queue = self.work_queue.clone();
-        let error_callback = self.error_callback.clone();
-        let codec_impl = self.codec_impl.clone();
-
-        work_queue.enqueue(Box::new(move || {
-            if ffmpeg_next::init().is_err() {
-                error_callback(Exception::InternalError);
-                return;
-            }
+        let internal_slots = &self.codec.internal_slots;
+        // Everything but a configure needs the backend a preceding configure installs.
+        let is_configure = matches!(self.kind, Some(ControlMessageKind::Configure(_)));
+        if !is_configure && !internal_slots.is_backend_ready() {
+            return Outcome::NotProcessed;
+        }
+        let Some(kind) = self.kind.take() else {
+            return Outcome::Processed;
+        };
+        let codec = self.codec.clone();
+        let epoch = internal_slots.epoch();
 
-            let codec = match ffmpeg_next::codec::decoder::find_by_name(&config.codec) {
-                Some(codec) => codec,
-                None => {
-                    error_callback(Exception::NotSupportedError);
-                    return;
-                }
-            };
-
-            let context = ffmpeg_next::codec::Context::new_with_codec(codec);
-            let decoder = match context.decoder().audio() {
-                Ok(decoder) => decoder,
-                Err(e) => {
-                    eprintln!("{e}");
-                    error_callback(Exception::NotSupportedError);
-                    return;
-                }
-            };
-
-            {
-                let mut dec_lock = match codec_impl.lock() {
-                    Ok(lock) => lock,
-                    Err(_) => {
-                        error_callback(Exception::InternalError);
-                        return;
-                    }
-                };
-                *dec_lock = Some(decoder);
+        let job: Box<dyn FnOnce() + Send> = match kind {
+            ControlMessageKind::Configure(create) => {
+                // Hold back subsequent messages until the backend has been created.
+                internal_slots.block();
+                Box::new(move || configure(&codec, epoch, create))
+            }
+            ControlMessageKind::Send { input, queue_size } => {
+                Box::new(move || send(&codec, epoch, input, &queue_size))
             }
-        }));
+            // Runs behind every job enqueued before it, so draining emits the last outputs.
+            ControlMessageKind::Flush(promise) => Box::new(move || flush(&codec, epoch, promise)),
+        };
+        internal_slots.work_queue.enqueue(job);
         Outcome::Processed
     }
+
+    fn abort(&mut self, reason: &Exception) {
+        if let Some(ControlMessageKind::Flush(promise)) = self.kind.take() {
+            promise.reject(reason.clone());
+        }
+    }
 }
 
-impl ControlMessageTrait for AudioDecodeMessage {
-    fn process(&mut self) -> Outcome {
-        let chunk = self.chunk.clone();
-        let work_queue = self.work_queue.clone();
-        let output_callback = self.output_callback.clone();
-        let error_callback = self.error_callback.clone();
-        let codec_impl = self.codec_impl.clone();
-
-        work_queue.enqueue(Box::new(move || {
-            let mut decoder_lock = codec_impl.lock().unwrap();
-            if let Some(decoder) = decoder_lock.as_mut() {
-                let mut packet = ffmpeg_next::Packet::new(chunk.data.len());
-                let chunk = chunk.clone();
-                if let Some(data) = packet.data_mut() {
-                    data.copy_from_slice(&chunk.data);
-                } else {
-                    eprintln!("Warn: packet.data_mut() is None ");
-                }
-
-                if let Err(e) = decoder.send_packet(&packet) {
-                    eprintln!("Error sending packet: {:?}", e);
-                    error_callback(Exception::DecodeError);
-                    return;
-                }
-                decode_audio_frames(decoder, chunk.timestamp as f64, output_callback);
-            } else {
-                error_callback(Exception::InvalidStateError);
+fn configure<B: CodecBackend + ?Sized>(
+    codec: &CodecHandle<B>,
+    epoch: u64,
+    create: BackendFactory<B>,
+) {
+    let internal_slots = &codec.internal_slots;
+    // On reconfigure, the outputs still pending for the previous config go out first. A new
+    // encoder reports its decoder config with its first chunk.
+    let Some((outputs, created)) = run_backend(codec, epoch, |backend, outputs| {
+        if let Some(backend) = backend.as_deref_mut() {
+            drain(backend, outputs)?;
+        }
+        *backend = Some(create()?);
+        Ok(())
+    }) else {
+        // A reset or close since this configure already unblocked the queue.
+        return;
+    };
+    match deliver(codec, epoch, outputs).and(created) {
+        Ok(()) => internal_slots.set_backend_ready(epoch),
+        Err(e) => close_codec(codec, epoch, e),
+    }
+    if internal_slots.is_current(epoch) {
+        internal_slots.unblock();
+    }
+}
+
+fn send<B: CodecBackend + ?Sized>(
+    codec: &CodecHandle<B>,
+    epoch: u64,
+    input: B::Input,
+    queue_size: &QueueSize,
+) {
+    let Some((outputs, sent)) = run_backend(codec, epoch, |backend, outputs| {
+        match backend.as_deref_mut() {
+            Some(backend) => send_and_collect(backend, input, outputs),
+            None => Err(not_configured()),
+        }
+    }) else {
+        return;
+    };
+    match deliver(codec, epoch, outputs).and(sent) {
+        Ok(()) => queue_size.decrement(),
+        Err(e) => {
+            queue_size.clear();
+            close_codec(codec, epoch, e);
+        }
+    }
+}
+
+fn flush<B: CodecBackend + ?Sized>(codec: &CodecHandle<B>, epoch: u64, promise: Promise<()>) {
+    let Some((outputs, flushed)) = run_backend(codec, epoch, |backend, outputs| {
+        match backend.as_deref_mut() {
+            Some(backend) => drain(backend, outputs),
+            None => Err(not_configured()),
+        }
+    }) else {
+        promise.reject(aborted_flush());
+        return;
+    };
+    match deliver(codec, epoch, outputs).and(flushed) {
+        // An output callback that reset or closed the codec aborted this flush too.
+        Ok(()) if !codec.internal_slots.is_current(epoch) => promise.reject(aborted_flush()),
+        Ok(()) => {
+            if let Some(flush_callback) = &codec.flush_callback {
+                let _delivering = codec.delivery.begin();
+                flush_callback();
             }
-        }));
-        Outcome::Processed
+            promise.resolve(());
+        }
+        Err(e) => {
+            promise.reject(e.clone());
+            close_codec(codec, epoch, e);
+        }
     }
 }
 
-impl ControlMessageTrait for AudioEncodeMessage {
-    fn process(&mut self) -> Outcome {
-        let data = self.data.clone();
-        let work_queue = self.work_queue.clone();
-        work_queue.enqueue(Box::new(move || todo!()));
-        Outcome::Processed
+/// The outputs the backend calls of a job collected, with their result.
+type Collected<O> = (Vec<O>, Result<(), Exception>);
+
+/// Runs the backend calls of a job under the codec lock and returns the outputs they
+/// collected, with their result. Returns `None` without calling `f` if the job is stale.
+fn run_backend<B: CodecBackend + ?Sized>(
+    codec: &CodecHandle<B>,
+    epoch: u64,
+    f: impl FnOnce(&mut Option<Box<B>>, &mut Vec<B::Output>) -> Result<(), Exception>,
+) -> Option<Collected<B::Output>> {
+    let mut backend = lock_codec(&codec.codec_impl);
+    if !codec.internal_slots.is_current(epoch) {
+        return None;
     }
+    let mut outputs = Vec::new();
+    let result = catch_panic(codec.panic_kind, || f(&mut backend, &mut outputs));
+    Some((outputs, result))
 }
 
-impl ControlMessageTrait for AudioFlushMessage {
-    fn process(&mut self) -> Outcome {
-        let mut dec_lock = self.codec_impl.lock().unwrap();
-        if let Some(decoder) = dec_lock.as_mut() {
-            decoder
-                .send_eof()
-                .map_err(|_| Exception::DecodeError)
-                .unwrap();
+/// Hands `outputs` to the output callback once the codec lock is released, so that the
+/// callback may reset or close the codec. Outputs after such a reset or close are dropped.
+fn deliver<B: CodecBackend + ?Sized>(
+    codec: &CodecHandle<B>,
+    epoch: u64,
+    outputs: Vec<B::Output>,
+) -> Result<(), Exception> {
+    let _delivering = codec.delivery.begin();
+    catch_panic(codec.panic_kind, || {
+        for output in outputs {
+            if !codec.internal_slots.is_current(epoch) {
+                break;
+            }
+            (codec.output_callback)(output);
         }
-        // TODO: Draining not implemented yet. Need to figure out how to get timestamps for
-        // these remaining frames
-        let mut decoder_lock = self.codec_impl.lock().unwrap();
-        // if let Some(decoder) = decoder_lock.as_mut() {
-        // decode_audio_frames(decoder, timestamp, self.output_callback);
-        // }
-        Outcome::Processed
+        Ok(())
+    })
+}
+
+/// Runs the backend calls of a job, or the output callback, turning a panic in them into an
+/// error of `kind`, so that it closes the codec and rejects its pending flushes like any
+/// other failure.
+fn catch_panic<R>(
+    kind: ExceptionKind,
+    f: impl FnOnce() -> Result<R, Exception>,
+) -> Result<R, Exception> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("no message");
+        Err(Exception::new(kind, format!("codec panicked: {message}")))
+    })
+}
+
+/// Sends `input` to the backend and collects every output it has ready.
+fn send_and_collect<B: CodecBackend + ?Sized>(
+    backend: &mut B,
+    input: B::Input,
+    outputs: &mut Vec<B::Output>,
+) -> Result<(), Exception> {
+    backend.send(input)?;
+    collect_outputs(backend, outputs)
+}
+
+/// Flushes the backend, collects its remaining outputs and readies it for new input.
+fn drain<B: CodecBackend + ?Sized>(
+    backend: &mut B,
+    outputs: &mut Vec<B::Output>,
+) -> Result<(), Exception> {
+    backend.flush()?;
+    collect_outputs(backend, outputs)?;
+    backend.reset()
+}
+
+fn collect_outputs<B: CodecBackend + ?Sized>(
+    backend: &mut B,
+    outputs: &mut Vec<B::Output>,
+) -> Result<(), Exception> {
+    while let Some(output) = backend.receive()? {
+        outputs.push(output);
     }
+    Ok(())
 }
 
-fn decode_audio_frames(
-    decoder: &mut ffmpeg_next::decoder::Audio,
-    timestamp: f64,
-    output_callback: Arc<dyn Fn(AudioData) + Send + Sync>,
-) {
-    let mut frame = ffmpeg_next::frame::Audio::empty();
-    while decoder.receive_frame(&mut frame).is_ok() {
-        let sample_format = frame.format();
-        let target_format =
-            ffmpeg_next::format::Sample::F32(ffmpeg_next::format::sample::Type::Planar);
-        let converted_frame = if sample_format != target_format {
-            let mut resampler = ffmpeg_next::software::resampling::Context::get(
-                sample_format,
-                frame.channel_layout(),
-                frame.rate(),
-                target_format,
-                frame.channel_layout(),
-                frame.rate(),
-            )
-            .unwrap();
-            let mut cf = ffmpeg_next::frame::Audio::empty();
-            resampler.run(&frame, &mut cf).unwrap();
-            cf
-        } else {
-            frame.clone()
-        };
+/// The error of a job that finds no backend, which only happens after the codec was closed.
+fn not_configured() -> Exception {
+    Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+}
+
+/// Locks the codec implementation for a job.
+///
+/// A poisoned lock is recovered rather than propagated; the next failure of the codec closes
+/// it anyway.
+fn lock_codec<T>(codec_impl: &Mutex<Option<T>>) -> MutexGuard<'_, Option<T>> {
+    codec_impl.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Closes the codec from a job of `epoch`, as in the "Close AudioDecoder" algorithm: pending
+/// control messages are dropped, queued jobs become stale, the codec implementation is
+/// released and the error callback is invoked with `err`, outside the codec lock.
+///
+/// Does nothing if a callback has reset or closed the codec since.
+fn close_codec<B: CodecBackend + ?Sized>(codec: &CodecHandle<B>, epoch: u64, err: Exception) {
+    {
+        let mut backend = lock_codec(&codec.codec_impl);
+        if !codec.internal_slots.is_current(epoch) {
+            return;
+        }
+        codec.internal_slots.set_state(State::Closed);
+        codec.internal_slots.abort(&err);
+        *backend = None;
+    }
+    let _delivering = codec.delivery.begin();
+    (codec.error_callback)(err);
+}
 
-        let num_frames = converted_frame.samples() as u16;
-        let sample_rate = converted_frame.rate();
-        let channels = converted_frame.channels();
-        let bytes_per_sample = std::mem::size_of::<f32>() as u16;
-        let mut audio_buffer =
-            Vec::with_capacity((num_frames * channels * bytes_per_sample).into());
+/// The thread, if any, that is running the callbacks of a job. Jobs run callbacks without
+/// the codec lock, so an abort waits for them here instead.
+#[derive(Default)]
+struct Delivery {
+    thread: Mutex<Option<ThreadId>>,
+    finished: Condvar,
+}
+
+impl Delivery {
+    fn begin(&self) -> DeliveryGuard<'_> {
+        *self.thread.lock().unwrap_or_else(PoisonError::into_inner) = Some(thread::current().id());
+        DeliveryGuard(self)
+    }
 
-        for ch in 0..channels {
-            let plane = converted_frame.data(ch.into());
-            audio_buffer.extend_from_slice(plane);
+    /// Waits until no other thread is running callbacks. A callback that resets or closes
+    /// its own codec does not wait for itself; the job stops delivering once it returns.
+    fn wait(&self) {
+        let current = thread::current().id();
+        let mut thread = self.thread.lock().unwrap_or_else(PoisonError::into_inner);
+        while thread.is_some_and(|id| id != current) {
+            thread = self
+                .finished
+                .wait(thread)
+                .unwrap_or_else(PoisonError::into_inner);
         }
-        let audio_data = AudioData::new(
-            "f32-planar".to_string(),
-            sample_rate as f64,
-            channels as u32,
-            num_frames as u32,
-            timestamp,
-            audio_buffer,
-        );
-        output_callback(audio_data);
     }
 }
+
+/// Ends a delivery when dropped, also when a callback panics.
+struct DeliveryGuard<'a>(&'a Delivery);
+
+impl Drop for DeliveryGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.thread.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.0.finished.notify_all();
+    }
+}
+
+/// The reason a flush job made stale by a reset or close is rejected with.
+fn aborted_flush() -> Exception {
+    Exception::new(
+        ExceptionKind::AbortError,
+        "flush was aborted by reset or close",
+    )
+}
@@ -0,0 +1,300 @@
+//! Accessors for the parts of ffmpeg's codec structs that `ffmpeg_next` does not wrap.
+
+use std::{ffi::CString, ops::Deref, ptr, slice};
+
+use ffmpeg_next::{
+    codec::{Context, Id, Parameters},
+    ffi,
+    format::context::Output,
+    media, Rational, Rescale,
+};
+
+/// Time base of all WebCodecs timestamps and durations.
+pub const MICROSECONDS: Rational = Rational(1, 1_000_000);
+
+/// Converts `ts` in `time_base` units to microseconds.
+pub fn to_microseconds(ts: i64, time_base: Rational) -> i64 {
+    ts.rescale(time_base, MICROSECONDS)
+}
+
+/// Converts `us` microseconds to `time_base` units.
+pub fn from_microseconds(us: i64, time_base: Rational) -> i64 {
+    us.rescale(MICROSECONDS, time_base)
+}
+
+/// The codec private data of an opened encoder or decoder.
+pub fn extradata(context: &Context) -> Option<Vec<u8>> {
+    // SAFETY: `extradata` is either null or points to `extradata_size` bytes owned by the
+    // context.
+    unsafe {
+        let context = context.as_ptr();
+        copy_bytes((*context).extradata, (*context).extradata_size)
+    }
+}
+
+/// The codec private data of a demuxed stream.
+pub fn parameters_extradata(parameters: &Parameters) -> Option<Vec<u8>> {
+    // SAFETY: as in `extradata`.
+    unsafe {
+        let parameters = parameters.as_ptr();
+        copy_bytes((*parameters).extradata, (*parameters).extradata_size)
+    }
+}
+
+/// Copies `size` bytes at `data`, which may be null.
+unsafe fn copy_bytes(data: *const u8, size: i32) -> Option<Vec<u8>> {
+    if data.is_null() || size <= 0 {
+        return None;
+    }
+    Some(slice::from_raw_parts(data, size as usize).to_vec())
+}
+
+/// Sets the codec private data of a context that has not been opened yet.
+pub fn set_extradata(context: &mut Context, data: &[u8]) {
+    // SAFETY: the previous buffer belongs to the context, the new one is freed by it.
+    unsafe {
+        let context = context.as_mut_ptr();
+        ffi::av_freep(ptr::addr_of_mut!((*context).extradata).cast());
+        ((*context).extradata, (*context).extradata_size) = alloc_extradata(data);
+    }
+}
+
+/// Copies `data` into a buffer with the padding ffmpeg requires of codec private data.
+unsafe fn alloc_extradata(data: &[u8]) -> (*mut u8, i32) {
+    let buffer =
+        ffi::av_mallocz(data.len() + ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+    if buffer.is_null() {
+        return (buffer, 0);
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
+    (buffer, data.len() as i32)
+}
+
+/// ffmpeg's `FF_QP2LAMBDA`, the scale of frame qualities.
+const QP2LAMBDA: i32 = 118;
+
+/// Sets the quantizer of a frame for an encoder opened with `Flags::QSCALE`.
+pub fn set_frame_quantizer(frame: &mut ffmpeg_next::frame::Video, quantizer: u32) {
+    // SAFETY: a plain field write on a frame we own.
+    unsafe {
+        (*frame.as_mut_ptr()).quality = quantizer as i32 * QP2LAMBDA;
+    }
+}
+
+/// Sets a private option of an opened codec, for the options its wrapper rereads before
+/// every frame, such as the `qp` of libx264.
+pub fn set_private_option(
+    context: &mut Context,
+    name: &str,
+    value: i64,
+) -> Result<(), ffmpeg_next::Error> {
+    let name = CString::new(name).map_err(|_| ffmpeg_next::Error::OptionNotFound)?;
+    // SAFETY: `priv_data` is the options object of the codec, owned by the context.
+    let ret =
+        unsafe { ffi::av_opt_set_int((*context.as_mut_ptr()).priv_data, name.as_ptr(), value, 0) };
+    if ret < 0 {
+        return Err(ffmpeg_next::Error::from(ret));
+    }
+    Ok(())
+}
+
+/// Sets the sample rate and a default layout for `channels` on a decoder context that has
+/// not been opened yet, for codecs whose bitstream does not carry them (e.g. PCM).
+pub fn set_audio_parameters(context: &mut Context, sample_rate: u32, channels: u32) {
+    // SAFETY: plain field writes on a context that has not been opened yet.
+    unsafe {
+        let context = context.as_mut_ptr();
+        (*context).sample_rate = sample_rate as i32;
+        ffi::av_channel_layout_uninit(&mut (*context).ch_layout);
+        ffi::av_channel_layout_default(&mut (*context).ch_layout, channels as i32);
+    }
+}
+
+/// The codec profile and level of a demuxed stream, negative when unknown.
+pub fn profile_and_level(parameters: &Parameters) -> (i32, i32) {
+    // SAFETY: plain field reads.
+    unsafe {
+        let parameters = parameters.as_ptr();
+        ((*parameters).profile, (*parameters).level)
+    }
+}
+
+/// The sample rate and channel count of a demuxed audio stream.
+pub fn audio_parameters(parameters: &Parameters) -> (u32, u32) {
+    // SAFETY: plain field reads.
+    unsafe {
+        let parameters = parameters.as_ptr();
+        (
+            (*parameters).sample_rate.max(0) as u32,
+            (*parameters).ch_layout.nb_channels.max(0) as u32,
+        )
+    }
+}
+
+/// The coded size of a demuxed video stream.
+pub fn video_size(parameters: &Parameters) -> (u32, u32) {
+    // SAFETY: plain field reads.
+    unsafe {
+        let parameters = parameters.as_ptr();
+        (
+            (*parameters).width.max(0) as u32,
+            (*parameters).height.max(0) as u32,
+        )
+    }
+}
+
+/// Codec parameters describing an audio stream to mux.
+pub fn audio_stream_parameters(
+    id: Id,
+    sample_rate: u32,
+    channels: u32,
+    extradata: Option<&[u8]>,
+) -> Parameters {
+    let mut parameters = Parameters::new();
+    // SAFETY: plain field writes on freshly allocated parameters, which free the extradata.
+    unsafe {
+        let raw = parameters.as_mut_ptr();
+        (*raw).codec_type = media::Type::Audio.into();
+        (*raw).codec_id = id.into();
+        (*raw).sample_rate = sample_rate as i32;
+        ffi::av_channel_layout_default(&mut (*raw).ch_layout, channels as i32);
+        if let Some(data) = extradata {
+            ((*raw).extradata, (*raw).extradata_size) = alloc_extradata(data);
+        }
+    }
+    parameters
+}
+
+/// Codec parameters describing a video stream to mux.
+pub fn video_stream_parameters(
+    id: Id,
+    width: u32,
+    height: u32,
+    extradata: Option<&[u8]>,
+) -> Parameters {
+    let mut parameters = Parameters::new();
+    // SAFETY: as in `audio_stream_parameters`.
+    unsafe {
+        let raw = parameters.as_mut_ptr();
+        (*raw).codec_type = media::Type::Video.into();
+        (*raw).codec_id = id.into();
+        (*raw).width = width as i32;
+        (*raw).height = height as i32;
+        if let Some(data) = extradata {
+            ((*raw).extradata, (*raw).extradata_size) = alloc_extradata(data);
+        }
+    }
+    parameters
+}
+
+/// An output context for `format` that writes nowhere until `open_memory_output` is called.
+pub fn memory_output(format: &str) -> Result<Output, ffmpeg_next::Error> {
+    let format = CString::new(format).map_err(|_| ffmpeg_next::Error::InvalidData)?;
+    let mut context = ptr::null_mut();
+    // SAFETY: on success `context` is a new output context owned by the returned `Output`,
+    // whose destructor accepts the null I/O context.
+    unsafe {
+        match ffi::avformat_alloc_output_context2(
+            &mut context,
+            ptr::null_mut(),
+            format.as_ptr(),
+            ptr::null(),
+        ) {
+            0 => Ok(Output::wrap(context)),
+            e => Err(ffmpeg_next::Error::from(e)),
+        }
+    }
+}
+
+/// The planes of a decoded frame as one run of bytes, for wrapping with
+/// `SharedBuffer::from_owner`. The frame holds a reference to its buffer, so the decoder
+/// does not reuse the memory while any output shares it.
+pub struct FrameBuffer<F> {
+    frame: F,
+    len: usize,
+}
+
+impl<F: Deref<Target = ffmpeg_next::Frame>> FrameBuffer<F> {
+    /// Wraps the planes of `frame`, of `sizes` bytes each, if each one starts where the
+    /// previous one ends inside the frame's first buffer. Hands `frame` back otherwise, as
+    /// when the planes have buffers of their own or padding between them.
+    pub fn new(frame: F, sizes: &[usize]) -> Result<Self, F> {
+        if sizes.is_empty() || sizes.len() > ffi::AV_NUM_DATA_POINTERS as usize {
+            return Err(frame);
+        }
+        // SAFETY: plain reads of the frame's pointers and of its first buffer.
+        let contiguous = unsafe {
+            let raw = &*frame.as_ptr();
+            let buffer = raw.buf[0];
+            if buffer.is_null() || raw.data[0].is_null() {
+                false
+            } else {
+                let start = (*buffer).data as usize;
+                let end = start + (*buffer).size;
+                let mut offset = raw.data[0] as usize;
+                let adjacent = sizes.iter().enumerate().all(|(plane, size)| {
+                    let starts_at_offset = raw.data[plane] as usize == offset;
+                    offset += size;
+                    starts_at_offset
+                });
+                adjacent && raw.data[0] as usize >= start && offset <= end
+            }
+        };
+        if !contiguous {
+            return Err(frame);
+        }
+        Ok(Self {
+            frame,
+            len: sizes.iter().sum(),
+        })
+    }
+}
+
+impl<F: Deref<Target = ffmpeg_next::Frame>> AsRef<[u8]> for FrameBuffer<F> {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: `new` checked that the bytes lie in the frame's first buffer, which the
+        // frame keeps alive and nothing writes to while it is shared.
+        unsafe { slice::from_raw_parts((*self.frame.as_ptr()).data[0], self.len) }
+    }
+}
+
+/// Directs everything `output` writes from now on into a memory buffer.
+pub fn open_memory_output(output: &mut Output) -> Result<(), ffmpeg_next::Error> {
+    // SAFETY: the I/O context is null or a buffer from this function, closed by
+    // `take_memory_output`.
+    unsafe {
+        let context = output.as_mut_ptr();
+        match ffi::avio_open_dyn_buf(&mut (*context).pb) {
+            0 => Ok(()),
+            e => Err(ffmpeg_next::Error::from(e)),
+        }
+    }
+}
+
+/// Takes the bytes written since `open_memory_output` and stops writing to memory.
+pub fn take_memory_output(output: &mut Output) -> Vec<u8> {
+    // SAFETY: the I/O context is the dynamic buffer opened by `open_memory_output`.
+    unsafe {
+        let context = output.as_mut_ptr();
+        if (*context).pb.is_null() {
+            return Vec::new();
+        }
+        let mut buffer = ptr::null_mut();
+        let size = ffi::avio_close_dyn_buf((*context).pb, &mut buffer);
+        (*context).pb = ptr::null_mut();
+        let data = copy_bytes(buffer, size).unwrap_or_default();
+        ffi::av_free(buffer.cast());
+        data
+    }
+}
+
+/// Makes a fragmenting muxer write out the fragment of everything written so far.
+pub fn flush_fragment(output: &mut Output) -> Result<(), ffmpeg_next::Error> {
+    // SAFETY: a null packet asks the muxer to flush.
+    unsafe {
+        match ffi::av_write_frame(output.as_mut_ptr(), ptr::null_mut()) {
+            e if e < 0 => Err(ffmpeg_next::Error::from(e)),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! The ffmpeg backend, which handles every codec string ffmpeg has a codec for.
+
+use crate::codec::{Exception, ExceptionKind};
+
+use super::{
+    audio_decoder::{self, AudioDecoderImpl},
+    audio_encoder::{self, AudioEncoderImpl},
+    backend::{
+        AudioDecoderBackend, AudioEncoderBackend, BackendProvider, VideoDecoderBackend,
+        VideoEncoderBackend,
+    },
+    buffer_pool::BufferPool,
+    video_decoder::{self, VideoDecoderImpl},
+    video_encoder::{self, VideoEncoderImpl},
+};
+
+/// Provides the ffmpeg codec implementations.
+pub struct FfmpegBackend;
+
+impl BackendProvider for FfmpegBackend {
+    fn name(&self) -> &str {
+        "ffmpeg"
+    }
+
+    fn audio_decoder(&self, codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        ffmpeg_next::init().ok()?;
+        audio_decoder::find_audio_decoder(codec)?;
+        Some(Box::new(FfmpegCodec::<AudioDecoderImpl>::default()))
+    }
+
+    fn audio_encoder(&self, codec: &str) -> Option<Box<AudioEncoderBackend>> {
+        ffmpeg_next::init().ok()?;
+        audio_encoder::find_audio_encoder(codec)?;
+        Some(Box::new(FfmpegCodec::<AudioEncoderImpl>::default()))
+    }
+
+    fn video_decoder(&self, codec: &str) -> Option<Box<VideoDecoderBackend>> {
+        ffmpeg_next::init().ok()?;
+        video_decoder::find_video_decoder(codec)?;
+        Some(Box::new(FfmpegCodec::<VideoDecoderImpl>::default()))
+    }
+
+    fn video_encoder(&self, codec: &str) -> Option<Box<VideoEncoderBackend>> {
+        ffmpeg_next::init().ok()?;
+        let (software, hardware) = video_encoder::find_video_encoders(codec);
+        if software.is_empty() && hardware.is_empty() {
+            return None;
+        }
+        Some(Box::new(FfmpegCodec::<VideoEncoderImpl>::default()))
+    }
+}
+
+/// An ffmpeg codec implementation, opened by `configure`.
+pub struct FfmpegCodec<T> {
+    opened: Option<T>,
+    /// The pool decoders draw their outputs from, kept across configures.
+    pool: Option<BufferPool>,
+}
+
+impl<T> Default for FfmpegCodec<T> {
+    fn default() -> Self {
+        Self {
+            opened: None,
+            pool: None,
+        }
+    }
+}
+
+impl<T> FfmpegCodec<T> {
+    /// Replaces the implementation with a newly opened one.
+    pub fn open(&mut self, opened: T) {
+        self.opened = Some(opened);
+    }
+
+    /// The pool for a decoder to open with.
+    pub fn pool(&self) -> Option<BufferPool> {
+        self.pool.clone()
+    }
+
+    pub fn set_pool(&mut self, pool: BufferPool) {
+        self.pool = Some(pool);
+    }
+
+    /// The implementation opened by `configure`.
+    pub fn opened(&mut self) -> Result<&mut T, Exception> {
+        self.opened.as_mut().ok_or_else(|| {
+            Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+        })
+    }
+}
@@ -0,0 +1,289 @@
+//! Hardware acceleration through ffmpeg's hardware device types (VAAPI, CUDA, QSV, ...).
+//!
+//! Nothing here is needed for software coding. On a machine without a usable device no
+//! device opens, and codecs fall back to software unless software was asked for, in which
+//! case no device is tried at all.
+
+use std::{ffi::CStr, ptr};
+
+use ffmpeg_next::{codec::Context, ffi, format::Pixel, frame};
+
+use crate::codec::{Exception, ExceptionKind, HardwareAcceleration};
+
+/// The kinds of implementation a codec can be backed by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Software,
+    Hardware,
+}
+
+/// The backends to try for `preference`, in order.
+///
+/// Hardware comes first unless software is preferred, and software always follows it, so a
+/// config that hardware cannot take, or a machine without a device, still gets a codec.
+pub fn backends(preference: HardwareAcceleration) -> &'static [Backend] {
+    match preference {
+        HardwareAcceleration::NoPreference | HardwareAcceleration::PreferHardware => {
+            &[Backend::Hardware, Backend::Software]
+        }
+        HardwareAcceleration::PreferSoftware => &[Backend::Software],
+    }
+}
+
+/// The names of the hardware device types ffmpeg was built with, e.g. "vaapi" or "cuda".
+///
+/// A device type being listed does not mean such a device is present.
+pub fn device_type_names() -> Vec<&'static str> {
+    device_types().into_iter().map(device_type_name).collect()
+}
+
+fn device_types() -> Vec<ffi::AVHWDeviceType> {
+    let mut types = Vec::new();
+    let mut device_type = ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_NONE;
+    loop {
+        // SAFETY: iterating from NONE visits every supported type once, then returns NONE.
+        device_type = unsafe { ffi::av_hwdevice_iterate_types(device_type) };
+        if device_type == ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_NONE {
+            return types;
+        }
+        types.push(device_type);
+    }
+}
+
+fn device_type_name(device_type: ffi::AVHWDeviceType) -> &'static str {
+    // SAFETY: the name is null or a static string.
+    unsafe {
+        let name = ffi::av_hwdevice_get_type_name(device_type);
+        if name.is_null() {
+            return "unknown";
+        }
+        CStr::from_ptr(name).to_str().unwrap_or("unknown")
+    }
+}
+
+/// Whether frames in `format` live on a hardware device rather than in memory.
+pub fn is_hardware_format(format: Pixel) -> bool {
+    format.descriptor().is_some_and(|descriptor| {
+        // SAFETY: a plain field read on a static descriptor.
+        let flags = unsafe { (*descriptor.as_ptr()).flags };
+        flags & ffi::AV_PIX_FMT_FLAG_HWACCEL as u64 != 0
+    })
+}
+
+/// The hardware configs ffmpeg advertises for `codec` that use `method`, as pixel format and
+/// device type.
+fn hardware_configs(
+    codec: ffmpeg_next::Codec,
+    method: u32,
+) -> Vec<(ffi::AVPixelFormat, ffi::AVHWDeviceType)> {
+    let mut configs = Vec::new();
+    for index in 0.. {
+        // SAFETY: the configs are static and the list ends with null.
+        let config = unsafe { ffi::avcodec_get_hw_config(codec.as_ptr(), index).as_ref() };
+        let Some(config) = config else {
+            return configs;
+        };
+        if config.methods & method as i32 != 0 {
+            configs.push((config.pix_fmt, config.device_type));
+        }
+    }
+    configs
+}
+
+/// An opened hardware device.
+pub struct HardwareDevice {
+    context: *mut ffi::AVBufferRef,
+}
+
+// SAFETY: device contexts are reference counted and ffmpeg synchronises their use.
+unsafe impl Send for HardwareDevice {}
+
+impl HardwareDevice {
+    /// Opens the default device of `device_type`, `None` when there is none.
+    fn open(device_type: ffi::AVHWDeviceType) -> Option<Self> {
+        let mut context = ptr::null_mut();
+        // SAFETY: on success `context` is a new reference owned by the returned device.
+        let result = unsafe {
+            ffi::av_hwdevice_ctx_create(&mut context, device_type, ptr::null(), ptr::null_mut(), 0)
+        };
+        (result >= 0 && !context.is_null()).then_some(Self { context })
+    }
+
+    /// Opens a device that `codec` can decode on, `None` when there is none.
+    pub fn for_decoder(codec: ffmpeg_next::Codec) -> Option<Self> {
+        hardware_configs(codec, ffi::AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as u32)
+            .into_iter()
+            .find_map(|(_, device_type)| Self::open(device_type))
+    }
+
+    /// Makes a decoder context that has not been opened yet decode on this device.
+    ///
+    /// ffmpeg then picks the hardware pixel format when the stream is supported by the
+    /// device, and decodes in software otherwise.
+    pub fn attach(&self, context: &mut Context) {
+        // SAFETY: the context takes a new reference and frees any previous one.
+        unsafe {
+            let context = context.as_mut_ptr();
+            ffi::av_buffer_unref(&mut (*context).hw_device_ctx);
+            (*context).hw_device_ctx = ffi::av_buffer_ref(self.context);
+        }
+    }
+}
+
+impl Drop for HardwareDevice {
+    fn drop(&mut self) {
+        // SAFETY: we own one reference.
+        unsafe { ffi::av_buffer_unref(&mut self.context) }
+    }
+}
+
+/// A pool of frames on a hardware device, for encoders that only read frames from the
+/// device, such as the VAAPI ones.
+pub struct HardwareFrames {
+    context: *mut ffi::AVBufferRef,
+    format: Pixel,
+}
+
+// SAFETY: as for `HardwareDevice`.
+unsafe impl Send for HardwareFrames {}
+
+impl HardwareFrames {
+    /// The format frames are uploaded from.
+    pub const SOFTWARE_FORMAT: Pixel = Pixel::NV12;
+
+    /// Sets up `width` by `height` frames on a device that `codec` can encode from.
+    pub fn for_encoder(
+        codec: ffmpeg_next::Codec,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, Exception> {
+        let not_supported = || {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no hardware device for {}", codec.name()),
+            )
+        };
+        let (format, device) =
+            hardware_configs(codec, ffi::AV_CODEC_HW_CONFIG_METHOD_HW_FRAMES_CTX as u32)
+                .into_iter()
+                .find_map(|(format, device_type)| {
+                    HardwareDevice::open(device_type).map(|device| (format, device))
+                })
+                .ok_or_else(not_supported)?;
+
+        // SAFETY: the frames context takes its own reference to the device, and is freed
+        // here unless it is handed to the returned frames.
+        unsafe {
+            let mut context = ffi::av_hwframe_ctx_alloc(device.context);
+            if context.is_null() {
+                return Err(not_supported());
+            }
+            let frames = (*context).data as *mut ffi::AVHWFramesContext;
+            (*frames).format = format;
+            (*frames).sw_format = Self::SOFTWARE_FORMAT.into();
+            (*frames).width = width as i32;
+            (*frames).height = height as i32;
+            (*frames).initial_pool_size = 20;
+            if ffi::av_hwframe_ctx_init(context) < 0 {
+                ffi::av_buffer_unref(&mut context);
+                return Err(not_supported());
+            }
+            Ok(Self {
+                context,
+                format: Pixel::from(format),
+            })
+        }
+    }
+
+    /// The hardware pixel format the encoder is opened with.
+    pub fn format(&self) -> Pixel {
+        self.format
+    }
+
+    /// Makes an encoder context that has not been opened yet read frames from this pool.
+    pub fn attach(&self, context: &mut Context) {
+        // SAFETY: as in `HardwareDevice::attach`.
+        unsafe {
+            let context = context.as_mut_ptr();
+            ffi::av_buffer_unref(&mut (*context).hw_frames_ctx);
+            (*context).hw_frames_ctx = ffi::av_buffer_ref(self.context);
+        }
+    }
+
+    /// Copies a frame in `SOFTWARE_FORMAT` to the device, with its timestamp and flags.
+    pub fn upload(&self, video: &frame::Video) -> Result<frame::Video, Exception> {
+        let mut uploaded = frame::Video::empty();
+        // SAFETY: both frames are valid and the pool outlives the call.
+        let result = unsafe {
+            let target = uploaded.as_mut_ptr();
+            match ffi::av_hwframe_get_buffer(self.context, target, 0) {
+                0 => match ffi::av_hwframe_transfer_data(target, video.as_ptr(), 0) {
+                    0 => ffi::av_frame_copy_props(target, video.as_ptr()),
+                    e => e,
+                },
+                e => e,
+            }
+        };
+        if result < 0 {
+            return Err(
+                Exception::new(ExceptionKind::EncodingError, "failed to upload frame")
+                    .with_source(ffmpeg_next::Error::from(result)),
+            );
+        }
+        Ok(uploaded)
+    }
+}
+
+impl Drop for HardwareFrames {
+    fn drop(&mut self) {
+        // SAFETY: we own one reference.
+        unsafe { ffi::av_buffer_unref(&mut self.context) }
+    }
+}
+
+/// Whether a decoded frame lives on a hardware device and has to be downloaded.
+pub fn is_hardware_frame(video: &frame::Video) -> bool {
+    // SAFETY: a plain field read.
+    unsafe { !(*video.as_ptr()).hw_frames_ctx.is_null() }
+}
+
+/// Copies a decoded hardware frame to memory, with its timestamp and flags.
+pub fn download(video: &frame::Video) -> Result<frame::Video, Exception> {
+    let mut downloaded = frame::Video::empty();
+    // SAFETY: both frames are valid; the transfer picks the memory format.
+    let result = unsafe {
+        let target = downloaded.as_mut_ptr();
+        match ffi::av_hwframe_transfer_data(target, video.as_ptr(), 0) {
+            0 => ffi::av_frame_copy_props(target, video.as_ptr()),
+            e => e,
+        }
+    };
+    if result < 0 {
+        return Err(
+            Exception::new(ExceptionKind::DecodeError, "failed to download frame")
+                .with_source(ffmpeg_next::Error::from(result)),
+        );
+    }
+    Ok(downloaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn software_follows_hardware_unless_preferred() {
+        assert_eq!(
+            backends(HardwareAcceleration::PreferHardware),
+            [Backend::Hardware, Backend::Software]
+        );
+        assert_eq!(
+            backends(HardwareAcceleration::NoPreference),
+            [Backend::Hardware, Backend::Software]
+        );
+        assert_eq!(
+            backends(HardwareAcceleration::PreferSoftware),
+            [Backend::Software]
+        );
+    }
+}
@@ -1,87 +1,144 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use crate::codec::ConfigMessage;
+use crate::codec::{Exception, State};
 
 use super::{
-    control::{
-        self, ControlMessage, ControlMessageTrait, DecodeMessage, EncodeMessage, FlushMessage,
-        Outcome,
-    },
+    control::{ControlMessageTrait, Outcome},
     work_queue::WorkQueue,
 };
 
 /// Internal slots shared by codec instances.
+///
+/// Cloning is cheap and yields a handle to the same queues, so that jobs running on the
+/// work queue can unblock and resume processing of the control message queue.
+#[derive(Clone)]
 pub struct CodecInternalSlots {
-    pub control_message_queue: VecDeque<control::ControlMessage>,
-    pub message_queue_blocked: bool,
+    pub control_message_queue: Arc<Mutex<VecDeque<Box<dyn ControlMessageTrait>>>>,
+    pub message_queue_blocked: Arc<AtomicBool>,
+    /// Whether a configure job of the current epoch has installed a backend; every other
+    /// message waits for it.
+    pub backend_ready: Arc<AtomicBool>,
     pub work_queue: Arc<WorkQueue>,
+    pub state: Arc<Mutex<State>>,
+    /// Bumped by every reset or close; jobs enqueued under an older epoch are stale.
+    pub epoch: Arc<AtomicU64>,
 }
 
 impl CodecInternalSlots {
     pub fn new(num_threads: usize) -> Self {
         CodecInternalSlots {
-            control_message_queue: VecDeque::new(),
-            message_queue_blocked: false,
+            control_message_queue: Arc::new(Mutex::new(VecDeque::new())),
+            message_queue_blocked: Arc::new(AtomicBool::new(false)),
+            backend_ready: Arc::new(AtomicBool::new(false)),
             work_queue: Arc::new(WorkQueue::new(num_threads)),
+            state: Arc::new(Mutex::new(State::Unconfigured)),
+            epoch: Arc::new(AtomicU64::new(0)),
         }
     }
 
     /// Enqueue a control message and process the control message queue.
-    pub fn enqueue_control_message(&mut self, msg: control::ControlMessage) {
-        self.control_message_queue.push_back(msg);
+    pub fn enqueue_control_message(&self, msg: impl ControlMessageTrait + 'static) {
+        if let Ok(mut queue) = self.control_message_queue.lock() {
+            queue.push_back(Box::new(msg));
+        }
+        self.process_control_message_queue();
+    }
+
+    /// Blocks the control message queue until `unblock` is called, e.g. while a
+    /// configure job is running on the work queue.
+    pub fn block(&self) {
+        self.message_queue_blocked.store(true, Ordering::SeqCst);
+    }
+
+    /// Unblocks the control message queue and resumes processing it.
+    pub fn unblock(&self) {
+        self.message_queue_blocked.store(false, Ordering::SeqCst);
         self.process_control_message_queue();
     }
 
+    pub fn is_blocked(&self) -> bool {
+        self.message_queue_blocked.load(Ordering::SeqCst)
+    }
+
+    pub fn is_backend_ready(&self) -> bool {
+        self.backend_ready.load(Ordering::SeqCst)
+    }
+
+    /// Marks the backend installed by a configure job of `epoch` as ready, unless a reset or
+    /// close has happened since.
+    ///
+    /// The check holds the control message queue lock, like `abort`, so an abort either
+    /// comes first and the flag stays unset, or comes after and clears it again.
+    pub fn set_backend_ready(&self, epoch: u64) {
+        let Ok(_queue) = self.control_message_queue.lock() else {
+            return;
+        };
+        if self.is_current(epoch) {
+            self.backend_ready.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state.lock().map_or(State::Closed, |state| *state)
+    }
+
+    /// Sets the codec state. Jobs use this to close the codec when they fail.
+    pub fn set_state(&self, state: State) {
+        if let Ok(mut current) = self.state.lock() {
+            *current = state;
+        }
+    }
+
+    /// The current epoch, captured by jobs when they are enqueued.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Whether no reset or close has happened since `epoch` was captured.
+    pub fn is_current(&self, epoch: u64) -> bool {
+        self.epoch() == epoch
+    }
+
+    /// Aborts all pending work: jobs already on the work queue become stale, pending control
+    /// messages are dropped and the promises of pending flushes are rejected with `reason`.
+    ///
+    /// The epoch is bumped while the control message queue is locked, so every job is either
+    /// enqueued under the old epoch or belongs to a message sent after the abort.
+    pub fn abort(&self, reason: &Exception) {
+        let Ok(mut queue) = self.control_message_queue.lock() else {
+            return;
+        };
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        // A configure job made stale by this abort will not unblock the queue itself.
+        self.message_queue_blocked.store(false, Ordering::SeqCst);
+        // The backend is released with the abort; only a new configure installs another.
+        self.backend_ready.store(false, Ordering::SeqCst);
+        for mut msg in queue.drain(..) {
+            msg.abort(reason);
+        }
+    }
+
     /// Sequential processing
-    pub fn process_control_message_queue(&mut self) {
-        while !self.message_queue_blocked && !self.control_message_queue.is_empty() {
-            if let Some(front_msg) = self.control_message_queue.front_mut() {
-                // TODO: refact this
-                match front_msg {
-                    ControlMessage::Decode(decode_msg) => match decode_msg {
-                        DecodeMessage::AudioDecode(dcd_msg) => {
-                            let outcome = dcd_msg.process();
-                            match outcome {
-                                Outcome::NotProcessed => break,
-                                Outcome::Processed => {
-                                    self.control_message_queue.pop_front();
-                                }
-                            }
-                        }
-                    },
-                    ControlMessage::Encode(encode_msg) => match encode_msg {
-                        EncodeMessage::AudioEncode(enc_msg) => {
-                            let outcome = enc_msg.process();
-                            match outcome {
-                                Outcome::NotProcessed => break,
-                                Outcome::Processed => {
-                                    self.control_message_queue.pop_front();
-                                }
-                            }
-                        }
-                    },
-                    ControlMessage::Config(config_msg) => match config_msg {
-                        ConfigMessage::AudioConfig(cfg_msg) => {
-                            let outcome = cfg_msg.process();
-                            match outcome {
-                                Outcome::NotProcessed => break,
-                                Outcome::Processed => {
-                                    self.control_message_queue.pop_front();
-                                }
-                            }
-                        }
-                    },
-                    ControlMessage::Flush(flush_msg) => match flush_msg {
-                        FlushMessage::AudioFlush(fls_msg) => {
-                            let outcome = fls_msg.process();
-                            match outcome {
-                                Outcome::NotProcessed => break,
-                                Outcome::Processed => {
-                                    self.control_message_queue.pop_front();
-                                }
-                            }
-                        }
-                    },
+    pub fn process_control_message_queue(&self) {
+        let mut queue = match self.control_message_queue.lock() {
+            Ok(queue) => queue,
+            Err(_) => return,
+        };
+        while !self.is_blocked() {
+            let Some(front_msg) = queue.front_mut() else {
+                break;
+            };
+            let outcome = front_msg.process();
+            match outcome {
+                Outcome::NotProcessed => break,
+                Outcome::Processed => {
+                    queue.pop_front();
                 }
             }
         }
@@ -1,3 +1,31 @@
+#[cfg(feature = "ffmpeg")]
+pub mod audio_decoder;
+#[cfg(feature = "ffmpeg")]
+pub mod audio_encoder;
+pub mod backend;
+pub mod buffer_pool;
 pub mod control;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg_backend;
+#[cfg(feature = "ffmpeg")]
+pub mod hardware;
 pub mod internal_slots;
+pub mod pcm;
+pub mod pcm_backend;
+pub mod promise;
+pub mod queue_size;
+#[cfg(feature = "pure-rust")]
+pub mod rust_audio_decoder;
+#[cfg(feature = "pure-rust")]
+pub mod rust_audio_encoder;
+#[cfg(feature = "pure-rust")]
+pub mod rust_backend;
+#[cfg(feature = "ffmpeg")]
+pub mod video_decoder;
+#[cfg(feature = "ffmpeg")]
+pub mod video_encoder;
+#[cfg(feature = "ffmpeg")]
+pub mod video_frame;
 pub mod work_queue;
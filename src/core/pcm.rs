@@ -0,0 +1,305 @@
+//! Sample conversions for uncompressed audio: the sample formats of `AudioData`, and the
+//! little-endian PCM and G.711 (u-law and a-law) formats of the WebCodecs codec registry.
+
+use crate::{
+    codec::{Exception, ExceptionKind},
+    data::audio_data::{sample_format_layout, AudioData},
+};
+
+use super::buffer_pool::{BufferPool, PooledBuffer};
+
+/// The sample format of a PCM, u-law or a-law codec string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+    Ulaw,
+    Alaw,
+}
+
+impl PcmFormat {
+    /// The format of `codec`, `None` for other codec strings.
+    pub fn from_codec(codec: &str) -> Option<Self> {
+        match codec {
+            "pcm-u8" => Some(Self::U8),
+            "pcm-s16" => Some(Self::S16),
+            "pcm-s24" => Some(Self::S24),
+            "pcm-s32" => Some(Self::S32),
+            "pcm-f32" => Some(Self::F32),
+            "ulaw" => Some(Self::Ulaw),
+            "alaw" => Some(Self::Alaw),
+            _ => None,
+        }
+    }
+
+    /// The size of one sample of one channel in bytes.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::U8 | Self::Ulaw | Self::Alaw => 1,
+            Self::S16 => 2,
+            Self::S24 => 3,
+            Self::S32 | Self::F32 => 4,
+        }
+    }
+
+    /// The `AudioData` format that decoded samples are in: the codec's own sample type, or
+    /// `s32` for 24 bit samples and `s16` for u-law and a-law, which `AudioData` has no
+    /// format for. Every sample converts without loss.
+    pub fn audio_data_format(self) -> &'static str {
+        match self {
+            Self::U8 => "u8",
+            Self::S16 | Self::Ulaw | Self::Alaw => "s16",
+            Self::S24 | Self::S32 => "s32",
+            Self::F32 => "f32",
+        }
+    }
+
+    /// Decodes a chunk of interleaved samples to `AudioData` in `audio_data_format`, in a
+    /// buffer from `pool` if there is one.
+    pub fn decode(
+        self,
+        data: &[u8],
+        sample_rate: u32,
+        channels: u32,
+        timestamp: f64,
+        pool: Option<&BufferPool>,
+    ) -> Result<AudioData, Exception> {
+        let frame_size = self.bytes_per_sample() * channels as usize;
+        if frame_size == 0 || !data.len().is_multiple_of(frame_size) {
+            return Err(Exception::new(
+                ExceptionKind::DataError,
+                format!(
+                    "chunk of {} bytes is not a whole number of {channels} channel frames",
+                    data.len()
+                ),
+            ));
+        }
+
+        let samples = data.chunks_exact(self.bytes_per_sample());
+        let (decoded_sample_size, _) = sample_format_layout(self.audio_data_format())
+            .expect("PCM formats decode to AudioData formats");
+        let mut decoded = PooledBuffer::acquire(pool, samples.len() * decoded_sample_size)?;
+        match self {
+            Self::U8 => decoded.extend_from_slice(data),
+            Self::S16 => decoded.extend(
+                samples.flat_map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]).to_ne_bytes()),
+            ),
+            Self::S32 => decoded.extend(samples.flat_map(|bytes| {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_ne_bytes()
+            })),
+            Self::F32 => decoded.extend(samples.flat_map(|bytes| {
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_ne_bytes()
+            })),
+            // Shifted up to 32 bits.
+            Self::S24 => decoded.extend(samples.flat_map(|bytes| {
+                i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]).to_ne_bytes()
+            })),
+            Self::Ulaw => {
+                decoded.extend(samples.flat_map(|bytes| ulaw_to_linear(bytes[0]).to_ne_bytes()))
+            }
+            Self::Alaw => {
+                decoded.extend(samples.flat_map(|bytes| alaw_to_linear(bytes[0]).to_ne_bytes()))
+            }
+        }
+        Ok(AudioData::new(
+            self.audio_data_format().to_string(),
+            sample_rate as f64,
+            channels,
+            (data.len() / frame_size) as u32,
+            timestamp,
+            decoded.freeze(),
+        ))
+    }
+
+    /// Encodes `AudioData` of any format to interleaved samples. Samples in the matching
+    /// `audio_data_format` convert without loss; integer samples out of range are clipped.
+    pub fn encode(self, data: &AudioData) -> Result<Vec<u8>, Exception> {
+        let sample = sample_reader(data)?;
+        let channels = data.number_of_channels as usize;
+        let frames = data.number_of_frames as usize;
+        let mut encoded = Vec::with_capacity(frames * channels * self.bytes_per_sample());
+        for i in 0..frames {
+            for ch in 0..channels {
+                let sample = sample(ch, i);
+                match self {
+                    Self::U8 => encoded.push((to_integer(sample, 7) + 128) as u8),
+                    Self::S16 => encoded.extend_from_slice(&to_s16(sample).to_le_bytes()),
+                    Self::S24 => {
+                        encoded.extend_from_slice(&to_integer(sample, 23).to_le_bytes()[..3])
+                    }
+                    Self::S32 => encoded.extend_from_slice(&to_integer(sample, 31).to_le_bytes()),
+                    Self::F32 => encoded.extend_from_slice(&(sample as f32).to_le_bytes()),
+                    Self::Ulaw => encoded.push(linear_to_ulaw(to_s16(sample))),
+                    Self::Alaw => encoded.push(linear_to_alaw(to_s16(sample))),
+                }
+            }
+        }
+        Ok(encoded)
+    }
+}
+
+/// Scales a sample in [-1, 1) to a `bits` + 1 bit signed integer, clipping it to that range.
+fn to_integer(sample: f64, bits: u32) -> i32 {
+    let scale = (1u64 << bits) as f64;
+    (sample * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+fn to_s16(sample: f64) -> i16 {
+    to_integer(sample, 15) as i16
+}
+
+/// Checks that `data` holds all of its samples, and returns a reader of the sample of a
+/// channel and frame, scaled to [-1, 1) for integer formats.
+fn sample_reader(data: &AudioData) -> Result<impl Fn(usize, usize) -> f64 + '_, Exception> {
+    let channels = data.number_of_channels as usize;
+    let frames = data.number_of_frames as usize;
+    let (bytes_per_sample, planar) = sample_format_layout(&data.format).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("unsupported AudioData format {:?}", data.format),
+        )
+    })?;
+    if data.data.len() < channels * frames * bytes_per_sample {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            "AudioData buffer is smaller than its format requires",
+        ));
+    }
+
+    let kind = data.format.trim_end_matches("-planar");
+    Ok(move |ch: usize, i: usize| -> f64 {
+        let index = if planar {
+            ch * frames + i
+        } else {
+            i * channels + ch
+        };
+        let bytes = &data.data[index * bytes_per_sample..(index + 1) * bytes_per_sample];
+        match kind {
+            "u8" => (bytes[0] as f64 - 128.0) / 128.0,
+            "s16" => i16::from_ne_bytes([bytes[0], bytes[1]]) as f64 / 32768.0,
+            "s32" => {
+                i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / 2147483648.0
+            }
+            _ => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        }
+    })
+}
+
+/// Reads `data` as one `f32` vector per channel.
+pub fn f32_planes(data: &AudioData) -> Result<Vec<Vec<f32>>, Exception> {
+    let sample = sample_reader(data)?;
+    Ok((0..data.number_of_channels as usize)
+        .map(|ch| {
+            (0..data.number_of_frames as usize)
+                .map(|i| sample(ch, i) as f32)
+                .collect()
+        })
+        .collect())
+}
+
+/// Makes `f32-planar` `AudioData` out of one `f32` vector per channel.
+pub fn f32_planar_audio_data(planes: &[Vec<f32>], sample_rate: u32, timestamp: f64) -> AudioData {
+    f32_planar_audio_data_in(planes, sample_rate, timestamp, None)
+        .expect("buffers without a pool are always available")
+}
+
+/// Like `f32_planar_audio_data`, in a buffer from `pool` if there is one.
+pub fn f32_planar_audio_data_in(
+    planes: &[Vec<f32>],
+    sample_rate: u32,
+    timestamp: f64,
+    pool: Option<&BufferPool>,
+) -> Result<AudioData, Exception> {
+    let frames = planes.first().map_or(0, Vec::len);
+    let mut data = PooledBuffer::acquire(pool, frames * planes.len() * std::mem::size_of::<f32>())?;
+    for plane in planes {
+        for sample in plane {
+            data.extend_from_slice(&sample.to_ne_bytes());
+        }
+    }
+    Ok(AudioData::new(
+        "f32-planar".to_string(),
+        sample_rate as f64,
+        planes.len() as u32,
+        frames as u32,
+        timestamp,
+        data.freeze(),
+    ))
+}
+
+/// The largest magnitude of each G.711 segment, for 14 bit u-law and 13 bit a-law input.
+const ULAW_SEGMENT_ENDS: [i32; 8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+const ALAW_SEGMENT_ENDS: [i32; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+const ULAW_BIAS: i32 = 0x84;
+const ULAW_CLIP: i32 = 8159;
+
+fn segment(value: i32, ends: &[i32; 8]) -> Option<i32> {
+    ends.iter()
+        .position(|&end| value <= end)
+        .map(|segment| segment as i32)
+}
+
+/// Compresses a 16 bit sample to G.711 u-law.
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let mut value = (sample as i32) >> 2;
+    let mask = if value < 0 {
+        value = -value;
+        0x7F
+    } else {
+        0xFF
+    };
+    let value = value.min(ULAW_CLIP) + (ULAW_BIAS >> 2);
+    match segment(value, &ULAW_SEGMENT_ENDS) {
+        Some(segment) => (((segment << 4) | ((value >> (segment + 1)) & 0xF)) ^ mask) as u8,
+        None => (0x7F ^ mask) as u8,
+    }
+}
+
+/// Expands a G.711 u-law sample to 16 bits.
+pub fn ulaw_to_linear(ulaw: u8) -> i16 {
+    let ulaw = !ulaw as i32;
+    let magnitude = ((((ulaw & 0xF) << 3) + ULAW_BIAS) << ((ulaw & 0x70) >> 4)) - ULAW_BIAS;
+    if ulaw & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+/// Compresses a 16 bit sample to G.711 a-law.
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    let mut value = (sample as i32) >> 3;
+    let mask = if value >= 0 {
+        0xD5
+    } else {
+        value = -value - 1;
+        0x55
+    };
+    match segment(value, &ALAW_SEGMENT_ENDS) {
+        Some(segment) => {
+            let shift = if segment < 2 { 1 } else { segment };
+            (((segment << 4) | ((value >> shift) & 0xF)) ^ mask) as u8
+        }
+        None => (0x7F ^ mask) as u8,
+    }
+}
+
+/// Expands a G.711 a-law sample to 16 bits.
+pub fn alaw_to_linear(alaw: u8) -> i16 {
+    let alaw = (alaw ^ 0x55) as i32;
+    let segment = (alaw & 0x70) >> 4;
+    let mut magnitude = (alaw & 0xF) << 4;
+    match segment {
+        0 => magnitude += 8,
+        1 => magnitude += 0x108,
+        _ => magnitude = (magnitude + 0x108) << (segment - 1),
+    }
+    if alaw & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
@@ -0,0 +1,170 @@
+//! The PCM backend, which codes `pcm-u8`, `pcm-s16`, `pcm-s24`, `pcm-s32`, `pcm-f32`,
+//! `ulaw` and `alaw` natively in every build.
+//!
+//! Chunks hold interleaved little-endian samples. Decoded `AudioData` is interleaved in the
+//! format of `PcmFormat::audio_data_format`, and each `AudioData` is encoded to one chunk.
+
+use std::collections::VecDeque;
+
+use crate::{
+    codec::{AudioDecoderConfig, AudioEncoderConfig, Exception, ExceptionKind},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+};
+
+use super::{
+    backend::{AudioDecoderBackend, AudioEncoderBackend, BackendProvider, CodecBackend},
+    buffer_pool::BufferPool,
+    pcm::PcmFormat,
+};
+
+/// Provides the PCM, u-law and a-law codecs.
+pub struct PcmBackend;
+
+impl BackendProvider for PcmBackend {
+    fn name(&self) -> &str {
+        "pcm"
+    }
+
+    fn audio_decoder(&self, codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        PcmFormat::from_codec(codec)?;
+        Some(Box::new(PcmDecoder::default()))
+    }
+
+    fn audio_encoder(&self, codec: &str) -> Option<Box<AudioEncoderBackend>> {
+        PcmFormat::from_codec(codec)?;
+        Some(Box::new(PcmEncoder::default()))
+    }
+}
+
+fn not_configured() -> Exception {
+    Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+}
+
+fn pcm_format(codec: &str, kind: &str) -> Result<PcmFormat, Exception> {
+    PcmFormat::from_codec(codec).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("no {kind} found for codec {codec:?}"),
+        )
+    })
+}
+
+#[derive(Default)]
+pub struct PcmDecoder {
+    config: Option<(PcmFormat, AudioDecoderConfig)>,
+    buffer_pool: Option<BufferPool>,
+    ready: VecDeque<AudioData>,
+}
+
+impl CodecBackend for PcmDecoder {
+    type Config = AudioDecoderConfig;
+    type Input = EncodedAudioChunk;
+    type Output = AudioData;
+
+    fn configure(&mut self, config: &AudioDecoderConfig) -> Result<(), Exception> {
+        self.config = Some((pcm_format(&config.codec, "decoder")?, config.clone()));
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        let (format, config) = self.config.as_ref().ok_or_else(not_configured)?;
+        let data = format.decode(
+            &chunk.data,
+            config.sample_rate,
+            config.number_of_channels,
+            chunk.timestamp as f64,
+            self.buffer_pool.as_ref(),
+        )?;
+        if data.number_of_frames > 0 {
+            self.ready.push_back(data);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<AudioData>, Exception> {
+        Ok(self.ready.pop_front())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        // Every chunk is decoded as it is sent.
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.buffer_pool = Some(pool);
+    }
+}
+
+#[derive(Default)]
+pub struct PcmEncoder {
+    config: Option<(PcmFormat, AudioEncoderConfig)>,
+    /// Whether the decoder config has been reported in output metadata.
+    reported_config: bool,
+    ready: VecDeque<(EncodedAudioChunk, EncodedAudioChunkMetadata)>,
+}
+
+impl CodecBackend for PcmEncoder {
+    type Config = AudioEncoderConfig;
+    type Input = AudioData;
+    type Output = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
+    fn configure(&mut self, config: &AudioEncoderConfig) -> Result<(), Exception> {
+        self.config = Some((pcm_format(&config.codec, "encoder")?, config.clone()));
+        self.reported_config = false;
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, data: AudioData) -> Result<(), Exception> {
+        let (format, config) = self.config.as_ref().ok_or_else(not_configured)?;
+        if data.sample_rate as u32 != config.sample_rate
+            || data.number_of_channels != config.number_of_channels
+        {
+            return Err(Exception::new(
+                ExceptionKind::EncodingError,
+                format!(
+                    "AudioData with {} channels at {} Hz does not match the encoder config",
+                    data.number_of_channels, data.sample_rate
+                ),
+            ));
+        }
+        let chunk = EncodedAudioChunk {
+            data: format.encode(&data)?.into(),
+            timestamp: data.timestamp as i64,
+            duration: Some(data.duration as u64),
+            is_key: true,
+        };
+        let mut metadata = EncodedAudioChunkMetadata::default();
+        if !self.reported_config {
+            metadata.decoder_config = Some(AudioDecoderConfig {
+                codec: config.codec.clone(),
+                sample_rate: config.sample_rate,
+                number_of_channels: config.number_of_channels,
+                description: None,
+            });
+            self.reported_config = true;
+        }
+        self.ready.push_back((chunk, metadata));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        Ok(self.ready.pop_front())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        // Every `AudioData` is encoded as it is sent.
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.ready.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::codec::{Exception, ExceptionKind};
+
+struct PromiseState<T> {
+    /// Kept once settled, so that every clone sees it.
+    result: Option<Result<T, Exception>>,
+    /// The wakers of every clone being awaited.
+    wakers: Vec<Waker>,
+}
+
+/// A one-shot result settled by a job on the codec work queue, e.g. the completion of a
+/// flush.
+///
+/// It can either be waited on from a blocking context with `wait`, or awaited on any
+/// executor. Every clone sees the same result, so it can be waited on or awaited from as
+/// many places as needed.
+pub struct Promise<T> {
+    inner: Arc<(Mutex<PromiseState<T>>, Condvar)>,
+}
+
+impl<T> Clone for Promise<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for Promise<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Promise<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new((
+                Mutex::new(PromiseState {
+                    result: None,
+                    wakers: Vec::new(),
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Settles the promise. Only the first call has any effect.
+    pub fn settle(&self, result: Result<T, Exception>) {
+        let (lock, cvar) = &*self.inner;
+        let Ok(mut state) = lock.lock() else {
+            return;
+        };
+        if state.result.is_some() {
+            return;
+        }
+        state.result = Some(result);
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        cvar.notify_all();
+    }
+
+    pub fn resolve(&self, value: T) {
+        self.settle(Ok(value));
+    }
+
+    pub fn reject(&self, error: Exception) {
+        self.settle(Err(error));
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.inner
+            .0
+            .lock()
+            .map(|state| state.result.is_some())
+            .unwrap_or(true)
+    }
+}
+
+impl<T: Clone> Promise<T> {
+    /// Blocks the current thread until the promise is settled.
+    pub fn wait(self) -> Result<T, Exception> {
+        let (lock, cvar) = &*self.inner;
+        let mut state = lock.lock().map_err(|_| poisoned())?;
+        loop {
+            if let Some(result) = &state.result {
+                return result.clone();
+            }
+            state = cvar.wait(state).map_err(|_| poisoned())?;
+        }
+    }
+}
+
+impl<T: Clone> Future for Promise<T> {
+    type Output = Result<T, Exception>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Ok(mut state) = self.inner.0.lock() else {
+            return Poll::Ready(Err(poisoned()));
+        };
+        match &state.result {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                let waker = cx.waker();
+                if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+                    state.wakers.push(waker.clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn poisoned() -> Exception {
+    Exception::new(ExceptionKind::InternalError, "promise state is poisoned")
+}
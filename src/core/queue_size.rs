@@ -0,0 +1,55 @@
+use std::sync::{Condvar, Mutex};
+
+/// Number of requests handed to the codec that have not been completed yet, like the
+/// `decodeQueueSize` and `encodeQueueSize` attributes.
+///
+/// Jobs decrement it once their outputs have been delivered, which lets a caller wait for
+/// the queue to drain below a limit.
+#[derive(Default)]
+pub struct QueueSize {
+    size: Mutex<u32>,
+    changed: Condvar,
+}
+
+impl QueueSize {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> u32 {
+        self.size.lock().map(|size| *size).unwrap_or(0)
+    }
+
+    pub fn increment(&self) {
+        if let Ok(mut size) = self.size.lock() {
+            *size += 1;
+        }
+    }
+
+    pub fn decrement(&self) {
+        if let Ok(mut size) = self.size.lock() {
+            *size = size.saturating_sub(1);
+            self.changed.notify_all();
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut size) = self.size.lock() {
+            *size = 0;
+            self.changed.notify_all();
+        }
+    }
+
+    /// Blocks the current thread until fewer than `limit` requests are pending.
+    pub fn wait_below(&self, limit: u32) {
+        let Ok(mut size) = self.size.lock() else {
+            return;
+        };
+        while *size >= limit {
+            size = match self.changed.wait(size) {
+                Ok(size) => size,
+                Err(_) => return,
+            };
+        }
+    }
+}
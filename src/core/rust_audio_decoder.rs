@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use symphonia::core::{
+    audio::{AudioBuffer, Signal},
+    codecs::{
+        CodecParameters, CodecType, Decoder, DecoderOptions, CODEC_TYPE_FLAC, CODEC_TYPE_MP3,
+        CODEC_TYPE_VORBIS,
+    },
+    formats::Packet,
+};
+
+use crate::{
+    bitstream,
+    codec::{AudioDecoderConfig, Exception, ExceptionKind},
+    core::{backend::CodecBackend, buffer_pool::BufferPool, pcm},
+    data::audio_data::{AudioData, EncodedAudioChunk},
+};
+
+/// A symphonia audio decoder for FLAC, MP3 and Vorbis, which outputs `f32-planar`
+/// `AudioData`.
+#[derive(Default)]
+pub struct RustAudioDecoder {
+    decoder: Option<Box<dyn Decoder>>,
+    buffer_pool: Option<BufferPool>,
+    /// Decoded data that has not been received yet.
+    ready: VecDeque<AudioData>,
+}
+
+impl RustAudioDecoder {
+    /// Whether `codec` can be decoded.
+    pub fn supports(codec: &str) -> bool {
+        symphonia_codec(codec).is_some()
+    }
+
+    fn decoder(&mut self) -> Result<&mut Box<dyn Decoder>, Exception> {
+        self.decoder.as_mut().ok_or_else(|| {
+            Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+        })
+    }
+}
+
+impl CodecBackend for RustAudioDecoder {
+    type Config = AudioDecoderConfig;
+    type Input = EncodedAudioChunk;
+    type Output = AudioData;
+
+    fn configure(&mut self, config: &AudioDecoderConfig) -> Result<(), Exception> {
+        self.decoder = Some(open_symphonia_decoder(config)?);
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        let timestamp = chunk.timestamp as f64;
+        let decoder = self.decoder()?;
+        // Timestamps are only used by symphonia for gapless trimming, which needs the
+        // stream's delay and padding from a container, so they are left out.
+        let packet = Packet::new_from_slice(0, 0, 0, &chunk.data);
+        let buffer = decoder.decode(&packet).map_err(|e| {
+            Exception::new(
+                ExceptionKind::DecodeError,
+                format!("failed to decode chunk at timestamp {}", chunk.timestamp),
+            )
+            .with_source(e)
+        })?;
+        let mut converted: AudioBuffer<f32> = buffer.make_equivalent();
+        buffer.convert(&mut converted);
+        let planes: Vec<Vec<f32>> = (0..converted.spec().channels.count())
+            .map(|ch| converted.chan(ch).to_vec())
+            .collect();
+        let decoded = pcm::f32_planar_audio_data_in(
+            &planes,
+            converted.spec().rate,
+            timestamp,
+            self.buffer_pool.as_ref(),
+        )?;
+        // Vorbis outputs nothing for the first packet, which only primes the overlap.
+        if decoded.number_of_frames > 0 {
+            self.ready.push_back(decoded);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<AudioData>, Exception> {
+        self.decoder()?;
+        Ok(self.ready.pop_front())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        // Every chunk is decoded as it is sent.
+        self.decoder()?;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.decoder()?.reset();
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.buffer_pool = Some(pool);
+    }
+}
+
+/// The symphonia codec for a WebCodecs codec string.
+fn symphonia_codec(codec: &str) -> Option<CodecType> {
+    match codec {
+        "flac" => Some(CODEC_TYPE_FLAC),
+        "mp3" | "mp4a.69" | "mp4a.6B" | "mp4a.40.34" => Some(CODEC_TYPE_MP3),
+        "vorbis" => Some(CODEC_TYPE_VORBIS),
+        _ => None,
+    }
+}
+
+fn open_symphonia_decoder(config: &AudioDecoderConfig) -> Result<Box<dyn Decoder>, Exception> {
+    let codec = symphonia_codec(&config.codec).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("no decoder found for codec {:?}", config.codec),
+        )
+    })?;
+
+    let mut params = CodecParameters::new();
+    params.for_codec(codec).with_sample_rate(config.sample_rate);
+    if let Some(extra_data) = symphonia_extra_data(codec, config)? {
+        params.with_extra_data(extra_data.into_boxed_slice());
+    }
+    symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("failed to open decoder for codec {:?}", config.codec),
+            )
+            .with_source(e)
+        })
+}
+
+/// The setup data symphonia expects for `codec`: the STREAMINFO block of FLAC, and the
+/// identification and setup headers of Vorbis.
+fn symphonia_extra_data(
+    codec: CodecType,
+    config: &AudioDecoderConfig,
+) -> Result<Option<Vec<u8>>, Exception> {
+    if codec == CODEC_TYPE_MP3 {
+        return Ok(None);
+    }
+    let description = config.description.as_deref().ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("codec {:?} requires a description", config.codec),
+        )
+    })?;
+    if codec == CODEC_TYPE_FLAC {
+        return Ok(Some(bitstream::flac_stream_info(description)?.to_vec()));
+    }
+    match bitstream::split_xiph_lacing(description)?.as_slice() {
+        [identification, _comment, setup] => Ok(Some([*identification, *setup].concat())),
+        _ => Err(Exception::new(
+            ExceptionKind::DataError,
+            "Vorbis description does not hold three headers",
+        )),
+    }
+}
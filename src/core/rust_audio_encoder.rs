@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+
+use flacenc::{
+    bitsink::ByteSink,
+    component::{BitRepr, StreamInfo},
+    config,
+    error::{EncodeError, Verified, Verify},
+    source::{Fill, FrameBuf},
+};
+
+use crate::{
+    bitstream,
+    codec::{AudioDecoderConfig, AudioEncoderConfig, Exception, ExceptionKind},
+    core::{backend::CodecBackend, pcm},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+};
+
+/// The sample size FLAC is encoded at.
+const FLAC_BITS_PER_SAMPLE: usize = 16;
+
+/// A FLAC encoder backed by flacenc, whose chunks hold one FLAC frame each.
+#[derive(Default)]
+pub struct RustAudioEncoder {
+    encoder: Option<Encoder>,
+}
+
+struct Encoder {
+    config: AudioEncoderConfig,
+    flac: FlacEncoder,
+    /// The decoder config last reported in output metadata.
+    active_output_config: Option<AudioDecoderConfig>,
+    /// Chunks that have not been received yet.
+    ready: VecDeque<(EncodedAudioChunk, EncodedAudioChunkMetadata)>,
+}
+
+impl RustAudioEncoder {
+    /// Whether `codec` can be encoded.
+    pub fn supports(codec: &str) -> bool {
+        codec == "flac"
+    }
+
+    fn encoder(&mut self) -> Result<&mut Encoder, Exception> {
+        self.encoder.as_mut().ok_or_else(|| {
+            Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+        })
+    }
+}
+
+impl CodecBackend for RustAudioEncoder {
+    type Config = AudioEncoderConfig;
+    type Input = AudioData;
+    type Output = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
+    fn configure(&mut self, config: &AudioEncoderConfig) -> Result<(), Exception> {
+        if !Self::supports(&config.codec) {
+            return Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no encoder found for codec {:?}", config.codec),
+            ));
+        }
+        self.encoder = Some(Encoder {
+            config: config.clone(),
+            flac: FlacEncoder::new(config)?,
+            active_output_config: None,
+            ready: VecDeque::new(),
+        });
+        Ok(())
+    }
+
+    fn send(&mut self, data: AudioData) -> Result<(), Exception> {
+        self.encoder()?.encode(&data)
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        Ok(self.encoder()?.ready.pop_front())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        let encoder = self.encoder()?;
+        let chunks = encoder.flac.drain()?;
+        encoder.push_chunks(chunks);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        let encoder = self.encoder()?;
+        encoder.flac.reset();
+        encoder.ready.clear();
+        Ok(())
+    }
+}
+
+impl Encoder {
+    fn encode(&mut self, data: &AudioData) -> Result<(), Exception> {
+        if data.sample_rate as u32 != self.config.sample_rate
+            || data.number_of_channels != self.config.number_of_channels
+        {
+            return Err(Exception::new(
+                ExceptionKind::EncodingError,
+                format!(
+                    "AudioData with {} channels at {} Hz does not match the encoder config",
+                    data.number_of_channels, data.sample_rate
+                ),
+            ));
+        }
+        let planes = pcm::f32_planes(data)?;
+        let chunks = self.flac.encode(&planes, data.timestamp)?;
+        self.push_chunks(chunks);
+        Ok(())
+    }
+
+    fn push_chunks(&mut self, chunks: Vec<EncodedAudioChunk>) {
+        for chunk in chunks {
+            let metadata = self.metadata();
+            self.ready.push_back((chunk, metadata));
+        }
+    }
+
+    /// Metadata for the next chunk, carrying the decoder config if it has not been reported.
+    fn metadata(&mut self) -> EncodedAudioChunkMetadata {
+        let decoder_config = AudioDecoderConfig {
+            codec: self.config.codec.clone(),
+            sample_rate: self.config.sample_rate,
+            number_of_channels: self.config.number_of_channels,
+            description: Some(self.flac.description()),
+        };
+        if self.active_output_config.as_ref() == Some(&decoder_config) {
+            return EncodedAudioChunkMetadata::default();
+        }
+        self.active_output_config = Some(decoder_config.clone());
+        EncodedAudioChunkMetadata {
+            decoder_config: Some(decoder_config),
+        }
+    }
+}
+
+/// Encodes 16 bit FLAC frames of flacenc's default block size.
+struct FlacEncoder {
+    config: Verified<config::Encoder>,
+    stream_info: StreamInfo,
+    frame: FrameBuf,
+    sample_rate: u32,
+    /// Pending samples of each channel.
+    pending: Vec<Vec<i32>>,
+    /// Timestamp of the first pending sample, in samples.
+    next_pts: Option<i64>,
+    frame_number: usize,
+}
+
+impl FlacEncoder {
+    fn new(config: &AudioEncoderConfig) -> Result<Self, Exception> {
+        let unsupported = |e: flacenc::error::VerifyError| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!(
+                    "FLAC cannot encode {} channels at {} Hz",
+                    config.number_of_channels, config.sample_rate
+                ),
+            )
+            .with_source(e)
+        };
+        let encoder_config = config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, e)| unsupported(e))?;
+        let block_size = encoder_config.block_size;
+        let channels = config.number_of_channels as usize;
+        let mut stream_info =
+            StreamInfo::new(config.sample_rate as usize, channels, FLAC_BITS_PER_SAMPLE)
+                .map_err(unsupported)?;
+        stream_info
+            .set_block_sizes(block_size, block_size)
+            .map_err(unsupported)?;
+        // Unknown, as the frames are handed out as they are encoded.
+        stream_info.set_frame_sizes(0, 0).map_err(unsupported)?;
+
+        Ok(Self {
+            frame: FrameBuf::with_size(channels, block_size).map_err(unsupported)?,
+            config: encoder_config,
+            stream_info,
+            sample_rate: config.sample_rate,
+            pending: vec![Vec::new(); channels],
+            next_pts: None,
+            frame_number: 0,
+        })
+    }
+
+    /// The "fLaC" magic and STREAMINFO block.
+    fn description(&self) -> Vec<u8> {
+        let mut sink = ByteSink::new();
+        // Writing to memory does not fail, and the stream info was verified when it was set.
+        let _ = self.stream_info.write(&mut sink);
+        bitstream::flac_description(sink.as_slice())
+    }
+
+    /// Queues `planes` and encodes every full block.
+    fn encode(
+        &mut self,
+        planes: &[Vec<f32>],
+        timestamp: f64,
+    ) -> Result<Vec<EncodedAudioChunk>, Exception> {
+        if self.next_pts.is_none() {
+            let rate = self.sample_rate as f64;
+            self.next_pts = Some((timestamp * rate / 1_000_000.0).round() as i64);
+        }
+        let max = (1 << (FLAC_BITS_PER_SAMPLE - 1)) as f32;
+        for (pending, plane) in self.pending.iter_mut().zip(planes) {
+            pending.extend(
+                plane
+                    .iter()
+                    .map(|sample| (sample * max).round().clamp(-max, max - 1.0) as i32),
+            );
+        }
+
+        let block_size = self.config.block_size;
+        let mut chunks = Vec::new();
+        while self.pending_samples() >= block_size {
+            chunks.push(self.encode_pending(block_size)?);
+        }
+        Ok(chunks)
+    }
+
+    /// Encodes the remaining samples as a last, shorter frame.
+    fn drain(&mut self) -> Result<Vec<EncodedAudioChunk>, Exception> {
+        match self.pending_samples() {
+            0 => Ok(Vec::new()),
+            samples => Ok(vec![self.encode_pending(samples)?]),
+        }
+    }
+
+    fn reset(&mut self) {
+        for pending in &mut self.pending {
+            pending.clear();
+        }
+        self.next_pts = None;
+        self.frame_number = 0;
+    }
+
+    fn pending_samples(&self) -> usize {
+        self.pending.first().map_or(0, Vec::len)
+    }
+
+    /// Encodes the first `samples` pending samples as one frame.
+    fn encode_pending(&mut self, samples: usize) -> Result<EncodedAudioChunk, Exception> {
+        let mut interleaved = Vec::with_capacity(samples * self.pending.len());
+        for i in 0..samples {
+            interleaved.extend(self.pending.iter().map(|pending| pending[i]));
+        }
+        for pending in &mut self.pending {
+            pending.drain(..samples);
+        }
+
+        let encode_error =
+            || Exception::new(ExceptionKind::EncodingError, "failed to encode frame");
+        self.frame.resize(samples);
+        // Filling a frame buffer only fails for more samples than it holds.
+        self.frame
+            .fill_interleaved(&interleaved)
+            .map_err(|_| encode_error())?;
+        let frame = flacenc::encode_fixed_size_frame(
+            &self.config,
+            &self.frame,
+            self.frame_number,
+            &self.stream_info,
+        )
+        .map_err(|e| match e {
+            EncodeError::Config(e) => encode_error().with_source(e),
+            _ => encode_error(),
+        })?;
+        let mut sink = ByteSink::new();
+        frame.write(&mut sink).map_err(|e| {
+            Exception::new(ExceptionKind::EncodingError, "failed to write frame").with_source(e)
+        })?;
+
+        let pts = self.next_pts.unwrap_or(0);
+        self.next_pts = Some(pts + samples as i64);
+        self.frame_number += 1;
+        let rate = self.sample_rate as i64;
+        Ok(EncodedAudioChunk {
+            data: sink.into_inner().into(),
+            timestamp: pts * 1_000_000 / rate,
+            duration: Some(samples as u64 * 1_000_000 / rate as u64),
+            is_key: true,
+        })
+    }
+}
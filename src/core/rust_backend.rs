@@ -0,0 +1,27 @@
+//! The pure-Rust backend, for builds without ffmpeg: it decodes FLAC, MP3 and Vorbis and
+//! encodes FLAC. PCM, u-law and a-law are handled by the PCM backend in every build.
+
+use super::{
+    backend::{AudioDecoderBackend, AudioEncoderBackend, BackendProvider},
+    rust_audio_decoder::RustAudioDecoder,
+    rust_audio_encoder::RustAudioEncoder,
+};
+
+/// Provides the pure-Rust codec implementations.
+pub struct RustBackend;
+
+impl BackendProvider for RustBackend {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn audio_decoder(&self, codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        RustAudioDecoder::supports(codec)
+            .then(|| Box::new(RustAudioDecoder::default()) as Box<AudioDecoderBackend>)
+    }
+
+    fn audio_encoder(&self, codec: &str) -> Option<Box<AudioEncoderBackend>> {
+        RustAudioEncoder::supports(codec)
+            .then(|| Box::new(RustAudioEncoder::default()) as Box<AudioEncoderBackend>)
+    }
+}
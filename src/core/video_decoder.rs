@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use ffmpeg_next::{codec::Id, format::Pixel};
+
+use crate::{
+    bitstream::{AvcDecoderConfigurationRecord, HevcDecoderConfigurationRecord},
+    codec::{EncodedVideoChunk, Exception, ExceptionKind, VideoDecoderConfig, VideoFrame},
+    core::{
+        backend::CodecBackend,
+        buffer_pool::BufferPool,
+        ffmpeg,
+        ffmpeg_backend::FfmpegCodec,
+        hardware::{self, Backend, HardwareDevice},
+        video_frame::{self, FrameConverter},
+    },
+};
+
+/// An ffmpeg video decoder.
+///
+/// H.264 and HEVC chunks are length-prefixed when the config has a `description` (the
+/// avcC/hvcC record) and Annex B otherwise; ffmpeg reads either.
+///
+/// On a hardware device, frames are downloaded to memory before they are output.
+pub struct VideoDecoderImpl {
+    decoder: ffmpeg_next::decoder::Video,
+    converter: FrameConverter,
+    /// Durations of the chunks whose frames have not come out yet, by timestamp.
+    durations: HashMap<i64, u64>,
+    /// The pool the pixel buffers of outputs are drawn from, if any.
+    pool: Option<BufferPool>,
+}
+
+impl VideoDecoderImpl {
+    pub fn new(config: &VideoDecoderConfig, pool: Option<BufferPool>) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+
+        let codec = find_video_decoder(&config.codec).ok_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no decoder found for codec {:?}", config.codec),
+            )
+        })?;
+
+        let mut last_error = None;
+        for backend in hardware::backends(config.hardware_acceleration) {
+            let device = match backend {
+                Backend::Software => None,
+                Backend::Hardware => match HardwareDevice::for_decoder(codec) {
+                    Some(device) => Some(device),
+                    None => continue,
+                },
+            };
+            match open_video_decoder(codec, config, device.as_ref()) {
+                Ok(decoder) => {
+                    return Ok(Self {
+                        decoder,
+                        converter: FrameConverter::default(),
+                        durations: HashMap::new(),
+                        pool,
+                    })
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!(
+                    "no hardware decoder for codec {:?} (device types: {})",
+                    config.codec,
+                    hardware::device_type_names().join(", ")
+                ),
+            )
+        }))
+    }
+
+    pub fn decode(&mut self, chunk: &EncodedVideoChunk) -> Result<(), Exception> {
+        // ffmpeg reads past the end of packets, so they need padding the chunk's buffer
+        // does not have, and the bytes are copied.
+        let mut packet = ffmpeg_next::Packet::copy(&chunk.data);
+        packet.set_pts(Some(chunk.timestamp));
+        packet.set_dts(Some(chunk.timestamp));
+        if chunk.is_key {
+            packet.set_flags(ffmpeg_next::packet::Flags::KEY);
+        }
+        if let Some(duration) = chunk.duration {
+            self.durations.insert(chunk.timestamp, duration);
+        }
+
+        self.decoder.send_packet(&packet).map_err(|e| {
+            Exception::new(
+                ExceptionKind::DecodeError,
+                format!("failed to decode chunk at timestamp {}", chunk.timestamp),
+            )
+            .with_source(e)
+        })
+    }
+
+    /// The next decoded frame, `None` when the decoder needs more chunks.
+    pub fn receive_frame(&mut self) -> Result<Option<VideoFrame>, Exception> {
+        let mut frame = ffmpeg_next::frame::Video::empty();
+        match self.decoder.receive_frame(&mut frame) {
+            Ok(()) => {}
+            Err(ffmpeg_next::Error::Eof) => return Ok(None),
+            Err(ffmpeg_next::Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                return Ok(None)
+            }
+            Err(e) => {
+                return Err(
+                    Exception::new(ExceptionKind::DecodeError, "failed to decode frame")
+                        .with_source(e),
+                )
+            }
+        }
+        let timestamp = frame.timestamp().or(frame.pts()).unwrap_or(0);
+        let duration = self.durations.remove(&timestamp);
+        if hardware::is_hardware_frame(&frame) {
+            frame = hardware::download(&frame)?;
+        }
+        let video_frame = if video_frame::format_name(frame.format()).is_some() {
+            video_frame::from_ffmpeg_frame(frame, timestamp, duration, self.pool.as_ref())?
+        } else {
+            let converted =
+                self.converter
+                    .convert(&frame, Pixel::YUV420P, frame.width(), frame.height())?;
+            video_frame::from_ffmpeg_frame(converted, timestamp, duration, self.pool.as_ref())?
+        };
+        Ok(Some(video_frame))
+    }
+
+    /// Lets the decoder output every frame it holds.
+    pub fn drain(&mut self) -> Result<(), Exception> {
+        self.decoder.send_eof().map_err(|e| {
+            Exception::new(ExceptionKind::DecodeError, "failed to drain decoder").with_source(e)
+        })
+    }
+
+    /// Readies the decoder for the next key chunk.
+    pub fn reset(&mut self) {
+        self.decoder.flush();
+        self.durations.clear();
+    }
+}
+
+impl CodecBackend for FfmpegCodec<VideoDecoderImpl> {
+    type Config = VideoDecoderConfig;
+    type Input = EncodedVideoChunk;
+    type Output = VideoFrame;
+
+    fn configure(&mut self, config: &VideoDecoderConfig) -> Result<(), Exception> {
+        self.open(VideoDecoderImpl::new(config, self.pool())?);
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedVideoChunk) -> Result<(), Exception> {
+        self.opened()?.decode(&chunk)
+    }
+
+    fn receive(&mut self) -> Result<Option<VideoFrame>, Exception> {
+        self.opened()?.receive_frame()
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.opened()?.drain()
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.opened()?.reset();
+        Ok(())
+    }
+
+    fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.set_pool(pool);
+    }
+}
+
+/// Opens `codec` for `config`, on `device` if given.
+fn open_video_decoder(
+    codec: ffmpeg_next::Codec,
+    config: &VideoDecoderConfig,
+    device: Option<&HardwareDevice>,
+) -> Result<ffmpeg_next::decoder::Video, Exception> {
+    let mut context = ffmpeg_next::codec::Context::new_with_codec(codec);
+    if let Some(description) = &config.description {
+        // Reject a description that is not the record ffmpeg expects up front, rather than
+        // failing on every chunk.
+        match codec.id() {
+            Id::H264 => {
+                AvcDecoderConfigurationRecord::parse(description)?;
+            }
+            Id::HEVC => {
+                HevcDecoderConfigurationRecord::parse(description)?;
+            }
+            _ => {}
+        }
+        ffmpeg::set_extradata(&mut context, description);
+    }
+    if let Some(device) = device {
+        device.attach(&mut context);
+    }
+    let mut decoder = context.decoder();
+    decoder.set_packet_time_base(ffmpeg::MICROSECONDS);
+    decoder.video().map_err(|e| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("failed to open decoder for codec {:?}", config.codec),
+        )
+        .with_source(e)
+    })
+}
+
+/// Looks up an ffmpeg decoder by WebCodecs codec string or ffmpeg decoder name.
+pub fn find_video_decoder(codec: &str) -> Option<ffmpeg_next::Codec> {
+    let id = match codec.split('.').next().unwrap_or_default() {
+        "avc1" | "avc3" => Id::H264,
+        "hvc1" | "hev1" => Id::HEVC,
+        "vp8" => Id::VP8,
+        "vp09" => Id::VP9,
+        "av01" => {
+            return ffmpeg_next::codec::decoder::find_by_name("libdav1d")
+                .or_else(|| ffmpeg_next::codec::decoder::find(Id::AV1))
+        }
+        _ => return ffmpeg_next::codec::decoder::find_by_name(codec),
+    };
+    ffmpeg_next::codec::decoder::find(id)
+}
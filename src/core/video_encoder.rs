@@ -0,0 +1,810 @@
+use std::collections::BTreeMap;
+
+use ffmpeg_next::{codec::Id, format::Pixel};
+
+use crate::{
+    bitstream::{
+        annexb_to_length_prefixed, av1_temporal_id, AvcDecoderConfigurationRecord, BitstreamFormat,
+        HevcDecoderConfigurationRecord,
+    },
+    codec::{
+        AlphaOption, EncodedVideoChunk, EncodedVideoChunkMetadata, Exception, ExceptionKind,
+        HardwareAcceleration, LatencyMode, SvcOutputMetadata, VideoDecoderConfig,
+        VideoEncoderBitrateMode, VideoEncoderConfig, VideoEncoderEncodeOptions, VideoFrame,
+    },
+    core::{
+        backend::CodecBackend,
+        ffmpeg,
+        ffmpeg_backend::FfmpegCodec,
+        hardware::{self, Backend, HardwareFrames},
+        video_frame::{self, FrameConverter},
+    },
+};
+
+/// An ffmpeg video encoder.
+///
+/// H.264 and HEVC come out of ffmpeg as Annex B. In the "avc"/"hevc" format the parameter
+/// sets go into an avcC/hvcC `description` and the chunks are converted to length-prefixed
+/// NAL units.
+///
+/// Hardware encoders that only read frames on their device, such as the VAAPI ones, get
+/// every frame uploaded first.
+pub struct VideoEncoderImpl {
+    encoder: ffmpeg_next::encoder::Video,
+    codec: ffmpeg_next::Codec,
+    /// The device frames of an encoder that cannot read frames from memory.
+    hardware_frames: Option<HardwareFrames>,
+    config: VideoEncoderConfig,
+    converter: FrameConverter,
+    /// The frames whose chunks have not come out yet, by the order they were sent in.
+    /// Frames may share a timestamp, so a chunk belongs to the earliest one with its own.
+    pending: BTreeMap<u64, PendingFrame>,
+    /// The sequence number of the next frame sent.
+    next_sequence: u64,
+    /// The quantizer an encoder that reads it only when opened was opened with.
+    quantizer: Option<u32>,
+    /// How the encoder was configured to lay out temporal layers, `None` without a
+    /// scalability mode.
+    temporal_layering: Option<TemporalLayering>,
+    /// Frames sent since the encoder was opened, which is where its layer pattern starts.
+    /// The first one is always encoded as a key frame.
+    frames_sent: usize,
+    /// The decoder config last reported in output metadata.
+    active_output_config: Option<VideoDecoderConfig>,
+}
+
+impl VideoEncoderImpl {
+    pub fn new(config: &VideoEncoderConfig) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+
+        let (software, hardware) = find_video_encoders(&config.codec);
+        let mut last_error = None;
+        for backend in hardware::backends(config.hardware_acceleration) {
+            let codecs = match backend {
+                Backend::Software => &software,
+                Backend::Hardware => &hardware,
+            };
+            // Hardware encoders fail to open without their device, so try each in turn.
+            for &codec in codecs {
+                match open_video_encoder(codec, config, None) {
+                    Ok((encoder, hardware_frames)) => {
+                        return Ok(Self {
+                            encoder,
+                            codec,
+                            hardware_frames,
+                            config: config.clone(),
+                            converter: FrameConverter::default(),
+                            pending: BTreeMap::new(),
+                            next_sequence: 0,
+                            quantizer: None,
+                            temporal_layering: temporal_layering(codec.name(), config),
+                            frames_sent: 0,
+                            active_output_config: None,
+                        })
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("no encoder found for codec {:?}", config.codec),
+            )
+        }))
+    }
+
+    /// Sends `frame` to the encoder; its chunk comes out of `receive_chunk`.
+    pub fn encode(
+        &mut self,
+        frame: &VideoFrame,
+        options: &VideoEncoderEncodeOptions,
+    ) -> Result<(), Exception> {
+        let video = video_frame::to_ffmpeg_frame(frame)?;
+        let format = match &self.hardware_frames {
+            Some(_) => HardwareFrames::SOFTWARE_FORMAT,
+            None => self.encoder.format(),
+        };
+        let (width, height) = (self.encoder.width(), self.encoder.height());
+        let mut video =
+            if video.format() == format && video.width() == width && video.height() == height {
+                video
+            } else {
+                self.converter.convert(&video, format, width, height)?
+            };
+        video.set_pts(Some(frame.timestamp));
+        if self.config.bitrate_mode == VideoEncoderBitrateMode::Quantizer {
+            if let Some((quantizer, _)) = options.quantizer(&self.config.codec) {
+                self.set_quantizer(quantizer, &mut video)?;
+            }
+        }
+        // Encoders start with a key frame anyway; forcing it makes sure the first chunk after
+        // configure or flush is a key chunk for every encoder.
+        if options.key_frame || self.frames_sent == 0 {
+            video.set_kind(ffmpeg_next::picture::Type::I);
+        }
+        let pattern = match self.temporal_layering {
+            Some(TemporalLayering::Pattern(pattern)) => pattern,
+            _ => temporal_pattern(1),
+        };
+        self.pending.insert(
+            self.next_sequence,
+            PendingFrame {
+                timestamp: frame.timestamp,
+                duration: frame.duration,
+                temporal_layer_id: pattern[self.frames_sent % pattern.len()],
+            },
+        );
+        self.next_sequence += 1;
+        self.frames_sent += 1;
+        if let Some(hardware_frames) = &self.hardware_frames {
+            video = hardware_frames.upload(&video)?;
+        }
+
+        self.encoder.send_frame(&video).map_err(|e| {
+            Exception::new(ExceptionKind::EncodingError, "failed to encode frame").with_source(e)
+        })
+    }
+
+    /// Lets the encoder output every chunk it holds.
+    pub fn drain(&mut self) -> Result<(), Exception> {
+        self.encoder.send_eof().map_err(|e| {
+            Exception::new(ExceptionKind::EncodingError, "failed to drain encoder").with_source(e)
+        })
+    }
+
+    /// Reopens the encoder once it has been drained, so that encoding can continue from a
+    /// key frame.
+    pub fn reset(&mut self) -> Result<(), Exception> {
+        (self.encoder, self.hardware_frames) =
+            open_video_encoder(self.codec, &self.config, self.quantizer)?;
+        self.pending.clear();
+        self.frames_sent = 0;
+        Ok(())
+    }
+
+    /// Applies the quantizer of the next frame, `video`, the way the encoder takes it.
+    fn set_quantizer(
+        &mut self,
+        quantizer: u32,
+        video: &mut ffmpeg_next::frame::Video,
+    ) -> Result<(), Exception> {
+        match quantizer_control(self.codec) {
+            QuantizerControl::Option(name) => {
+                ffmpeg::set_private_option(&mut self.encoder, name, quantizer as i64).map_err(|e| {
+                    Exception::new(ExceptionKind::EncodingError, "failed to set quantizer")
+                        .with_source(e)
+                })
+            }
+            QuantizerControl::Open if self.quantizer == Some(quantizer) => Ok(()),
+            // Nothing has been sent since the encoder was opened, so reopening it costs no
+            // extra key frame.
+            QuantizerControl::Open if self.frames_sent == 0 => {
+                self.quantizer = Some(quantizer);
+                self.reset()
+            }
+            QuantizerControl::Open => Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!(
+                    "{} cannot change the quantizer to {quantizer} before a flush",
+                    self.codec.name()
+                ),
+            )),
+            QuantizerControl::FrameQuality => {
+                ffmpeg::set_frame_quantizer(video, quantizer);
+                Ok(())
+            }
+        }
+    }
+
+    /// The next chunk, `None` when the encoder needs more frames.
+    pub fn receive_chunk(
+        &mut self,
+    ) -> Result<Option<(EncodedVideoChunk, EncodedVideoChunkMetadata)>, Exception> {
+        let mut packet = ffmpeg_next::Packet::empty();
+        match self.encoder.receive_packet(&mut packet) {
+            Ok(()) => {}
+            Err(ffmpeg_next::Error::Eof) => return Ok(None),
+            Err(ffmpeg_next::Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {
+                return Ok(None)
+            }
+            Err(e) => {
+                return Err(
+                    Exception::new(ExceptionKind::EncodingError, "failed to encode frame")
+                        .with_source(e),
+                )
+            }
+        }
+
+        let data = packet.data().unwrap_or_default();
+        let data = match bitstream_format(&self.config) {
+            Some(BitstreamFormat::LengthPrefixed) => annexb_to_length_prefixed(data),
+            _ => data.to_vec(),
+        };
+        let timestamp = packet.pts().unwrap_or(0);
+        let sequence = self
+            .pending
+            .iter()
+            .find(|(_, pending)| pending.timestamp == timestamp)
+            .map(|(&sequence, _)| sequence);
+        let pending = sequence.and_then(|sequence| self.pending.remove(&sequence));
+        let mut metadata = self.metadata()?;
+        let temporal_layer_id = match self.temporal_layering {
+            Some(TemporalLayering::Pattern(_)) => pending.map(|pending| pending.temporal_layer_id),
+            Some(TemporalLayering::Bitstream) => av1_temporal_id(&data).map(u32::from),
+            None => None,
+        };
+        metadata.svc =
+            temporal_layer_id.map(|temporal_layer_id| SvcOutputMetadata { temporal_layer_id });
+        let chunk = EncodedVideoChunk {
+            data: data.into(),
+            timestamp,
+            decode_timestamp: packet.dts(),
+            duration: pending.and_then(|pending| pending.duration),
+            is_key: packet.is_key(),
+        };
+        Ok(Some((chunk, metadata)))
+    }
+
+    /// Metadata for the next chunk, carrying the decoder config if it has not been reported.
+    fn metadata(&mut self) -> Result<EncodedVideoChunkMetadata, Exception> {
+        let decoder_config = VideoDecoderConfig {
+            codec: self.config.codec.clone(),
+            coded_width: Some(self.encoder.width()),
+            coded_height: Some(self.encoder.height()),
+            description: self.description()?,
+            hardware_acceleration: HardwareAcceleration::default(),
+        };
+        if self.active_output_config.as_ref() == Some(&decoder_config) {
+            return Ok(EncodedVideoChunkMetadata::default());
+        }
+        self.active_output_config = Some(decoder_config.clone());
+        Ok(EncodedVideoChunkMetadata {
+            decoder_config: Some(decoder_config),
+            ..Default::default()
+        })
+    }
+
+    /// The avcC/hvcC record in the length-prefixed format, built from the parameter sets
+    /// the encoder put in its extradata.
+    fn description(&self) -> Result<Option<Vec<u8>>, Exception> {
+        if bitstream_format(&self.config) != Some(BitstreamFormat::LengthPrefixed) {
+            return Ok(None);
+        }
+        let Some(extradata) = ffmpeg::extradata(&self.encoder) else {
+            return Ok(None);
+        };
+        // Some encoders already write the record rather than Annex B.
+        if extradata.first() == Some(&1) {
+            return Ok(Some(extradata));
+        }
+        let record = match self.codec.id() {
+            Id::HEVC => HevcDecoderConfigurationRecord::from_annexb(&extradata)?.to_bytes(),
+            _ => AvcDecoderConfigurationRecord::from_annexb(&extradata)?.to_bytes(),
+        };
+        Ok(Some(record))
+    }
+}
+
+impl CodecBackend for FfmpegCodec<VideoEncoderImpl> {
+    type Config = VideoEncoderConfig;
+    type Input = (VideoFrame, VideoEncoderEncodeOptions);
+    type Output = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+    fn configure(&mut self, config: &VideoEncoderConfig) -> Result<(), Exception> {
+        self.open(VideoEncoderImpl::new(config)?);
+        Ok(())
+    }
+
+    fn send(&mut self, (frame, options): Self::Input) -> Result<(), Exception> {
+        self.opened()?.encode(&frame, &options)
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        self.opened()?.receive_chunk()
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.opened()?.drain()
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.opened()?.reset()
+    }
+}
+
+/// How an encoder takes the quantizer of each frame in `Quantizer` bitrate mode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum QuantizerControl {
+    /// A private option the ffmpeg wrapper rereads before every frame.
+    Option(&'static str),
+    /// The wrapper reads the quantizer range only when opening and has no control to change
+    /// it, so the encoder is opened with the range pinned to the quantizer of the first frame
+    /// after configure or flush. Reopening it later would start over with a key frame, so
+    /// another quantizer before the next flush is not supported.
+    Open,
+    /// The `quality` of each frame, which ffmpeg's own encoders read.
+    FrameQuality,
+}
+
+fn quantizer_control(codec: ffmpeg_next::Codec) -> QuantizerControl {
+    match codec.name() {
+        "libx264" => QuantizerControl::Option("qp"),
+        "libvpx" | "libvpx-vp9" | "libaom-av1" | "libsvtav1" => QuantizerControl::Open,
+        _ => QuantizerControl::FrameQuality,
+    }
+}
+
+/// Where the temporal layer of each chunk comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TemporalLayering {
+    /// The encoder was given this pattern of layer ids, which it repeats from the first
+    /// frame after it is opened.
+    Pattern(&'static [u32]),
+    /// The encoder lays out the layers itself and writes their ids in the OBU extension
+    /// headers.
+    Bitstream,
+}
+
+/// How the encoder named `codec` lays out the temporal layers of `config`, `None` if it
+/// was not configured for them. Matches the options set by `encoder_options`, which
+/// rejects the rest.
+fn temporal_layering(codec: &str, config: &VideoEncoderConfig) -> Option<TemporalLayering> {
+    config.scalability_mode.as_ref()?;
+    match (config.temporal_layers()?, codec) {
+        (layers, "libvpx" | "libvpx-vp9") | (layers @ 1, _) => {
+            Some(TemporalLayering::Pattern(temporal_pattern(layers)))
+        }
+        (_, "libsvtav1") => Some(TemporalLayering::Bitstream),
+        _ => None,
+    }
+}
+
+/// What is known about a frame until its chunk comes out of the encoder.
+#[derive(Clone, Copy)]
+struct PendingFrame {
+    timestamp: i64,
+    duration: Option<u64>,
+    temporal_layer_id: u32,
+}
+
+/// The temporal layer of each frame in one period of the L1T1, L1T2 and L1T3 patterns.
+fn temporal_pattern(layers: u32) -> &'static [u32] {
+    match layers {
+        2 => &[0, 1],
+        3 => &[0, 2, 1, 2],
+        _ => &[0],
+    }
+}
+
+/// The bitstream format of H.264 and HEVC configs, `None` for other codecs.
+fn bitstream_format(config: &VideoEncoderConfig) -> Option<BitstreamFormat> {
+    match config.codec.split('.').next().unwrap_or_default() {
+        "avc1" | "avc3" => Some(
+            config
+                .avc
+                .as_ref()
+                .map(|avc| avc.format)
+                .unwrap_or_default(),
+        ),
+        "hvc1" | "hev1" => Some(
+            config
+                .hevc
+                .as_ref()
+                .map(|hevc| hevc.format)
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+/// The ffmpeg encoders for a WebCodecs codec string, software and hardware ones, in order of
+/// preference. An ffmpeg encoder name is used as is, as a software encoder.
+pub fn find_video_encoders(codec: &str) -> (Vec<ffmpeg_next::Codec>, Vec<ffmpeg_next::Codec>) {
+    let (software, hardware, id): (&[&str], &[&str], Id) =
+        match codec.split('.').next().unwrap_or_default() {
+            "avc1" | "avc3" => (
+                &["libx264", "libopenh264"],
+                &[
+                    "h264_nvenc",
+                    "h264_qsv",
+                    "h264_vaapi",
+                    "h264_videotoolbox",
+                    "h264_amf",
+                ],
+                Id::H264,
+            ),
+            "hvc1" | "hev1" => (
+                &["libx265"],
+                &[
+                    "hevc_nvenc",
+                    "hevc_qsv",
+                    "hevc_vaapi",
+                    "hevc_videotoolbox",
+                    "hevc_amf",
+                ],
+                Id::HEVC,
+            ),
+            "vp8" => (&["libvpx"], &["vp8_vaapi"], Id::VP8),
+            "vp09" => (&["libvpx-vp9"], &["vp9_qsv", "vp9_vaapi"], Id::VP9),
+            "av01" => (
+                &["libsvtav1", "libaom-av1", "librav1e"],
+                &["av1_nvenc", "av1_qsv", "av1_vaapi", "av1_amf"],
+                Id::AV1,
+            ),
+            _ => {
+                return (
+                    ffmpeg_next::encoder::find_by_name(codec)
+                        .into_iter()
+                        .collect(),
+                    Vec::new(),
+                )
+            }
+        };
+    let find = |names: &[&str]| -> Vec<ffmpeg_next::Codec> {
+        names
+            .iter()
+            .filter_map(|name| ffmpeg_next::encoder::find_by_name(name))
+            .collect()
+    };
+    let mut software_encoders = find(software);
+    if software_encoders.is_empty() {
+        // Whatever encoder ffmpeg was built with, unless it is one of the hardware ones.
+        software_encoders.extend(
+            ffmpeg_next::encoder::find(id).filter(|encoder| !hardware.contains(&encoder.name())),
+        );
+    }
+    (software_encoders, find(hardware))
+}
+
+/// Opens `codec` for `config`, with `quantizer` as the quantizer of encoders that read it
+/// only when opened.
+fn open_video_encoder(
+    codec: ffmpeg_next::Codec,
+    config: &VideoEncoderConfig,
+    quantizer: Option<u32>,
+) -> Result<(ffmpeg_next::encoder::Video, Option<HardwareFrames>), Exception> {
+    let not_supported = |message: String| Exception::new(ExceptionKind::NotSupportedError, message);
+    let unsupported = |e: ffmpeg_next::Error| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("failed to open encoder for codec {:?}", config.codec),
+        )
+        .with_source(e)
+    };
+
+    // Prefer I420, the most common `VideoFrame` format, or I420A to keep the alpha channel.
+    // Encoders that read no format from memory get frames uploaded to their device.
+    let formats: Vec<Pixel> = codec
+        .video()
+        .ok()
+        .and_then(|video| video.formats())
+        .map(|formats| {
+            formats
+                .filter(|format| !hardware::is_hardware_format(*format))
+                .collect()
+        })
+        .unwrap_or_default();
+    let hardware_frames = if formats.is_empty() && codec_has_hardware_formats(codec) {
+        Some(HardwareFrames::for_encoder(
+            codec,
+            config.width,
+            config.height,
+        )?)
+    } else {
+        None
+    };
+    let format = match (config.alpha, &hardware_frames, formats.first()) {
+        (AlphaOption::Keep, None, _) if formats.contains(&Pixel::YUVA420P) => Pixel::YUVA420P,
+        (AlphaOption::Keep, ..) => {
+            return Err(not_supported(format!(
+                "{} cannot encode an alpha channel",
+                codec.name()
+            )))
+        }
+        (AlphaOption::Discard, Some(hardware_frames), _) => hardware_frames.format(),
+        (AlphaOption::Discard, None, Some(first)) if !formats.contains(&Pixel::YUV420P) => *first,
+        (AlphaOption::Discard, None, _) => Pixel::YUV420P,
+    };
+
+    let mut context = ffmpeg_next::codec::Context::new_with_codec(codec);
+    if let Some(hardware_frames) = &hardware_frames {
+        hardware_frames.attach(&mut context);
+    }
+    let mut encoder = context.encoder().video().map_err(unsupported)?;
+    encoder.set_width(config.width);
+    encoder.set_height(config.height);
+    encoder.set_format(format);
+    // Frame timestamps are in microseconds, so chunks come out in microseconds too.
+    encoder.set_time_base(ffmpeg::MICROSECONDS);
+    if let Some(framerate) = config.framerate {
+        encoder.set_frame_rate(Some(ffmpeg_next::Rational::from(framerate)));
+    }
+    let quantizer_mode = config.bitrate_mode == VideoEncoderBitrateMode::Quantizer;
+    if let Some(bitrate) = config.bitrate.filter(|_| !quantizer_mode) {
+        encoder.set_bit_rate(bitrate as usize);
+        if config.bitrate_mode == VideoEncoderBitrateMode::Constant {
+            encoder.set_max_bit_rate(bitrate as usize);
+        }
+    }
+    if config.latency_mode == LatencyMode::Realtime {
+        // B-frames hold back the frames they depend on.
+        encoder.set_max_b_frames(0);
+    }
+    if let Some(interval) = config.keyframe_interval {
+        encoder.set_gop(interval);
+    }
+    let mut flags = ffmpeg_next::codec::Flags::empty();
+    // Keep the parameter sets out of the chunks; they go into the description instead.
+    if bitstream_format(config) == Some(BitstreamFormat::LengthPrefixed) {
+        flags |= ffmpeg_next::codec::Flags::GLOBAL_HEADER;
+    }
+    match (quantizer_mode, quantizer_control(codec), quantizer) {
+        (true, QuantizerControl::FrameQuality, _) => {
+            flags |= ffmpeg_next::codec::Flags::QSCALE;
+        }
+        (true, QuantizerControl::Open, Some(quantizer)) => {
+            encoder.set_qmin(quantizer as i32);
+            encoder.set_qmax(quantizer as i32);
+        }
+        _ => {}
+    }
+    encoder.set_flags(flags);
+
+    let mut options = encoder_options(codec, config)?;
+    if quantizer_mode {
+        match (quantizer_control(codec), quantizer) {
+            // Constant QP from the start; each frame then sets its own.
+            (QuantizerControl::Option(name), _) => options.set(name, "23"),
+            (QuantizerControl::Open, Some(quantizer)) => {
+                let name = if codec.name() == "libsvtav1" {
+                    "qp"
+                } else {
+                    "crf"
+                };
+                options.set(name, &quantizer.to_string());
+            }
+            _ => {}
+        }
+    }
+    let encoder = encoder.open_as_with(codec, options).map_err(unsupported)?;
+    Ok((encoder, hardware_frames))
+}
+
+/// Whether `codec` lists pixel formats, all of them on a hardware device.
+fn codec_has_hardware_formats(codec: ffmpeg_next::Codec) -> bool {
+    codec
+        .video()
+        .ok()
+        .and_then(|video| video.formats())
+        .is_some_and(|mut formats| formats.any(hardware::is_hardware_format))
+}
+
+/// The ffmpeg options for the latency mode, bitrate mode, scalability mode and content hint
+/// of `config`, for the encoders that have them.
+fn encoder_options(
+    codec: ffmpeg_next::Codec,
+    config: &VideoEncoderConfig,
+) -> Result<ffmpeg_next::Dictionary<'static>, Exception> {
+    let mut options = ffmpeg_next::Dictionary::new();
+    let realtime = config.latency_mode == LatencyMode::Realtime;
+    let text = config.content_hint.as_deref() == Some("text");
+    let constant_bitrate = config
+        .bitrate
+        .filter(|_| config.bitrate_mode == VideoEncoderBitrateMode::Constant);
+    if let Some(bitrate) = constant_bitrate {
+        options.set("minrate", &bitrate.to_string());
+        options.set("bufsize", &bitrate.to_string());
+    }
+
+    match codec.name() {
+        "libx264" => {
+            let tune: Vec<&str> = [
+                text.then_some("stillimage"),
+                realtime.then_some("zerolatency"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if !tune.is_empty() {
+                options.set("tune", &tune.join(","));
+            }
+            if constant_bitrate.is_some() {
+                options.set("nal-hrd", "cbr");
+            }
+            // Forced key frames must be IDR frames, which decoders can start from.
+            options.set("forced-idr", "1");
+        }
+        "libx265" => {
+            if realtime {
+                options.set("tune", "zerolatency");
+            }
+            options.set("forced-idr", "1");
+        }
+        "h264_nvenc" | "hevc_nvenc" => options.set("forced-idr", "1"),
+        "h264_qsv" | "hevc_qsv" | "h264_amf" | "hevc_amf" => options.set("forced_idr", "1"),
+        // VAAPI, VideoToolbox and OpenH264 already encode every forced I frame as an IDR
+        // frame; VP8, VP9 and AV1 key frames need nothing more.
+        "libvpx" | "libvpx-vp9" => {
+            if realtime {
+                options.set("deadline", "realtime");
+                options.set("lag-in-frames", "0");
+            }
+            if text && codec.name() == "libvpx-vp9" {
+                options.set("tune-content", "screen");
+            }
+        }
+        "libaom-av1" if realtime => {
+            options.set("usage", "realtime");
+            options.set("lag-in-frames", "0");
+        }
+        _ => {}
+    }
+
+    match (config.temporal_layers(), codec.name()) {
+        (Some(1), _) => {}
+        (Some(layers), "libvpx" | "libvpx-vp9") => {
+            options.set(
+                "ts-parameters",
+                &temporal_layer_parameters(layers, config.bitrate),
+            );
+        }
+        // Low-delay hierarchical prediction, which lays out L1T2 and L1T3 like libvpx.
+        (Some(layers), "libsvtav1") => options.set(
+            "svtav1-params",
+            &format!("pred-struct=1:hierarchical-levels={}", layers - 1),
+        ),
+        _ => {
+            return Err(Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!(
+                    "{} does not support scalability mode {:?}",
+                    codec.name(),
+                    config.scalability_mode.as_deref().unwrap_or_default()
+                ),
+            ))
+        }
+    }
+    Ok(options)
+}
+
+/// The libvpx `ts-parameters` of the L1T2 and L1T3 modes, splitting `bitrate` between the
+/// layers.
+fn temporal_layer_parameters(layers: u32, bitrate: Option<u64>) -> String {
+    // libvpx takes cumulative layer bitrates in kbit/s; ffmpeg's default is 200 kbit/s.
+    let kbps = bitrate.map_or(200, |bitrate| bitrate / 1000);
+    let (shares, decimators): (&[u64], &str) = match layers {
+        2 => (&[60, 100], "2,1"),
+        _ => (&[40, 60, 100], "4,2,1"),
+    };
+    let targets: Vec<String> = shares
+        .iter()
+        .map(|share| (kbps * share / 100).to_string())
+        .collect();
+    let pattern = temporal_pattern(layers);
+    let layer_ids: Vec<String> = pattern.iter().map(u32::to_string).collect();
+    format!(
+        "ts_number_layers={layers}:ts_target_bitrate={}:ts_rate_decimator={decimators}:\
+         ts_periodicity={}:ts_layer_id={}:ts_layering_mode={layers}",
+        targets.join(","),
+        pattern.len(),
+        layer_ids.join(","),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frame each frame of `pattern` predicts from, repeated over `frames` frames: the
+    /// latest frame of a lower layer, or of the base layer for the base layer, as the
+    /// libvpx layering modes do. `None` for the key frame.
+    fn references(pattern: &[u32], frames: usize) -> Vec<Option<usize>> {
+        let layer = |frame: usize| pattern[frame % pattern.len()];
+        (0..frames)
+            .map(|frame| {
+                (0..frame)
+                    .rev()
+                    .find(|&earlier| layer(earlier) < layer(frame).max(1))
+            })
+            .collect()
+    }
+
+    fn config(scalability_mode: Option<&str>) -> VideoEncoderConfig {
+        let mut config = VideoEncoderConfig::new("vp09.00.10.08", 640, 480);
+        config.scalability_mode = scalability_mode.map(str::to_string);
+        config
+    }
+
+    #[test]
+    fn patterns_repeat_the_layer_ids_of_each_mode() {
+        let ids = |layers| -> Vec<u32> {
+            let pattern = temporal_pattern(layers);
+            (0..8).map(|frame| pattern[frame % pattern.len()]).collect()
+        };
+        assert_eq!(ids(1), [0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(ids(2), [0, 1, 0, 1, 0, 1, 0, 1]);
+        assert_eq!(ids(3), [0, 2, 1, 2, 0, 2, 1, 2]);
+    }
+
+    #[test]
+    fn dropping_upper_layers_keeps_every_reference() {
+        assert_eq!(
+            references(temporal_pattern(2), 6),
+            [None, Some(0), Some(0), Some(2), Some(2), Some(4)]
+        );
+        assert_eq!(
+            references(temporal_pattern(3), 8),
+            [
+                None,
+                Some(0),
+                Some(0),
+                Some(2),
+                Some(0),
+                Some(4),
+                Some(4),
+                Some(6)
+            ]
+        );
+        for layers in 1..=3 {
+            let pattern = temporal_pattern(layers);
+            let references = references(pattern, 12);
+            for kept in 0..layers {
+                for (frame, reference) in references.iter().enumerate() {
+                    if let Some(reference) =
+                        reference.filter(|_| pattern[frame % pattern.len()] <= kept)
+                    {
+                        assert!(
+                            pattern[reference % pattern.len()] <= kept,
+                            "L1T{layers}, frame {frame}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn layer_parameters_match_the_pattern() {
+        assert_eq!(
+            temporal_layer_parameters(2, Some(1_000_000)),
+            "ts_number_layers=2:ts_target_bitrate=600,1000:ts_rate_decimator=2,1:\
+             ts_periodicity=2:ts_layer_id=0,1:ts_layering_mode=2"
+        );
+        assert_eq!(
+            temporal_layer_parameters(3, None),
+            "ts_number_layers=3:ts_target_bitrate=80,120,200:ts_rate_decimator=4,2,1:\
+             ts_periodicity=4:ts_layer_id=0,2,1,2:ts_layering_mode=3"
+        );
+        // Each decimator is how many frames of the pattern a layer and those below it share.
+        for (layers, decimators) in [(2, [2, 1].as_slice()), (3, [4, 2, 1].as_slice())] {
+            let pattern = temporal_pattern(layers);
+            for (layer, &decimator) in decimators.iter().enumerate() {
+                let frames = pattern.iter().filter(|&&id| id <= layer as u32).count();
+                assert_eq!(frames * decimator, pattern.len(), "L1T{layers}");
+            }
+        }
+    }
+
+    #[test]
+    fn only_configured_encoders_report_layers() {
+        assert_eq!(temporal_layering("libvpx-vp9", &config(None)), None);
+        assert_eq!(
+            temporal_layering("libvpx", &config(Some("L1T3"))),
+            Some(TemporalLayering::Pattern(&[0, 2, 1, 2]))
+        );
+        assert_eq!(
+            temporal_layering("libaom-av1", &config(Some("L1T1"))),
+            Some(TemporalLayering::Pattern(&[0]))
+        );
+        assert_eq!(
+            temporal_layering("libsvtav1", &config(Some("L1T2"))),
+            Some(TemporalLayering::Bitstream)
+        );
+        assert_eq!(temporal_layering("libaom-av1", &config(Some("L1T2"))), None);
+        assert_eq!(temporal_layering("libvpx-vp9", &config(Some("L2T2"))), None);
+    }
+}
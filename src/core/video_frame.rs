@@ -0,0 +1,197 @@
+use ffmpeg_next::{format::Pixel, software::scaling};
+
+use crate::{
+    codec::{plane_layout, Exception, ExceptionKind, VideoFrame},
+    data::buffer::SharedBuffer,
+};
+
+use super::{
+    buffer_pool::{BufferPool, PooledBuffer},
+    ffmpeg,
+};
+
+/// The ffmpeg pixel format of a `VideoFrame` format.
+pub fn pixel_format(format: &str) -> Option<Pixel> {
+    let pixel = match format {
+        "I420" => Pixel::YUV420P,
+        "I420A" => Pixel::YUVA420P,
+        "I422" => Pixel::YUV422P,
+        "I444" => Pixel::YUV444P,
+        "NV12" => Pixel::NV12,
+        "RGBA" => Pixel::RGBA,
+        "RGBX" => Pixel::RGBZ,
+        "BGRA" => Pixel::BGRA,
+        "BGRX" => Pixel::BGRZ,
+        _ => return None,
+    };
+    Some(pixel)
+}
+
+/// The `VideoFrame` format of an ffmpeg pixel format, if there is one.
+pub fn format_name(pixel: Pixel) -> Option<&'static str> {
+    let name = match pixel {
+        Pixel::YUV420P => "I420",
+        Pixel::YUVA420P => "I420A",
+        Pixel::YUV422P => "I422",
+        Pixel::YUV444P => "I444",
+        Pixel::NV12 => "NV12",
+        Pixel::RGBA => "RGBA",
+        Pixel::RGBZ => "RGBX",
+        Pixel::BGRA => "BGRA",
+        Pixel::BGRZ => "BGRX",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Copies a `VideoFrame` into an ffmpeg frame of the same format and size.
+pub fn to_ffmpeg_frame(frame: &VideoFrame) -> Result<ffmpeg_next::frame::Video, Exception> {
+    let unsupported = || {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("unsupported VideoFrame format {:?}", frame.format),
+        )
+    };
+    let pixel = pixel_format(&frame.format).ok_or_else(unsupported)?;
+    let layout = plane_layout(&frame.format, frame.coded_width, frame.coded_height)
+        .ok_or_else(unsupported)?;
+    let size: usize = layout.iter().map(|(row, rows)| row * rows).sum();
+    if frame.data.len() < size {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            "VideoFrame buffer is smaller than its format requires",
+        ));
+    }
+
+    let mut video = ffmpeg_next::frame::Video::new(pixel, frame.coded_width, frame.coded_height);
+    let mut offset = 0;
+    for (plane, (row_size, rows)) in layout.into_iter().enumerate() {
+        let stride = video.stride(plane);
+        let data = video.data_mut(plane);
+        for row in 0..rows {
+            data[row * stride..row * stride + row_size]
+                .copy_from_slice(&frame.data[offset..offset + row_size]);
+            offset += row_size;
+        }
+    }
+    video.set_pts(Some(frame.timestamp));
+    Ok(video)
+}
+
+/// Makes a tightly packed `VideoFrame` of an ffmpeg frame in one of the `VideoFrame`
+/// formats, copied into a buffer from `pool` if there is one. Without a pool, a frame whose
+/// rows and planes already follow one another without padding is shared rather than copied.
+pub fn from_ffmpeg_frame(
+    video: ffmpeg_next::frame::Video,
+    timestamp: i64,
+    duration: Option<u64>,
+    pool: Option<&BufferPool>,
+) -> Result<VideoFrame, Exception> {
+    let format = format_name(video.format()).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("no VideoFrame format for {:?}", video.format()),
+        )
+    })?;
+    let layout = plane_layout(format, video.width(), video.height()).unwrap_or_default();
+    let (coded_width, coded_height) = (video.width(), video.height());
+    let unpadded = layout
+        .iter()
+        .enumerate()
+        .all(|(plane, &(row_size, _))| video.stride(plane) == row_size);
+    let data = match pool {
+        // Outputs in the pool's buffers are what bounds the frames a decoder has outstanding.
+        Some(_) => copy_planes(&video, &layout, pool)?,
+        // Rows are padded to ffmpeg's alignment and decoders allocate every plane on its
+        // own, so this takes a width the alignment divides and, for frames straight from a
+        // decoder rather than converted ones, a single plane.
+        None if unpadded => {
+            let sizes: Vec<usize> = layout.iter().map(|(row, rows)| row * rows).collect();
+            match ffmpeg::FrameBuffer::new(video, &sizes) {
+                Ok(buffer) => SharedBuffer::from_owner(buffer),
+                Err(video) => copy_planes(&video, &layout, None)?,
+            }
+        }
+        None => copy_planes(&video, &layout, None)?,
+    };
+    Ok(VideoFrame {
+        format: format.to_string(),
+        coded_width,
+        coded_height,
+        timestamp,
+        duration,
+        data,
+    })
+}
+
+/// Copies the rows of every plane of `video`, of the sizes in `layout`, one after another.
+fn copy_planes(
+    video: &ffmpeg_next::frame::Video,
+    layout: &[(usize, usize)],
+    pool: Option<&BufferPool>,
+) -> Result<SharedBuffer, Exception> {
+    let mut data = PooledBuffer::acquire(pool, layout.iter().map(|(row, rows)| row * rows).sum())?;
+    for (plane, &(row_size, rows)) in layout.iter().enumerate() {
+        let stride = video.stride(plane);
+        let plane_data = video.data(plane);
+        for row in 0..rows {
+            data.extend_from_slice(&plane_data[row * stride..row * stride + row_size]);
+        }
+    }
+    Ok(data.freeze())
+}
+
+/// Converts frames between pixel formats and sizes, reusing the scaler while the
+/// conversion stays the same.
+#[derive(Default)]
+pub struct FrameConverter {
+    scaler: Option<((Pixel, u32, u32, Pixel, u32, u32), scaling::Context)>,
+}
+
+// SAFETY: the scaler is only ever used by the thread holding the converter.
+unsafe impl Send for FrameConverter {}
+
+impl FrameConverter {
+    pub fn convert(
+        &mut self,
+        video: &ffmpeg_next::frame::Video,
+        format: Pixel,
+        width: u32,
+        height: u32,
+    ) -> Result<ffmpeg_next::frame::Video, Exception> {
+        let key = (
+            video.format(),
+            video.width(),
+            video.height(),
+            format,
+            width,
+            height,
+        );
+        let convert_error = |e: ffmpeg_next::Error| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("cannot convert {:?} frames to {format:?}", video.format()),
+            )
+            .with_source(e)
+        };
+        if self.scaler.as_ref().map(|(current, _)| *current) != Some(key) {
+            let scaler = scaling::Context::get(
+                video.format(),
+                video.width(),
+                video.height(),
+                format,
+                width,
+                height,
+                scaling::Flags::BILINEAR,
+            )
+            .map_err(convert_error)?;
+            self.scaler = Some((key, scaler));
+        }
+        let mut converted = ffmpeg_next::frame::Video::empty();
+        if let Some((_, scaler)) = self.scaler.as_mut() {
+            scaler.run(video, &mut converted).map_err(convert_error)?;
+        }
+        converted.set_pts(video.pts());
+        Ok(converted)
+    }
+}
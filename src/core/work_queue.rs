@@ -1,10 +1,13 @@
 #![allow(unused)]
 use std::{
+    panic::{self, AssertUnwindSafe},
     sync::{mpsc, Arc, Mutex},
     thread,
 };
 
-pub const MAX_WORKERS: usize = 4;
+/// Jobs of a codec instance must run in the order they were enqueued (a flush has to observe
+/// every decode before it), which a single worker guarantees.
+pub const MAX_WORKERS: usize = 1;
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
@@ -28,7 +31,8 @@ impl ThreadPool {
     where
         F: FnOnce() + Send + 'static,
     {
-        self.sender.send(Box::new(job)).unwrap();
+        // Sending only fails once every worker is gone, in which case the job is dropped.
+        let _ = self.sender.send(Box::new(job));
     }
 }
 
@@ -41,10 +45,16 @@ pub struct Worker {
 impl Worker {
     fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Self {
         let thread = thread::spawn(move || loop {
-            let job = receiver.lock().unwrap().recv();
+            let job = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => break,
+            };
             match job {
                 Ok(job) => {
-                    job();
+                    // Jobs report panics of the backend and the output callback as codec
+                    // errors; what is left, e.g. a panicking error callback, must not take the
+                    // worker down with it, or every later job of the codec would be lost.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(job));
                 }
                 Err(_) => {
                     break;
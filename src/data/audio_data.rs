@@ -1,7 +1,15 @@
+use std::borrow::Cow;
+
+use crate::codec::{AudioDecoderConfig, Exception, ExceptionKind};
+
+use super::buffer::SharedBuffer;
+
 /// Represents unencoded audio data.
 ///
+/// Clones share the sample buffer rather than copying it.
+///
 /// https://developer.mozilla.org/en-US/docs/Web/API/AudioData
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AudioData {
     /// The sample format of the audio.
     pub format: String,
@@ -15,7 +23,7 @@ pub struct AudioData {
     pub duration: f64,
     /// The timestamp of the audio in microseconds.
     pub timestamp: f64,
-    pub data: Vec<u8>,
+    pub data: SharedBuffer,
 }
 
 impl AudioData {
@@ -25,7 +33,7 @@ impl AudioData {
         number_of_channels: u32,
         number_of_frames: u32,
         timestamp: f64,
-        data: Vec<u8>,
+        data: impl Into<SharedBuffer>,
     ) -> Self {
         let duration = (number_of_frames as f64 / sample_rate) * 1_000_000.0;
         AudioData {
@@ -35,17 +43,236 @@ impl AudioData {
             number_of_frames,
             duration,
             timestamp,
+            data: data.into(),
+        }
+    }
+
+    /// Creates `AudioData` from samples in a caller's buffer, checking that the buffer holds
+    /// every sample the format, channels and frames call for.
+    ///
+    /// Fails with `TypeError` for an unknown format, a sample rate, channel count or frame
+    /// count that is not positive, or a buffer that is too small.
+    pub fn try_new(init: AudioDataInit<'_>) -> Result<Self, Exception> {
+        let invalid = |message: String| Exception::new(ExceptionKind::TypeError, message);
+        let (bytes_per_sample, _) = sample_format_layout(&init.format)
+            .ok_or_else(|| invalid(format!("unknown AudioData format {:?}", init.format)))?;
+        if !(init.sample_rate.is_finite() && init.sample_rate > 0.0) {
+            return Err(invalid(format!("invalid sample rate {}", init.sample_rate)));
+        }
+        if init.number_of_frames == 0 || init.number_of_channels == 0 {
+            return Err(invalid(
+                "AudioData needs at least one frame and one channel".to_string(),
+            ));
+        }
+        let size = (init.number_of_frames as usize)
+            .checked_mul(init.number_of_channels as usize)
+            .and_then(|samples| samples.checked_mul(bytes_per_sample))
+            .filter(|&size| size <= init.data.len())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "buffer of {} bytes is too small for {} frames of {} {} channels",
+                    init.data.len(),
+                    init.number_of_frames,
+                    init.number_of_channels,
+                    init.format
+                ))
+            })?;
+
+        let data = match init.data {
+            Cow::Owned(mut data) if init.transfer => {
+                // Shortening keeps the allocation, so the samples are not copied.
+                data.truncate(size);
+                data
+            }
+            data => data[..size].to_vec(),
+        };
+        Ok(Self::new(
+            init.format,
+            init.sample_rate,
+            init.number_of_channels,
+            init.number_of_frames,
+            init.timestamp,
             data,
+        ))
+    }
+
+    /// Releases this reference to the samples without waiting for the `AudioData` to be
+    /// dropped. Afterwards it has no format, channels or frames, and codecs reject it.
+    ///
+    /// Clones keep their own reference, so the memory is freed once every clone is closed
+    /// or dropped.
+    pub fn close(&mut self) {
+        self.format.clear();
+        self.sample_rate = 0.0;
+        self.number_of_channels = 0;
+        self.number_of_frames = 0;
+        self.duration = 0.0;
+        self.data = SharedBuffer::new();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.format.is_empty()
+    }
+
+    /// The samples of each channel of `u8-planar` data.
+    pub fn as_u8_planes(&self) -> Result<Vec<Cow<'_, [u8]>>, Exception> {
+        self.planes("u8-planar")
+    }
+
+    /// The samples of each channel of `s16-planar` data.
+    pub fn as_i16_planes(&self) -> Result<Vec<Cow<'_, [i16]>>, Exception> {
+        self.planes("s16-planar")
+    }
+
+    /// The samples of each channel of `s32-planar` data.
+    pub fn as_i32_planes(&self) -> Result<Vec<Cow<'_, [i32]>>, Exception> {
+        self.planes("s32-planar")
+    }
+
+    /// The samples of each channel of `f32-planar` data.
+    pub fn as_f32_planes(&self) -> Result<Vec<Cow<'_, [f32]>>, Exception> {
+        self.planes("f32-planar")
+    }
+
+    /// The interleaved samples of `u8` data.
+    pub fn as_u8_interleaved(&self) -> Result<Cow<'_, [u8]>, Exception> {
+        self.samples("u8")
+    }
+
+    /// The interleaved samples of `s16` data.
+    pub fn as_i16_interleaved(&self) -> Result<Cow<'_, [i16]>, Exception> {
+        self.samples("s16")
+    }
+
+    /// The interleaved samples of `s32` data.
+    pub fn as_i32_interleaved(&self) -> Result<Cow<'_, [i32]>, Exception> {
+        self.samples("s32")
+    }
+
+    /// The interleaved samples of `f32` data.
+    pub fn as_f32_interleaved(&self) -> Result<Cow<'_, [f32]>, Exception> {
+        self.samples("f32")
+    }
+
+    /// Every sample in the buffer, which must be in `format`. The samples are borrowed, or
+    /// copied out of a buffer that does not start on a sample boundary, such as a slice at
+    /// an odd offset into a larger one.
+    fn samples<T: Sample>(&self, format: &str) -> Result<Cow<'_, [T]>, Exception> {
+        if self.is_closed() {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "AudioData is closed",
+            ));
+        }
+        if self.format != format {
+            return Err(Exception::new(
+                ExceptionKind::DataError,
+                format!("AudioData is {}, not {format}", self.format),
+            ));
         }
+        let len = self.number_of_frames as usize * self.number_of_channels as usize;
+        let bytes = self
+            .data
+            .get(..len * std::mem::size_of::<T>())
+            .ok_or_else(|| {
+                Exception::new(
+                    ExceptionKind::DataError,
+                    "AudioData buffer is smaller than its format requires",
+                )
+            })?;
+        // SAFETY: `Sample` is only implemented for plain integer and float types, for which
+        // every bit pattern is a valid value.
+        let (prefix, samples, _) = unsafe { bytes.align_to::<T>() };
+        if prefix.is_empty() {
+            return Ok(Cow::Borrowed(samples));
+        }
+        let samples = bytes
+            .chunks_exact(std::mem::size_of::<T>())
+            // SAFETY: as above, and each chunk holds exactly one `T`.
+            .map(|sample| unsafe { std::ptr::read_unaligned(sample.as_ptr().cast::<T>()) })
+            .collect();
+        Ok(Cow::Owned(samples))
+    }
+
+    fn planes<T: Sample>(&self, format: &str) -> Result<Vec<Cow<'_, [T]>>, Exception> {
+        let samples = self.samples(format)?;
+        let frames = self.number_of_frames as usize;
+        let planes = (0..self.number_of_channels as usize).map(|ch| ch * frames..(ch + 1) * frames);
+        Ok(match samples {
+            Cow::Borrowed(samples) => planes.map(|plane| Cow::Borrowed(&samples[plane])).collect(),
+            Cow::Owned(samples) => planes
+                .map(|plane| Cow::Owned(samples[plane].to_vec()))
+                .collect(),
+        })
     }
 }
 
+/// The sample types `AudioData` can be read as.
+trait Sample: Copy {}
+
+impl Sample for u8 {}
+impl Sample for i16 {}
+impl Sample for i32 {}
+impl Sample for f32 {}
+
+/// The size in bytes of one sample of an `AudioData` format, and whether the format is
+/// planar; `None` for unknown formats.
+pub fn sample_format_layout(format: &str) -> Option<(usize, bool)> {
+    let layout = match format {
+        "u8" => (1, false),
+        "u8-planar" => (1, true),
+        "s16" => (2, false),
+        "s16-planar" => (2, true),
+        "s32" => (4, false),
+        "s32-planar" => (4, true),
+        "f32" => (4, false),
+        "f32-planar" => (4, true),
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// The arguments of `AudioData::try_new`.
+///
+/// https://w3c.github.io/webcodecs/#dictdef-audiodatainit
+#[derive(Debug, Clone)]
+pub struct AudioDataInit<'a> {
+    /// The sample format, e.g. "f32-planar" or "s16".
+    pub format: String,
+    /// The sample rate in Hz.
+    pub sample_rate: f64,
+    pub number_of_frames: u32,
+    pub number_of_channels: u32,
+    /// The timestamp in microseconds.
+    pub timestamp: f64,
+    /// The samples in native byte order, one channel after another for planar formats and
+    /// interleaved otherwise. Bytes past the last sample are ignored.
+    pub data: Cow<'a, [u8]>,
+    /// Whether an owned `data` buffer is taken over without copying rather than copied,
+    /// like listing it in `transfer` in the spec. Borrowed data is always copied.
+    pub transfer: bool,
+}
+
 /// Represents codec-specific encoded audio bytes.
 ///
 /// https://developer.mozilla.org/en-US/docs/Web/API/EncodedAudioChunk
 #[derive(Debug, Clone)]
 pub struct EncodedAudioChunk {
-    pub data: Vec<u8>,
+    pub data: SharedBuffer,
+    /// The presentation timestamp in microseconds.
     pub timestamp: i64,
+    /// The duration in microseconds, if known.
+    pub duration: Option<u64>,
     pub is_key: bool,
 }
+
+/// Information about an `EncodedAudioChunk` passed along with it to the encoder's output
+/// callback.
+///
+/// https://developer.mozilla.org/en-US/docs/Web/API/AudioEncoder/AudioEncoder#metadata
+#[derive(Debug, Clone, Default)]
+pub struct EncodedAudioChunkMetadata {
+    /// Set on the first chunk after the encoder (re)configures, and whenever the
+    /// configuration needed to decode the chunks changes.
+    pub decoder_config: Option<AudioDecoderConfig>,
+}
@@ -0,0 +1,101 @@
+use std::{
+    fmt::{self, Debug},
+    ops::{Deref, RangeBounds},
+};
+
+use bytes::Bytes;
+
+/// An immutable, reference-counted byte buffer: the samples of `AudioData`, the pixels of a
+/// `VideoFrame` or the data of an encoded chunk.
+///
+/// Cloning takes another reference to the same memory instead of copying it, so media moves
+/// between threads and codecs without copies. The memory is released with the last
+/// reference.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct SharedBuffer(Bytes);
+
+impl SharedBuffer {
+    /// An empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copies `data` into a new buffer.
+    pub fn copy_from_slice(data: &[u8]) -> Self {
+        Self(Bytes::copy_from_slice(data))
+    }
+
+    /// Wraps memory owned by `owner`, e.g. a decoded frame of a codec library, without
+    /// copying it. `owner` is dropped with the last reference.
+    pub fn from_owner<T: AsRef<[u8]> + Send + 'static>(owner: T) -> Self {
+        Self(Bytes::from_owner(owner))
+    }
+
+    /// A buffer of `range` of this one, sharing its memory.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        Self(self.0.slice(range))
+    }
+
+    /// The bytes as a `Vec`, which takes over the memory if this is the only reference to a
+    /// buffer made from a `Vec`, and copies it otherwise.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0.into()
+    }
+}
+
+impl Deref for SharedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SharedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Debug for SharedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SharedBuffer({} bytes)", self.0.len())
+    }
+}
+
+/// Takes over the memory of the `Vec` without copying it.
+impl From<Vec<u8>> for SharedBuffer {
+    fn from(data: Vec<u8>) -> Self {
+        Self(Bytes::from(data))
+    }
+}
+
+impl From<&'static [u8]> for SharedBuffer {
+    fn from(data: &'static [u8]) -> Self {
+        Self(Bytes::from_static(data))
+    }
+}
+
+impl From<Bytes> for SharedBuffer {
+    fn from(data: Bytes) -> Self {
+        Self(data)
+    }
+}
+
+impl From<SharedBuffer> for Bytes {
+    fn from(buffer: SharedBuffer) -> Self {
+        buffer.0
+    }
+}
+
+impl PartialEq<[u8]> for SharedBuffer {
+    fn eq(&self, other: &[u8]) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for SharedBuffer {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        *self.0 == **other
+    }
+}
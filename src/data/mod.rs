@@ -1 +1,2 @@
 pub mod audio_data;
+pub mod buffer;
@@ -0,0 +1,117 @@
+//! WebCodecs codec strings and descriptions for demuxed ffmpeg streams.
+//!
+//! https://www.w3.org/TR/webcodecs-codec-registry/
+
+use ffmpeg_next::codec::Id;
+
+use crate::bitstream::{
+    AudioSpecificConfig, AvcDecoderConfigurationRecord, HevcDecoderConfigurationRecord,
+};
+
+/// The codec string and description of an audio stream.
+pub fn audio_codec(id: Id, extradata: Option<Vec<u8>>, profile: i32) -> (String, Option<Vec<u8>>) {
+    match id {
+        Id::AAC => {
+            let codec = match extradata.as_deref().map(AudioSpecificConfig::parse) {
+                Some(Ok(config)) => config.codec_string(),
+                _ if profile >= 0 => format!("mp4a.40.{}", profile + 1),
+                _ => "mp4a.40.2".to_string(),
+            };
+            (codec, extradata)
+        }
+        Id::MP3 => ("mp3".to_string(), None),
+        Id::OPUS => ("opus".to_string(), extradata),
+        Id::VORBIS => ("vorbis".to_string(), extradata),
+        Id::FLAC => ("flac".to_string(), extradata.map(flac_description)),
+        Id::PCM_U8 => ("pcm-u8".to_string(), None),
+        Id::PCM_S16LE => ("pcm-s16".to_string(), None),
+        Id::PCM_S24LE => ("pcm-s24".to_string(), None),
+        Id::PCM_S32LE => ("pcm-s32".to_string(), None),
+        Id::PCM_F32LE => ("pcm-f32".to_string(), None),
+        Id::PCM_MULAW => ("ulaw".to_string(), None),
+        Id::PCM_ALAW => ("alaw".to_string(), None),
+        // Not in the registry; the ffmpeg decoder name still configures a decoder.
+        id => (id.name().to_string(), extradata),
+    }
+}
+
+/// ffmpeg keeps the bare STREAMINFO block, the registry wants the `fLaC` stream header.
+fn flac_description(extradata: Vec<u8>) -> Vec<u8> {
+    if extradata.starts_with(b"fLaC") {
+        return extradata;
+    }
+    let mut description = b"fLaC".to_vec();
+    // Last metadata block, type STREAMINFO.
+    description.push(0x80);
+    description.extend_from_slice(&(extradata.len() as u32).to_be_bytes()[1..]);
+    description.extend_from_slice(&extradata);
+    description
+}
+
+/// The codec string and description of a video stream.
+pub fn video_codec(
+    id: Id,
+    extradata: Option<Vec<u8>>,
+    profile: i32,
+    level: i32,
+) -> (String, Option<Vec<u8>>) {
+    // Only the avcC/hvcC configuration records are descriptions; Annex B parameter sets
+    // stay in the bitstream.
+    let record = extradata.clone().filter(|data| data.first() == Some(&1));
+    match id {
+        Id::H264 => {
+            let codec = match record.as_deref().map(AvcDecoderConfigurationRecord::parse) {
+                Some(Ok(record)) => record.codec_string(),
+                _ => {
+                    let profile = profile.max(66);
+                    // FF_PROFILE_H264_CONSTRAINED marks constrained baseline.
+                    let compatibility = if profile & (1 << 9) != 0 { 0x40 } else { 0 };
+                    format!(
+                        "avc1.{:02x}{compatibility:02x}{:02x}",
+                        profile & 0xff,
+                        level.max(10)
+                    )
+                }
+            };
+            (codec, record)
+        }
+        Id::HEVC => {
+            let codec = match record.as_deref().map(HevcDecoderConfigurationRecord::parse) {
+                Some(Ok(record)) => record.codec_string(),
+                _ => format!("hvc1.{}.0.L{}.B0", profile.max(1), level.max(30)),
+            };
+            (codec, record)
+        }
+        Id::VP8 => ("vp8".to_string(), None),
+        Id::VP9 => {
+            let profile = profile.max(0);
+            let bit_depth = if profile >= 2 { 10 } else { 8 };
+            let level = if level > 0 { level } else { 10 };
+            (format!("vp09.{profile:02}.{level:02}.{bit_depth:02}"), None)
+        }
+        Id::AV1 => {
+            // The av1C record starts with its marker and version byte.
+            let av1c = extradata
+                .as_deref()
+                .filter(|data| data.first() == Some(&0x81));
+            let codec = match av1c {
+                Some([_, profile_level, flags, ..]) => {
+                    let tier = if flags & 0x80 != 0 { 'H' } else { 'M' };
+                    let bit_depth = match (flags & 0x40 != 0, flags & 0x20 != 0) {
+                        (true, true) => 12,
+                        (true, false) => 10,
+                        _ => 8,
+                    };
+                    format!(
+                        "av01.{}.{:02}{tier}.{bit_depth:02}",
+                        profile_level >> 5,
+                        profile_level & 0x1f
+                    )
+                }
+                _ => format!("av01.{}.{:02}M.08", profile.max(0), level.max(0)),
+            };
+            (codec, None)
+        }
+        id => (id.name().to_string(), record),
+    }
+}
@@ -0,0 +1,240 @@
+//! Reads encoded chunks and decoder configs out of media files.
+//!
+//! Supports what ffmpeg can open, notably MP4, WebM/Matroska, Ogg, MP3, ADTS AAC, FLAC and
+//! WAV.
+
+mod codec_string;
+
+use std::path::Path;
+
+use ffmpeg_next::{media, Rational};
+
+use crate::{
+    codec::{
+        AudioDecoderConfig, EncodedVideoChunk, Exception, ExceptionKind, HardwareAcceleration,
+        VideoDecoderConfig,
+    },
+    core::ffmpeg,
+    data::audio_data::EncodedAudioChunk,
+};
+
+/// The decoder config of a track.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackConfig {
+    Audio(AudioDecoderConfig),
+    Video(VideoDecoderConfig),
+}
+
+/// An audio or video track of a media file.
+#[derive(Debug, Clone)]
+pub struct Track {
+    /// Identifies the track in the chunks read from the file.
+    pub index: usize,
+    pub config: TrackConfig,
+    /// The duration of the track in microseconds, if known.
+    pub duration: Option<u64>,
+}
+
+/// An encoded chunk of either kind.
+#[derive(Debug, Clone)]
+pub enum EncodedChunk {
+    Audio(EncodedAudioChunk),
+    Video(EncodedVideoChunk),
+}
+
+impl EncodedChunk {
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            EncodedChunk::Audio(chunk) => chunk.timestamp,
+            EncodedChunk::Video(chunk) => chunk.timestamp,
+        }
+    }
+
+    /// The decode timestamp, falling back to the presentation timestamp for chunks that
+    /// carry none, such as audio.
+    pub fn decode_timestamp(&self) -> i64 {
+        match self {
+            EncodedChunk::Audio(chunk) => chunk.timestamp,
+            EncodedChunk::Video(chunk) => chunk.decode_timestamp.unwrap_or(chunk.timestamp),
+        }
+    }
+
+    pub fn duration(&self) -> Option<u64> {
+        match self {
+            EncodedChunk::Audio(chunk) => chunk.duration,
+            EncodedChunk::Video(chunk) => chunk.duration,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self {
+            EncodedChunk::Audio(chunk) => &chunk.data,
+            EncodedChunk::Video(chunk) => &chunk.data,
+        }
+    }
+
+    pub fn is_key(&self) -> bool {
+        match self {
+            EncodedChunk::Audio(chunk) => chunk.is_key,
+            EncodedChunk::Video(chunk) => chunk.is_key,
+        }
+    }
+}
+
+/// Splits a media file into tracks of encoded chunks.
+///
+/// Chunks come out in file order, interleaved across tracks, with timestamps and durations
+/// in microseconds. Each is ready to be passed to a decoder configured with its track's
+/// config.
+pub struct Demuxer {
+    input: ffmpeg_next::format::context::Input,
+    tracks: Vec<Track>,
+    /// The time base of each stream, or `None` for streams that are not tracks.
+    time_bases: Vec<Option<Rational>>,
+    /// Where the next chunk of each stream starts if its packet carries no timestamp: the
+    /// end of the previous chunk, or its start when its duration is unknown.
+    next_timestamps: Vec<i64>,
+}
+
+impl Demuxer {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+        let path = path.as_ref();
+        let input = ffmpeg_next::format::input(path).map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("failed to open {}", path.display()),
+            )
+            .with_source(e)
+        })?;
+
+        let mut tracks = Vec::new();
+        let mut time_bases = Vec::new();
+        for stream in input.streams() {
+            let parameters = stream.parameters();
+            let extradata = ffmpeg::parameters_extradata(&parameters);
+            let (profile, level) = ffmpeg::profile_and_level(&parameters);
+            let config = match parameters.medium() {
+                media::Type::Audio => {
+                    let (codec, description) =
+                        codec_string::audio_codec(parameters.id(), extradata, profile);
+                    let (sample_rate, number_of_channels) = ffmpeg::audio_parameters(&parameters);
+                    TrackConfig::Audio(AudioDecoderConfig {
+                        codec,
+                        sample_rate,
+                        number_of_channels,
+                        description,
+                    })
+                }
+                media::Type::Video => {
+                    let (codec, description) =
+                        codec_string::video_codec(parameters.id(), extradata, profile, level);
+                    let (width, height) = ffmpeg::video_size(&parameters);
+                    let known = width > 0 && height > 0;
+                    TrackConfig::Video(VideoDecoderConfig {
+                        codec,
+                        coded_width: known.then_some(width),
+                        coded_height: known.then_some(height),
+                        description,
+                        hardware_acceleration: HardwareAcceleration::default(),
+                    })
+                }
+                _ => {
+                    time_bases.push(None);
+                    continue;
+                }
+            };
+            let duration = Some(stream.duration())
+                .filter(|duration| *duration > 0)
+                .map(|duration| ffmpeg::to_microseconds(duration, stream.time_base()) as u64);
+            tracks.push(Track {
+                index: stream.index(),
+                config,
+                duration,
+            });
+            time_bases.push(Some(stream.time_base()));
+        }
+
+        let next_timestamps = vec![0; time_bases.len()];
+        Ok(Self {
+            input,
+            tracks,
+            time_bases,
+            next_timestamps,
+        })
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Reads the next chunk and the index of its track, or `None` at the end of the file.
+    ///
+    /// A chunk whose packet carries no timestamp takes over from the previous chunk of its
+    /// track, starting where that one ends.
+    pub fn read(&mut self) -> Result<Option<(usize, EncodedChunk)>, Exception> {
+        loop {
+            let mut packet = ffmpeg_next::Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) => {}
+                Err(ffmpeg_next::Error::Eof) => return Ok(None),
+                Err(e) => {
+                    return Err(
+                        Exception::new(ExceptionKind::DataError, "failed to read packet")
+                            .with_source(e),
+                    )
+                }
+            }
+            let index = packet.stream();
+            let Some(Some(time_base)) = self.time_bases.get(index).copied() else {
+                continue;
+            };
+            let Some(data) = packet.data() else {
+                continue;
+            };
+            let Some(track) = self.tracks.iter().find(|track| track.index == index) else {
+                continue;
+            };
+
+            let timestamp = packet
+                .pts()
+                .or(packet.dts())
+                .map_or(self.next_timestamps[index], |ts| {
+                    ffmpeg::to_microseconds(ts, time_base)
+                });
+            let duration = Some(packet.duration())
+                .filter(|duration| *duration > 0)
+                .map(|duration| ffmpeg::to_microseconds(duration, time_base) as u64);
+            self.next_timestamps[index] = timestamp + duration.unwrap_or(0) as i64;
+            let chunk = match track.config {
+                TrackConfig::Audio(_) => EncodedChunk::Audio(EncodedAudioChunk {
+                    data: data.to_vec().into(),
+                    timestamp,
+                    duration,
+                    is_key: packet.is_key(),
+                }),
+                TrackConfig::Video(_) => EncodedChunk::Video(EncodedVideoChunk {
+                    data: data.to_vec().into(),
+                    timestamp,
+                    decode_timestamp: packet
+                        .dts()
+                        .map(|ts| ffmpeg::to_microseconds(ts, time_base)),
+                    duration,
+                    is_key: packet.is_key(),
+                }),
+            };
+            return Ok(Some((index, chunk)));
+        }
+    }
+}
+
+impl Iterator for Demuxer {
+    type Item = Result<(usize, EncodedChunk), Exception>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
@@ -1,3 +1,10 @@
+pub mod bitstream;
 pub mod codec;
 pub mod core;
 pub mod data;
+#[cfg(feature = "ffmpeg")]
+pub mod demux;
+#[cfg(feature = "ffmpeg")]
+pub mod mux;
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use crate::{
+    codec::{Exception, ExceptionKind},
+    core::ffmpeg,
+    demux::{EncodedChunk, TrackConfig},
+};
+
+use super::{add_stream, audio_parameters, update_time_bases, video_parameters, MuxedTrack};
+
+/// A CMAF media segment: one `moof`/`mdat` fragment.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub data: Vec<u8>,
+    /// Timestamp of the first chunk in the segment, in microseconds from the decode
+    /// timestamp of the first chunk pushed.
+    pub timestamp: i64,
+    /// In microseconds.
+    pub duration: u64,
+}
+
+/// Cuts encoder output into a CMAF init segment and media segments held in memory, e.g.
+/// to serve over HLS or DASH.
+///
+/// Segments start at key chunks of the video track, or at any chunk when there are only
+/// audio tracks, once the current segment has reached the target duration.
+pub struct CmafSegmenter {
+    output: ffmpeg_next::format::context::Output,
+    tracks: Vec<MuxedTrack>,
+    video_tracks: Vec<usize>,
+    target_duration: Duration,
+    /// Decode timestamp of the first chunk pushed, in microseconds. Chunks are pushed in
+    /// decode order across tracks, so no chunk decodes before it.
+    origin: Option<i64>,
+    /// Rebased timestamp of the first chunk in the current segment.
+    segment_start: Option<i64>,
+    /// Rebased end of the latest chunk pushed.
+    end: i64,
+    initialized: bool,
+}
+
+impl CmafSegmenter {
+    pub fn new(target_duration: Duration) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+        let output = ffmpeg::memory_output("mp4").map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                "failed to create MP4 muxer",
+            )
+            .with_source(e)
+        })?;
+        Ok(Self {
+            output,
+            tracks: Vec::new(),
+            video_tracks: Vec::new(),
+            target_duration,
+            origin: None,
+            segment_start: None,
+            end: 0,
+            initialized: false,
+        })
+    }
+
+    /// Adds a track and returns its index. All tracks must be added before the init segment
+    /// is taken.
+    pub fn add_track(&mut self, config: &TrackConfig) -> Result<usize, Exception> {
+        if self.initialized {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "tracks cannot be added after the init segment",
+            ));
+        }
+        let parameters = match config {
+            TrackConfig::Audio(config) => audio_parameters(config)?,
+            TrackConfig::Video(config) => video_parameters(config)?,
+        };
+        let index = add_stream(&mut self.output, parameters)?;
+        self.tracks.push(MuxedTrack::new());
+        if let TrackConfig::Video(_) = config {
+            self.video_tracks.push(index);
+        }
+        Ok(index)
+    }
+
+    /// Writes the `ftyp` and `moov` boxes describing every track. Must be called once, after
+    /// adding the tracks and before pushing chunks.
+    pub fn init_segment(&mut self) -> Result<Vec<u8>, Exception> {
+        if self.initialized {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "the init segment has already been written",
+            ));
+        }
+        let mut options = ffmpeg_next::Dictionary::new();
+        options.set(
+            "movflags",
+            "cmaf+frag_custom+empty_moov+default_base_moof+skip_trailer",
+        );
+        ffmpeg::open_memory_output(&mut self.output).map_err(memory_error)?;
+        self.output.write_header_with(options).map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                "cannot write these tracks to CMAF",
+            )
+            .with_source(e)
+        })?;
+        ffmpeg::flush_fragment(&mut self.output).map_err(write_error)?;
+        let init = ffmpeg::take_memory_output(&mut self.output);
+        ffmpeg::open_memory_output(&mut self.output).map_err(memory_error)?;
+        update_time_bases(&self.output, &mut self.tracks);
+        self.initialized = true;
+        Ok(init)
+    }
+
+    /// Adds a chunk to the current segment, returning the previous segment if this chunk
+    /// starts a new one. Chunks must be pushed in decode order.
+    pub fn push(
+        &mut self,
+        track: usize,
+        chunk: &EncodedChunk,
+    ) -> Result<Option<Segment>, Exception> {
+        if !self.initialized {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "the init segment must be written before chunks",
+            ));
+        }
+        if track >= self.tracks.len() {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                format!("no track with index {track}"),
+            ));
+        }
+
+        let origin = *self.origin.get_or_insert(chunk.decode_timestamp());
+        let timestamp = chunk.timestamp() - origin;
+        let starts_segment = if self.video_tracks.is_empty() {
+            true
+        } else {
+            self.video_tracks.contains(&track) && chunk.is_key()
+        };
+        let mut finished = None;
+        match self.segment_start {
+            None => self.segment_start = Some(timestamp),
+            Some(start)
+                if starts_segment
+                    && timestamp - start >= self.target_duration.as_micros() as i64 =>
+            {
+                finished = Some(self.cut_segment(timestamp)?);
+                self.segment_start = Some(timestamp);
+            }
+            Some(_) => {}
+        }
+
+        self.end = self
+            .end
+            .max(timestamp + chunk.duration().unwrap_or(0) as i64);
+        let packet = self.tracks[track].packet(track, chunk, origin);
+        packet.write(&mut self.output).map_err(write_error)?;
+        Ok(finished)
+    }
+
+    /// Ends the current segment, which lasts until `end`.
+    fn cut_segment(&mut self, end: i64) -> Result<Segment, Exception> {
+        ffmpeg::flush_fragment(&mut self.output).map_err(write_error)?;
+        let data = ffmpeg::take_memory_output(&mut self.output);
+        ffmpeg::open_memory_output(&mut self.output).map_err(memory_error)?;
+        let start = self.segment_start.unwrap_or(end);
+        Ok(Segment {
+            data,
+            timestamp: start,
+            duration: (end - start).max(0) as u64,
+        })
+    }
+
+    /// Ends the last segment and returns it, if any chunk was pushed since the previous one.
+    pub fn finish(mut self) -> Result<Option<Segment>, Exception> {
+        let Some(start) = self.segment_start.take() else {
+            return Ok(None);
+        };
+        // Flushes the last fragment; `skip_trailer` keeps the `mfra` index out of it.
+        self.output.write_trailer().map_err(write_error)?;
+        let end = self.end.max(start);
+        Ok(Some(Segment {
+            data: ffmpeg::take_memory_output(&mut self.output),
+            timestamp: start,
+            duration: (end - start).max(0) as u64,
+        }))
+    }
+}
+
+impl Drop for CmafSegmenter {
+    fn drop(&mut self) {
+        // Releases the memory buffer, which the output context cannot close itself.
+        ffmpeg::take_memory_output(&mut self.output);
+    }
+}
+
+fn memory_error(e: ffmpeg_next::Error) -> Exception {
+    Exception::new(
+        ExceptionKind::QuotaExceededError,
+        "failed to allocate segment buffer",
+    )
+    .with_source(e)
+}
+
+fn write_error(e: ffmpeg_next::Error) -> Exception {
+    Exception::new(ExceptionKind::DataError, "failed to write segment").with_source(e)
+}
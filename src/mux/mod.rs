@@ -0,0 +1,316 @@
+//! Writes encoded chunks into MP4, WebM and Ogg files, or into CMAF segments in memory.
+
+mod cmaf;
+
+pub use cmaf::*;
+
+use std::path::Path;
+
+use ffmpeg_next::{
+    codec::{Id, Parameters},
+    Rational,
+};
+
+use crate::{
+    codec::{AudioDecoderConfig, EncodedVideoChunk, Exception, ExceptionKind, VideoDecoderConfig},
+    core::ffmpeg,
+    data::audio_data::EncodedAudioChunk,
+    demux::{EncodedChunk, TrackConfig},
+};
+
+/// The container written by a `Muxer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    Mp4,
+    /// MP4 written as a sequence of fragments, one per video key chunk. Everything up to the
+    /// last complete fragment stays playable if the process dies before `finish`.
+    FragmentedMp4,
+    WebM,
+    /// Ogg, which only holds audio: Opus, Vorbis or FLAC.
+    Ogg,
+}
+
+impl ContainerFormat {
+    fn muxer_name(&self) -> &'static str {
+        match self {
+            ContainerFormat::Mp4 | ContainerFormat::FragmentedMp4 => "mp4",
+            ContainerFormat::WebM => "webm",
+            ContainerFormat::Ogg => "ogg",
+        }
+    }
+}
+
+/// Timing state of a track being muxed.
+struct MuxedTrack {
+    /// The time base the muxer stores timestamps in, known once the header is written.
+    time_base: Rational,
+    last_dts: Option<i64>,
+}
+
+impl MuxedTrack {
+    fn new() -> Self {
+        Self {
+            time_base: ffmpeg::MICROSECONDS,
+            last_dts: None,
+        }
+    }
+
+    /// A packet for stream `index` holding `chunk`, with its timestamps moved back by
+    /// `origin`.
+    fn packet(&mut self, index: usize, chunk: &EncodedChunk, origin: i64) -> ffmpeg_next::Packet {
+        let pts = ffmpeg::from_microseconds(chunk.timestamp() - origin, self.time_base);
+        let dts = ffmpeg::from_microseconds(chunk.decode_timestamp() - origin, self.time_base);
+        // Containers require increasing decode timestamps, which rounding to a coarser time
+        // base, or chunks without one, can break; a bumped one must not pass the
+        // presentation timestamp.
+        let dts = match self.last_dts {
+            Some(last) if dts <= last => last + 1,
+            _ => dts,
+        };
+        self.last_dts = Some(dts);
+
+        let mut packet = ffmpeg_next::Packet::copy(chunk.data());
+        packet.set_stream(index);
+        packet.set_pts(Some(pts.max(dts)));
+        packet.set_dts(Some(dts));
+        if let Some(duration) = chunk.duration() {
+            packet.set_duration(ffmpeg::from_microseconds(duration as i64, self.time_base));
+        }
+        if chunk.is_key() {
+            packet.set_flags(ffmpeg_next::packet::Flags::KEY);
+        }
+        packet
+    }
+}
+
+/// Writes encoder output into a media file.
+///
+/// Tracks are added from the `decoder_config` in the metadata of the first chunk of each
+/// encoder, then chunks are written in decode order. Timestamps are rebased so that the
+/// earliest decode timestamp across all tracks is zero; MP4 output keeps the presentation
+/// timestamps in an edit list when B-frames put them behind.
+pub struct Muxer {
+    output: ffmpeg_next::format::context::Output,
+    format: ContainerFormat,
+    tracks: Vec<MuxedTrack>,
+    header_written: bool,
+}
+
+impl Muxer {
+    pub fn create(path: impl AsRef<Path>, format: ContainerFormat) -> Result<Self, Exception> {
+        ffmpeg_next::init().map_err(|e| {
+            Exception::new(ExceptionKind::InternalError, "failed to initialise ffmpeg")
+                .with_source(e)
+        })?;
+        let path = path.as_ref();
+        let output = ffmpeg_next::format::output_as(path, format.muxer_name()).map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("failed to create {}", path.display()),
+            )
+            .with_source(e)
+        })?;
+        Ok(Self {
+            output,
+            format,
+            tracks: Vec::new(),
+            header_written: false,
+        })
+    }
+
+    /// Adds a track and returns its index. All tracks must be added before the first chunk is
+    /// written.
+    pub fn add_track(&mut self, config: &TrackConfig) -> Result<usize, Exception> {
+        match config {
+            TrackConfig::Audio(config) => self.add_audio_track(config),
+            TrackConfig::Video(config) => self.add_video_track(config),
+        }
+    }
+
+    pub fn add_audio_track(&mut self, config: &AudioDecoderConfig) -> Result<usize, Exception> {
+        let parameters = audio_parameters(config)?;
+        self.add_stream(parameters)
+    }
+
+    pub fn add_video_track(&mut self, config: &VideoDecoderConfig) -> Result<usize, Exception> {
+        let parameters = video_parameters(config)?;
+        self.add_stream(parameters)
+    }
+
+    fn add_stream(&mut self, parameters: Parameters) -> Result<usize, Exception> {
+        if self.header_written {
+            return Err(Exception::new(
+                ExceptionKind::InvalidStateError,
+                "tracks cannot be added after chunks have been written",
+            ));
+        }
+        let index = add_stream(&mut self.output, parameters)?;
+        self.tracks.push(MuxedTrack::new());
+        Ok(index)
+    }
+
+    pub fn write_audio(
+        &mut self,
+        track: usize,
+        chunk: &EncodedAudioChunk,
+    ) -> Result<(), Exception> {
+        self.write(track, &EncodedChunk::Audio(chunk.clone()))
+    }
+
+    pub fn write_video(
+        &mut self,
+        track: usize,
+        chunk: &EncodedVideoChunk,
+    ) -> Result<(), Exception> {
+        self.write(track, &EncodedChunk::Video(chunk.clone()))
+    }
+
+    pub fn write(&mut self, track: usize, chunk: &EncodedChunk) -> Result<(), Exception> {
+        if track >= self.tracks.len() {
+            return Err(Exception::new(
+                ExceptionKind::TypeError,
+                format!("no track with index {track}"),
+            ));
+        }
+        self.write_header()?;
+
+        // The muxer rebases every track once it has seen the first chunk of each.
+        let packet = self.tracks[track].packet(track, chunk, 0);
+        packet.write_interleaved(&mut self.output).map_err(|e| {
+            Exception::new(
+                ExceptionKind::DataError,
+                format!("failed to write chunk at timestamp {}", chunk.timestamp()),
+            )
+            .with_source(e)
+        })
+    }
+
+    fn write_header(&mut self) -> Result<(), Exception> {
+        if self.header_written {
+            return Ok(());
+        }
+        let mut options = ffmpeg_next::Dictionary::new();
+        // Shifts all tracks by the earliest decode timestamp, which the interleaver only
+        // lets through once every track has a chunk queued.
+        options.set("avoid_negative_ts", "make_zero");
+        if self.format == ContainerFormat::FragmentedMp4 {
+            options.set("movflags", "frag_keyframe+empty_moov+default_base_moof");
+        }
+        self.output.write_header_with(options).map_err(|e| {
+            Exception::new(
+                ExceptionKind::NotSupportedError,
+                format!("cannot write these tracks to {:?}", self.format),
+            )
+            .with_source(e)
+        })?;
+        self.header_written = true;
+        update_time_bases(&self.output, &mut self.tracks);
+        Ok(())
+    }
+
+    /// Writes the end of the file. Without it, only fragmented MP4 output is playable.
+    pub fn finish(mut self) -> Result<(), Exception> {
+        self.write_header()?;
+        self.output.write_trailer().map_err(|e| {
+            Exception::new(ExceptionKind::DataError, "failed to finish file").with_source(e)
+        })
+    }
+}
+
+fn audio_parameters(config: &AudioDecoderConfig) -> Result<Parameters, Exception> {
+    let id = audio_codec_id(&config.codec).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("cannot mux codec {:?}", config.codec),
+        )
+    })?;
+    let description = config.description.as_deref().map(|description| {
+        // ffmpeg wants the bare STREAMINFO block after the `fLaC` stream header.
+        match description.strip_prefix(b"fLaC") {
+            Some(blocks) if id == Id::FLAC && blocks.len() >= 38 => &blocks[4..38],
+            _ => description,
+        }
+    });
+    Ok(ffmpeg::audio_stream_parameters(
+        id,
+        config.sample_rate,
+        config.number_of_channels,
+        description,
+    ))
+}
+
+fn video_parameters(config: &VideoDecoderConfig) -> Result<Parameters, Exception> {
+    let id = video_codec_id(&config.codec).ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::NotSupportedError,
+            format!("cannot mux codec {:?}", config.codec),
+        )
+    })?;
+    let (Some(width), Some(height)) = (config.coded_width, config.coded_height) else {
+        return Err(Exception::new(
+            ExceptionKind::TypeError,
+            "video tracks need a coded width and height",
+        ));
+    };
+    Ok(ffmpeg::video_stream_parameters(
+        id,
+        width,
+        height,
+        config.description.as_deref(),
+    ))
+}
+
+/// Adds a stream described by `parameters` and returns its index.
+fn add_stream(
+    output: &mut ffmpeg_next::format::context::Output,
+    parameters: Parameters,
+) -> Result<usize, Exception> {
+    let mut stream = output.add_stream(None::<ffmpeg_next::Codec>).map_err(|e| {
+        Exception::new(ExceptionKind::InternalError, "failed to add stream").with_source(e)
+    })?;
+    stream.set_parameters(parameters);
+    stream.set_time_base(ffmpeg::MICROSECONDS);
+    Ok(stream.index())
+}
+
+/// Picks up the time bases the muxer chose when writing the header.
+fn update_time_bases(output: &ffmpeg_next::format::context::Output, tracks: &mut [MuxedTrack]) {
+    for (index, track) in tracks.iter_mut().enumerate() {
+        if let Some(stream) = output.stream(index) {
+            track.time_base = stream.time_base();
+        }
+    }
+}
+
+/// The ffmpeg codec of a WebCodecs audio codec string.
+fn audio_codec_id(codec: &str) -> Option<Id> {
+    let id = match codec {
+        "opus" => Id::OPUS,
+        "vorbis" => Id::VORBIS,
+        "flac" => Id::FLAC,
+        "mp3" | "mp4a.69" | "mp4a.6B" | "mp4a.40.34" => Id::MP3,
+        "pcm-u8" => Id::PCM_U8,
+        "pcm-s16" => Id::PCM_S16LE,
+        "pcm-s24" => Id::PCM_S24LE,
+        "pcm-s32" => Id::PCM_S32LE,
+        "pcm-f32" => Id::PCM_F32LE,
+        "ulaw" => Id::PCM_MULAW,
+        "alaw" => Id::PCM_ALAW,
+        codec if codec.starts_with("mp4a.") => Id::AAC,
+        _ => return None,
+    };
+    Some(id)
+}
+
+/// The ffmpeg codec of a WebCodecs video codec string.
+fn video_codec_id(codec: &str) -> Option<Id> {
+    let id = match codec.split('.').next()? {
+        "avc1" | "avc3" => Id::H264,
+        "hvc1" | "hev1" => Id::HEVC,
+        "vp8" => Id::VP8,
+        "vp09" => Id::VP9,
+        "av01" => Id::AV1,
+        _ => return None,
+    };
+    Some(id)
+}
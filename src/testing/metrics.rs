@@ -0,0 +1,66 @@
+use crate::codec::{Exception, ExceptionKind, VideoFrame};
+
+/// The signal-to-noise ratio of `decoded` against `reference` in dB, over the samples both
+/// have. Infinite when they are equal.
+pub fn snr(reference: &[f32], decoded: &[f32]) -> f64 {
+    let (signal, noise) =
+        reference
+            .iter()
+            .zip(decoded)
+            .fold((0.0, 0.0), |(signal, noise), (&r, &d)| {
+                let (r, d) = (r as f64, d as f64);
+                (signal + r * r, noise + (r - d) * (r - d))
+            });
+    10.0 * (signal / noise).log10()
+}
+
+/// The number of samples, up to `max_delay`, that `decoded` lags `reference` by: the offset
+/// with the least mean squared error. Encoders like AAC and Opus prepend priming samples.
+pub fn output_delay(reference: &[f32], decoded: &[f32], max_delay: usize) -> usize {
+    (0..=max_delay.min(decoded.len()))
+        .map(|delay| {
+            let (error, samples) = reference.iter().zip(&decoded[delay..]).fold(
+                (0.0, 0usize),
+                |(error, samples), (&r, &d)| {
+                    let diff = r as f64 - d as f64;
+                    (error + diff * diff, samples + 1)
+                },
+            );
+            (delay, error / samples.max(1) as f64)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(delay, _)| delay)
+}
+
+/// The peak signal-to-noise ratio of the 8 bit samples of `decoded` against `reference` in
+/// dB, over every plane. Infinite when they are equal.
+///
+/// Both frames must have the same format and size.
+pub fn psnr(reference: &VideoFrame, decoded: &VideoFrame) -> Result<f64, Exception> {
+    if reference.format != decoded.format
+        || (reference.coded_width, reference.coded_height)
+            != (decoded.coded_width, decoded.coded_height)
+        || reference.data.len() != decoded.data.len()
+    {
+        return Err(Exception::new(
+            ExceptionKind::DataError,
+            format!(
+                "{} {}x{} frame cannot be compared to {} {}x{} frame",
+                decoded.format,
+                decoded.coded_width,
+                decoded.coded_height,
+                reference.format,
+                reference.coded_width,
+                reference.coded_height
+            ),
+        ));
+    }
+    let squared_error: f64 = reference
+        .data
+        .iter()
+        .zip(decoded.data.iter())
+        .map(|(&r, &d)| (r as f64 - d as f64).powi(2))
+        .sum();
+    let mse = squared_error / reference.data.len().max(1) as f64;
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
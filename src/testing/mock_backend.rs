@@ -0,0 +1,488 @@
+//! A mock codec whose chunks hold the samples or pixels of their input verbatim, so that
+//! decoding gives back exactly what was encoded.
+//!
+//! Chunks start with a magic, the format name and the sample rate, channels and frames of
+//! `AudioData` or the size of a `VideoFrame`; anything else fails to decode. Encoders fail
+//! on input that does not match their config, and encode a key frame after every configure
+//! and reset, when asked to and every `keyframe_interval` frames.
+
+use std::collections::VecDeque;
+
+use crate::{
+    codec::{
+        AudioDecoderConfig, AudioEncoderConfig, EncodedVideoChunk, EncodedVideoChunkMetadata,
+        Exception, ExceptionKind, HardwareAcceleration, VideoDecoderConfig, VideoEncoderConfig,
+        VideoEncoderEncodeOptions, VideoFrame,
+    },
+    core::backend::{
+        AudioDecoderBackend, AudioEncoderBackend, BackendProvider, CodecBackend,
+        VideoDecoderBackend, VideoEncoderBackend,
+    },
+    data::{
+        audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+        buffer::SharedBuffer,
+    },
+};
+
+const AUDIO_MAGIC: &[u8; 4] = b"MCKA";
+const VIDEO_MAGIC: &[u8; 4] = b"MCKV";
+
+/// Provides mock decoders and encoders of audio and video for one codec string.
+pub struct MockBackend {
+    codec: String,
+    delay: usize,
+}
+
+impl MockBackend {
+    /// A mock for `codec` only, which outputs every input as soon as it is sent.
+    pub fn new(codec: impl Into<String>) -> Self {
+        Self {
+            codec: codec.into(),
+            delay: 0,
+        }
+    }
+
+    /// Holds back the last `delay` outputs until more input is sent or the codec is flushed,
+    /// like the lookahead of real codecs.
+    pub fn with_delay(mut self, delay: usize) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn supports(&self, codec: &str) -> bool {
+        codec == self.codec
+    }
+}
+
+impl BackendProvider for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn audio_decoder(&self, codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        self.supports(codec)
+            .then(|| Box::new(MockAudioDecoder::new(self.delay)) as Box<AudioDecoderBackend>)
+    }
+
+    fn audio_encoder(&self, codec: &str) -> Option<Box<AudioEncoderBackend>> {
+        self.supports(codec)
+            .then(|| Box::new(MockAudioEncoder::new(self.delay)) as Box<AudioEncoderBackend>)
+    }
+
+    fn video_decoder(&self, codec: &str) -> Option<Box<VideoDecoderBackend>> {
+        self.supports(codec)
+            .then(|| Box::new(MockVideoDecoder::new(self.delay)) as Box<VideoDecoderBackend>)
+    }
+
+    fn video_encoder(&self, codec: &str) -> Option<Box<VideoEncoderBackend>> {
+        self.supports(codec)
+            .then(|| Box::new(MockVideoEncoder::new(self.delay)) as Box<VideoEncoderBackend>)
+    }
+}
+
+fn not_configured() -> Exception {
+    Exception::new(ExceptionKind::InvalidStateError, "codec is not configured")
+}
+
+/// Outputs in the order they were produced, of which the last `delay` are held back until
+/// more are produced or the queue is flushed.
+struct DelayQueue<T> {
+    delay: usize,
+    outputs: VecDeque<T>,
+    /// Outputs released by a flush regardless of the delay.
+    flushed: usize,
+}
+
+impl<T> DelayQueue<T> {
+    fn new(delay: usize) -> Self {
+        Self {
+            delay,
+            outputs: VecDeque::new(),
+            flushed: 0,
+        }
+    }
+
+    fn push(&mut self, output: T) {
+        self.outputs.push_back(output);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.flushed > 0 {
+            self.flushed -= 1;
+        } else if self.outputs.len() <= self.delay {
+            return None;
+        }
+        self.outputs.pop_front()
+    }
+
+    fn flush(&mut self) {
+        self.flushed = self.outputs.len();
+    }
+
+    fn clear(&mut self) {
+        self.outputs.clear();
+        self.flushed = 0;
+    }
+}
+
+/// Serializes a mock chunk: the magic, the format name, `fields` and `payload`.
+fn write_chunk<const N: usize>(
+    magic: &[u8; 4],
+    format: &str,
+    fields: [u32; N],
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut data = magic.to_vec();
+    data.push(format.len() as u8);
+    data.extend_from_slice(format.as_bytes());
+    for field in fields {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Parses a chunk written by `write_chunk`.
+fn read_chunk<'a, const N: usize>(
+    magic: &[u8; 4],
+    data: &'a [u8],
+    timestamp: i64,
+) -> Result<(&'a str, [u32; N], &'a [u8]), Exception> {
+    let parse = || -> Option<(&'a str, [u32; N], &'a [u8])> {
+        let rest = data.strip_prefix(magic)?;
+        let (&len, rest) = rest.split_first()?;
+        let (format, mut rest) = rest.split_at_checked(len as usize)?;
+        let format = std::str::from_utf8(format).ok()?;
+        let mut fields = [0; N];
+        for field in &mut fields {
+            let (bytes, remaining) = rest.split_first_chunk::<4>()?;
+            *field = u32::from_le_bytes(*bytes);
+            rest = remaining;
+        }
+        Some((format, fields, rest))
+    };
+    parse().ok_or_else(|| {
+        Exception::new(
+            ExceptionKind::DecodeError,
+            format!("chunk at timestamp {timestamp} is not a mock chunk"),
+        )
+    })
+}
+
+/// The `payload` at the end of `data`, sharing its memory.
+fn payload_of(data: &SharedBuffer, payload: &[u8]) -> SharedBuffer {
+    data.slice(data.len() - payload.len()..)
+}
+
+/// Decodes chunks back to the `AudioData` that was encoded.
+pub struct MockAudioDecoder {
+    config: Option<AudioDecoderConfig>,
+    ready: DelayQueue<AudioData>,
+}
+
+impl MockAudioDecoder {
+    fn new(delay: usize) -> Self {
+        Self {
+            config: None,
+            ready: DelayQueue::new(delay),
+        }
+    }
+}
+
+impl CodecBackend for MockAudioDecoder {
+    type Config = AudioDecoderConfig;
+    type Input = EncodedAudioChunk;
+    type Output = AudioData;
+
+    fn configure(&mut self, config: &AudioDecoderConfig) -> Result<(), Exception> {
+        self.config = Some(config.clone());
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        let config = self.config.as_ref().ok_or_else(not_configured)?;
+        let (format, [sample_rate, channels, frames], payload) =
+            read_chunk(AUDIO_MAGIC, &chunk.data, chunk.timestamp)?;
+        if channels != config.number_of_channels {
+            return Err(Exception::new(
+                ExceptionKind::DecodeError,
+                format!(
+                    "chunk with {channels} channels does not match the decoder config of {}",
+                    config.number_of_channels
+                ),
+            ));
+        }
+        self.ready.push(AudioData::new(
+            format.to_string(),
+            sample_rate as f64,
+            channels,
+            frames,
+            chunk.timestamp as f64,
+            payload_of(&chunk.data, payload),
+        ));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<AudioData>, Exception> {
+        Ok(self.ready.pop())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.ready.flush();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.ready.clear();
+        Ok(())
+    }
+}
+
+/// Encodes each `AudioData` to one chunk holding its samples.
+pub struct MockAudioEncoder {
+    config: Option<AudioEncoderConfig>,
+    /// Whether the decoder config has been reported in output metadata.
+    reported_config: bool,
+    ready: DelayQueue<(EncodedAudioChunk, EncodedAudioChunkMetadata)>,
+}
+
+impl MockAudioEncoder {
+    fn new(delay: usize) -> Self {
+        Self {
+            config: None,
+            reported_config: false,
+            ready: DelayQueue::new(delay),
+        }
+    }
+}
+
+impl CodecBackend for MockAudioEncoder {
+    type Config = AudioEncoderConfig;
+    type Input = AudioData;
+    type Output = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
+    fn configure(&mut self, config: &AudioEncoderConfig) -> Result<(), Exception> {
+        self.config = Some(config.clone());
+        self.reported_config = false;
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, data: AudioData) -> Result<(), Exception> {
+        let config = self.config.as_ref().ok_or_else(not_configured)?;
+        if data.sample_rate as u32 != config.sample_rate
+            || data.number_of_channels != config.number_of_channels
+        {
+            return Err(Exception::new(
+                ExceptionKind::EncodingError,
+                format!(
+                    "AudioData with {} channels at {} Hz does not match the encoder config",
+                    data.number_of_channels, data.sample_rate
+                ),
+            ));
+        }
+        let chunk = EncodedAudioChunk {
+            data: write_chunk(
+                AUDIO_MAGIC,
+                &data.format,
+                [
+                    config.sample_rate,
+                    data.number_of_channels,
+                    data.number_of_frames,
+                ],
+                &data.data,
+            )
+            .into(),
+            timestamp: data.timestamp as i64,
+            duration: Some(data.duration as u64),
+            is_key: true,
+        };
+        let mut metadata = EncodedAudioChunkMetadata::default();
+        if !self.reported_config {
+            metadata.decoder_config = Some(AudioDecoderConfig {
+                codec: config.codec.clone(),
+                sample_rate: config.sample_rate,
+                number_of_channels: config.number_of_channels,
+                description: None,
+            });
+            self.reported_config = true;
+        }
+        self.ready.push((chunk, metadata));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        Ok(self.ready.pop())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.ready.flush();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.ready.clear();
+        Ok(())
+    }
+}
+
+/// Decodes chunks back to the `VideoFrame` that was encoded.
+pub struct MockVideoDecoder {
+    configured: bool,
+    ready: DelayQueue<VideoFrame>,
+}
+
+impl MockVideoDecoder {
+    fn new(delay: usize) -> Self {
+        Self {
+            configured: false,
+            ready: DelayQueue::new(delay),
+        }
+    }
+}
+
+impl CodecBackend for MockVideoDecoder {
+    type Config = VideoDecoderConfig;
+    type Input = EncodedVideoChunk;
+    type Output = VideoFrame;
+
+    fn configure(&mut self, _config: &VideoDecoderConfig) -> Result<(), Exception> {
+        self.configured = true;
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, chunk: EncodedVideoChunk) -> Result<(), Exception> {
+        if !self.configured {
+            return Err(not_configured());
+        }
+        let (format, [width, height], payload) =
+            read_chunk(VIDEO_MAGIC, &chunk.data, chunk.timestamp)?;
+        let mut frame = VideoFrame::new(
+            format.to_string(),
+            width,
+            height,
+            chunk.timestamp,
+            payload_of(&chunk.data, payload),
+        );
+        frame.duration = chunk.duration;
+        self.ready.push(frame);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<VideoFrame>, Exception> {
+        Ok(self.ready.pop())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.ready.flush();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.ready.clear();
+        Ok(())
+    }
+}
+
+/// Encodes each `VideoFrame` to one chunk holding its pixels.
+pub struct MockVideoEncoder {
+    config: Option<VideoEncoderConfig>,
+    /// Whether the decoder config has been reported in output metadata.
+    reported_config: bool,
+    /// Frames encoded since the last key frame, `None` when the next must be one.
+    frames_since_key: Option<u32>,
+    ready: DelayQueue<(EncodedVideoChunk, EncodedVideoChunkMetadata)>,
+}
+
+impl MockVideoEncoder {
+    fn new(delay: usize) -> Self {
+        Self {
+            config: None,
+            reported_config: false,
+            frames_since_key: None,
+            ready: DelayQueue::new(delay),
+        }
+    }
+}
+
+impl CodecBackend for MockVideoEncoder {
+    type Config = VideoEncoderConfig;
+    type Input = (VideoFrame, VideoEncoderEncodeOptions);
+    type Output = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+    fn configure(&mut self, config: &VideoEncoderConfig) -> Result<(), Exception> {
+        self.config = Some(config.clone());
+        self.reported_config = false;
+        self.frames_since_key = None;
+        self.ready.clear();
+        Ok(())
+    }
+
+    fn send(&mut self, (frame, options): Self::Input) -> Result<(), Exception> {
+        let config = self.config.as_ref().ok_or_else(not_configured)?;
+        if frame.coded_width != config.width || frame.coded_height != config.height {
+            return Err(Exception::new(
+                ExceptionKind::EncodingError,
+                format!(
+                    "{}x{} frame does not match the encoder config of {}x{}",
+                    frame.coded_width, frame.coded_height, config.width, config.height
+                ),
+            ));
+        }
+        let is_key = options.key_frame
+            || match self.frames_since_key {
+                None => true,
+                Some(frames) => config
+                    .keyframe_interval
+                    .is_some_and(|interval| frames + 1 >= interval),
+            };
+        self.frames_since_key = Some(match self.frames_since_key {
+            Some(frames) if !is_key => frames + 1,
+            _ => 0,
+        });
+
+        let chunk = EncodedVideoChunk {
+            data: write_chunk(
+                VIDEO_MAGIC,
+                &frame.format,
+                [frame.coded_width, frame.coded_height],
+                &frame.data,
+            )
+            .into(),
+            timestamp: frame.timestamp,
+            decode_timestamp: Some(frame.timestamp),
+            duration: frame.duration,
+            is_key,
+        };
+        let mut metadata = EncodedVideoChunkMetadata::default();
+        if !self.reported_config {
+            metadata.decoder_config = Some(VideoDecoderConfig {
+                codec: config.codec.clone(),
+                coded_width: Some(config.width),
+                coded_height: Some(config.height),
+                description: None,
+                hardware_acceleration: HardwareAcceleration::default(),
+            });
+            self.reported_config = true;
+        }
+        self.ready.push((chunk, metadata));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Self::Output>, Exception> {
+        Ok(self.ready.pop())
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        self.ready.flush();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        self.frames_since_key = None;
+        self.ready.clear();
+        Ok(())
+    }
+}
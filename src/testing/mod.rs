@@ -0,0 +1,18 @@
+//! A deterministic test harness: a mock codec backend and generated test signals, so codecs
+//! can be exercised under `cargo test` without codec libraries or sample files. Only built
+//! with the `testing` feature.
+//!
+//! Register a `MockBackend` for a codec string of its own with
+//! `core::backend::register_backend`, then drive the public codecs with it and with the
+//! signals of `sine_wave` and `color_bars`. `snr` and `psnr` measure what lossy codecs
+//! give back. `block_on` drives promises and the async codecs without a runtime.
+
+mod executor;
+mod metrics;
+mod mock_backend;
+mod signals;
+
+pub use executor::*;
+pub use metrics::*;
+pub use mock_backend::*;
+pub use signals::*;
@@ -0,0 +1,67 @@
+use crate::{codec::VideoFrame, core::pcm, data::audio_data::AudioData};
+
+/// The amplitude of `sine_wave`, leaving headroom for lossy codecs.
+const SINE_AMPLITUDE: f64 = 0.5;
+
+/// The luma, Cb and Cr of the eight 75% colour bars, left to right: white, yellow, cyan,
+/// green, magenta, red, blue and black (BT.601, limited range).
+const COLOR_BARS: [[u8; 3]; 8] = [
+    [180, 128, 128],
+    [162, 44, 142],
+    [131, 156, 44],
+    [112, 72, 58],
+    [84, 184, 198],
+    [65, 100, 212],
+    [35, 212, 114],
+    [16, 128, 128],
+];
+
+/// `frames` frames of `f32-planar` sine waves starting at `timestamp` microseconds.
+///
+/// Channel `c` is at `frequency * (c + 1)` Hz so that swapped channels show. The phase
+/// follows from the timestamp, so consecutive calls give one continuous signal.
+pub fn sine_wave(
+    frequency: f64,
+    sample_rate: u32,
+    channels: u32,
+    frames: u32,
+    timestamp: f64,
+) -> AudioData {
+    let start = timestamp / 1_000_000.0;
+    let planes: Vec<Vec<f32>> = (0..channels)
+        .map(|ch| {
+            let frequency = frequency * (ch + 1) as f64;
+            (0..frames)
+                .map(|i| {
+                    let t = start + i as f64 / sample_rate as f64;
+                    (SINE_AMPLITUDE * (2.0 * std::f64::consts::PI * frequency * t).sin()) as f32
+                })
+                .collect()
+        })
+        .collect();
+    pcm::f32_planar_audio_data(&planes, sample_rate, timestamp)
+}
+
+/// An `I420` frame of eight vertical colour bars at `timestamp` microseconds.
+pub fn color_bars(width: u32, height: u32, timestamp: i64) -> VideoFrame {
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let bar = |x: usize, row_width: usize| &COLOR_BARS[x * COLOR_BARS.len() / row_width];
+
+    let mut data = Vec::with_capacity(width * height + 2 * chroma_width * chroma_height);
+    for _ in 0..height {
+        data.extend((0..width).map(|x| bar(x, width)[0]));
+    }
+    for component in 1..3 {
+        for _ in 0..chroma_height {
+            data.extend((0..chroma_width).map(|x| bar(x, chroma_width)[component]));
+        }
+    }
+    VideoFrame::new(
+        "I420".to_string(),
+        width as u32,
+        height as u32,
+        timestamp,
+        data,
+    )
+}
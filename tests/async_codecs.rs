@@ -0,0 +1,152 @@
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    sync::{Arc, Once},
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use wcodecs::{
+    codec::{
+        AsyncVideoDecoder, AsyncVideoEncoder, EncodedVideoChunk, Exception, ExceptionKind, State,
+        VideoEncoderConfig, VideoFrame,
+    },
+    core::backend::register_backend,
+    testing::{block_on, color_bars, MockBackend},
+};
+
+/// Holds back the last two outputs until flushed.
+const DELAYED_MOCK: &str = "async-mock-delayed";
+
+fn register_mocks() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        register_backend(Arc::new(MockBackend::new(DELAYED_MOCK).with_delay(2)));
+    });
+}
+
+fn frames() -> Vec<VideoFrame> {
+    (0..5)
+        .map(|i| {
+            let mut frame = color_bars(64, 48, i * 33_333);
+            frame.duration = Some(33_333);
+            frame
+        })
+        .collect()
+}
+
+async fn send<S: Sink<T, Error = Exception> + Unpin, T>(sink: &mut S, item: T) {
+    poll_fn(|cx| Pin::new(&mut *sink).poll_ready(cx))
+        .await
+        .unwrap();
+    Pin::new(sink).start_send(item).unwrap();
+}
+
+async fn close<S: Sink<T, Error = Exception> + Unpin, T>(sink: &mut S) -> Result<(), Exception> {
+    poll_fn(|cx| Pin::new(&mut *sink).poll_close(cx)).await
+}
+
+/// Everything the stream hands out until it ends.
+async fn collect<S: Stream + Unpin>(stream: &mut S) -> Vec<S::Item> {
+    let mut items = Vec::new();
+    while let Some(item) = poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await {
+        items.push(item);
+    }
+    items
+}
+
+#[test]
+fn video_round_trip_through_sinks_and_streams() {
+    register_mocks();
+    let inputs = frames();
+    let (chunks, outputs) = block_on(async {
+        let mut encoder = AsyncVideoEncoder::new();
+        encoder
+            .configure(VideoEncoderConfig::new(DELAYED_MOCK, 64, 48))
+            .unwrap();
+        for frame in &inputs {
+            send(&mut encoder, frame.clone()).await;
+        }
+        // Closing flushes, so the delayed chunks come out before the stream ends.
+        close(&mut encoder).await.unwrap();
+        let chunks: Vec<_> = collect(&mut encoder)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(encoder.state(), State::Closed);
+
+        let mut decoder = AsyncVideoDecoder::new();
+        let config = chunks[0].1.decoder_config.clone().unwrap();
+        decoder.configure(config).unwrap();
+        for (chunk, _) in &chunks {
+            send(&mut decoder, chunk.clone()).await;
+        }
+        close(&mut decoder).await.unwrap();
+        (chunks, collect(&mut decoder).await)
+    });
+
+    assert_eq!(chunks.len(), inputs.len());
+    assert_eq!(outputs.len(), inputs.len());
+    for (output, input) in outputs.into_iter().zip(&inputs) {
+        let output = output.unwrap();
+        assert_eq!(output.timestamp, input.timestamp);
+        assert_eq!(output.data, input.data);
+    }
+}
+
+#[test]
+fn flush_resolves_with_every_output_in_the_stream() {
+    register_mocks();
+    block_on(async {
+        let mut encoder = AsyncVideoEncoder::new();
+        encoder
+            .configure(VideoEncoderConfig::new(DELAYED_MOCK, 64, 48))
+            .unwrap();
+        for frame in frames() {
+            send(&mut encoder, frame).await;
+        }
+        encoder.flush().await.unwrap();
+        encoder.close();
+
+        assert_eq!(collect(&mut encoder).await.len(), 5);
+    });
+}
+
+#[test]
+fn decode_errors_end_the_stream() {
+    register_mocks();
+    let outputs = block_on(async {
+        let mut decoder = AsyncVideoDecoder::new();
+        let mut encoder = AsyncVideoEncoder::new();
+        encoder
+            .configure(VideoEncoderConfig::new(DELAYED_MOCK, 64, 48))
+            .unwrap();
+        send(&mut encoder, color_bars(64, 48, 0)).await;
+        close(&mut encoder).await.unwrap();
+        let (chunk, metadata) = collect(&mut encoder).await.remove(0).unwrap();
+
+        decoder.configure(metadata.decoder_config.unwrap()).unwrap();
+        send(&mut decoder, chunk).await;
+        send(
+            &mut decoder,
+            EncodedVideoChunk {
+                data: vec![0; 16].into(),
+                timestamp: 33_333,
+                decode_timestamp: None,
+                duration: None,
+                is_key: true,
+            },
+        )
+        .await;
+        let flushed = decoder.flush().await;
+        assert!(flushed.is_err());
+        // Stream ends behind the error without closing the decoder by hand.
+        collect(&mut decoder).await
+    });
+
+    // The delayed decoder still holds the first frame back when the error closes it.
+    assert_eq!(outputs.len(), 1);
+    let error = outputs[0].as_ref().unwrap_err();
+    assert_eq!(error.kind(), ExceptionKind::DecodeError);
+}
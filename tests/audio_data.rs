@@ -0,0 +1,132 @@
+use std::{borrow::Cow, sync::mpsc};
+
+use wcodecs::{
+    codec::{AudioEncoder, AudioEncoderConfig, ExceptionKind},
+    data::{
+        audio_data::{AudioData, AudioDataInit},
+        buffer::SharedBuffer,
+    },
+    testing::sine_wave,
+};
+
+fn s16_init(data: Cow<'_, [u8]>, transfer: bool) -> AudioDataInit<'_> {
+    AudioDataInit {
+        format: "s16".to_string(),
+        sample_rate: 48_000.0,
+        number_of_frames: 3,
+        number_of_channels: 2,
+        timestamp: 1_000.0,
+        data,
+        transfer,
+    }
+}
+
+fn s16_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_ne_bytes()).collect()
+}
+
+#[test]
+fn try_new_copies_borrowed_samples() {
+    let samples = [1, -1, 2, -2, 3, -3];
+    let bytes = s16_bytes(&samples);
+    let data = AudioData::try_new(s16_init(Cow::Borrowed(&bytes), false)).unwrap();
+
+    assert_eq!(*data.as_i16_interleaved().unwrap(), samples);
+    assert_eq!(data.number_of_frames, 3);
+    assert_eq!(data.duration, 62.5);
+    assert_eq!(data.timestamp, 1_000.0);
+}
+
+#[test]
+fn try_new_takes_over_transferred_buffers() {
+    let mut bytes = s16_bytes(&[1, -1, 2, -2, 3, -3]);
+    // Trailing bytes are dropped without reallocating.
+    bytes.extend_from_slice(&[0; 4]);
+    let pointer = bytes.as_ptr();
+    let data = AudioData::try_new(s16_init(Cow::Owned(bytes), true)).unwrap();
+
+    assert_eq!(data.data.as_ptr(), pointer);
+    assert_eq!(data.data.len(), 12);
+}
+
+#[test]
+fn try_new_rejects_invalid_inits() {
+    let bytes = s16_bytes(&[0; 6]);
+    let kind = |init: AudioDataInit| AudioData::try_new(init).unwrap_err().kind();
+
+    let mut init = s16_init(Cow::Borrowed(&bytes), false);
+    init.format = "s24".to_string();
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+
+    let mut init = s16_init(Cow::Borrowed(&bytes), false);
+    init.number_of_frames = 0;
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+
+    let mut init = s16_init(Cow::Borrowed(&bytes), false);
+    init.sample_rate = f64::NAN;
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+
+    let init = s16_init(Cow::Borrowed(&bytes[..11]), false);
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+}
+
+#[test]
+fn typed_accessors_check_the_format() {
+    let data = sine_wave(440.0, 48_000, 2, 480, 0.0);
+    let planes = data.as_f32_planes().unwrap();
+
+    assert_eq!(planes.len(), 2);
+    assert!(planes.iter().all(|plane| plane.len() == 480));
+    assert_eq!(planes[0][0], 0.0);
+    assert_eq!(
+        data.as_i16_interleaved().unwrap_err().kind(),
+        ExceptionKind::DataError
+    );
+}
+
+#[test]
+fn samples_at_an_odd_offset_are_read_without_error() {
+    let samples = [1, -1, 2, -2, 3, -3];
+    let mut bytes = vec![0];
+    bytes.extend(s16_bytes(&samples));
+    let buffer = SharedBuffer::from(bytes).slice(1..);
+    let data = AudioData::new("s16".to_string(), 48_000.0, 2, 3, 0.0, buffer.clone());
+    assert_eq!(*data.as_i16_interleaved().unwrap(), samples);
+
+    let data = AudioData::new("s16-planar".to_string(), 48_000.0, 2, 3, 0.0, buffer);
+    let planes = data.as_i16_planes().unwrap();
+    assert_eq!(*planes[0], samples[..3]);
+    assert_eq!(*planes[1], samples[3..]);
+}
+
+#[test]
+fn captured_samples_feed_an_encoder() {
+    let samples = [100, -100, 200, -200, 300, -300];
+    let bytes = s16_bytes(&samples);
+    let data = AudioData::try_new(s16_init(Cow::Borrowed(&bytes), false)).unwrap();
+
+    let (output_tx, outputs) = mpsc::channel();
+    let mut encoder = AudioEncoder::new(
+        move |chunk, _| {
+            let _ = output_tx.send(chunk);
+        },
+        |error| panic!("{error}"),
+    );
+    encoder
+        .configure(AudioEncoderConfig {
+            codec: "pcm-s16".to_string(),
+            sample_rate: 48_000,
+            number_of_channels: 2,
+            bitrate: None,
+            opus: None,
+            aac: None,
+        })
+        .unwrap();
+    encoder.encode(data).unwrap();
+    encoder.flush().unwrap().wait().unwrap();
+    let chunk = outputs.try_recv().unwrap();
+
+    let encoded: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    assert_eq!(chunk.data, encoded);
+    assert_eq!(chunk.timestamp, 1_000);
+}
@@ -0,0 +1,135 @@
+use std::{sync::mpsc::Receiver, thread, time::Duration};
+
+use wcodecs::{
+    codec::{AudioDecoder, AudioDecoderConfig, CodecEvent, ExceptionKind, State},
+    core::buffer_pool::{BufferPool, PoolExhaustion},
+    data::audio_data::{AudioData, EncodedAudioChunk},
+};
+
+/// 480 frames of stereo `pcm-s16`.
+fn pcm_chunk(index: i64) -> EncodedAudioChunk {
+    EncodedAudioChunk {
+        data: vec![0; 480 * 2 * 2].into(),
+        timestamp: index * 10_000,
+        duration: Some(10_000),
+        is_key: true,
+    }
+}
+
+fn pcm_decoder(pool: &BufferPool) -> (AudioDecoder, Receiver<CodecEvent<AudioData>>) {
+    let (mut decoder, outputs) = AudioDecoder::with_channel();
+    decoder.set_buffer_pool(pool.clone());
+    decoder
+        .configure(AudioDecoderConfig {
+            codec: "pcm-s16".to_string(),
+            sample_rate: 48_000,
+            number_of_channels: 2,
+            description: None,
+        })
+        .unwrap();
+    (decoder, outputs)
+}
+
+#[test]
+fn released_buffers_are_reused() {
+    let pool = BufferPool::new(2, PoolExhaustion::Fail);
+    let mut buffer = pool.acquire(64).unwrap();
+    buffer.extend_from_slice(&[1; 64]);
+    let pointer = buffer.as_ptr();
+    let shared = buffer.freeze();
+    let clone = shared.clone();
+    assert_eq!(pool.outstanding(), 1);
+
+    drop(shared);
+    assert_eq!(pool.outstanding(), 1);
+    drop(clone);
+    assert_eq!((pool.outstanding(), pool.idle()), (0, 1));
+
+    let buffer = pool.acquire(32).unwrap();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.as_ptr(), pointer);
+    assert_eq!(pool.idle(), 0);
+}
+
+#[test]
+fn exhausted_pools_fail_with_quota_exceeded() {
+    let pool = BufferPool::new(2, PoolExhaustion::Fail);
+    let _first = pool.acquire(16).unwrap();
+    let second = pool.acquire(16).unwrap().freeze();
+
+    let error = pool.acquire(16).err().unwrap();
+    assert_eq!(error.kind(), ExceptionKind::QuotaExceededError);
+
+    drop(second);
+    assert!(pool.acquire(16).is_ok());
+}
+
+#[test]
+fn exhausted_pools_block_until_a_buffer_is_released() {
+    let pool = BufferPool::new(1, PoolExhaustion::Block);
+    let held = pool.acquire(16).unwrap().freeze();
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        drop(held);
+    });
+
+    let buffer = pool.acquire(16).unwrap();
+    assert_eq!(pool.outstanding(), 1);
+    drop(buffer);
+    releaser.join().unwrap();
+}
+
+#[test]
+fn decoders_fail_when_too_many_outputs_are_held() {
+    let pool = BufferPool::new(2, PoolExhaustion::Fail);
+    let (mut decoder, outputs) = pcm_decoder(&pool);
+    for i in 0..3 {
+        decoder.decode(pcm_chunk(i)).unwrap();
+    }
+    let flushed = decoder.flush().unwrap().wait();
+
+    // The flush is rejected before the error callback runs, so the error may still be on
+    // its way.
+    let events: Vec<_> = (0..3)
+        .map(|_| outputs.recv_timeout(Duration::from_secs(1)).unwrap())
+        .collect();
+    assert!(matches!(
+        events.as_slice(),
+        [CodecEvent::Output(_), CodecEvent::Output(_), CodecEvent::Error(error)]
+            if error.kind() == ExceptionKind::QuotaExceededError
+    ));
+    assert!(flushed.is_err());
+    assert_eq!(decoder.state(), State::Closed);
+}
+
+#[test]
+fn decoders_stall_until_outputs_are_released() {
+    let pool = BufferPool::new(2, PoolExhaustion::Block);
+    let (mut decoder, outputs) = pcm_decoder(&pool);
+    let consumer = thread::spawn(move || {
+        let mut frames = 0;
+        for event in outputs {
+            match event {
+                CodecEvent::Output(mut data) => {
+                    thread::sleep(Duration::from_millis(5));
+                    frames += data.number_of_frames;
+                    data.close();
+                }
+                CodecEvent::Flushed => break,
+                CodecEvent::Error(error) => panic!("{error}"),
+            }
+        }
+        frames
+    });
+
+    for i in 0..10 {
+        decoder.decode(pcm_chunk(i)).unwrap();
+    }
+    decoder.flush().unwrap().wait().unwrap();
+    assert!(pool.outstanding() <= 2);
+    drop(decoder);
+
+    assert_eq!(consumer.join().unwrap(), 4_800);
+    assert_eq!(pool.outstanding(), 0);
+    assert!(pool.idle() <= 2);
+}
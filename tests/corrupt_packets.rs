@@ -0,0 +1,214 @@
+//! Runs against whichever backend decodes MP3: ffmpeg, or symphonia in pure-Rust builds.
+#![cfg(any(feature = "ffmpeg", feature = "pure-rust"))]
+
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use wcodecs::{
+    codec::{AudioDecoder, AudioDecoderConfig, CodecEvent, Exception, ExceptionKind, State},
+    core::promise::Promise,
+    data::audio_data::{AudioData, EncodedAudioChunk},
+};
+
+const BEEP_MP3: &[u8] = include_bytes!("../examples/samples/beep.mp3");
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn mp3_config() -> AudioDecoderConfig {
+    AudioDecoderConfig {
+        codec: "mp3".to_string(),
+        sample_rate: 44100,
+        number_of_channels: 2,
+        description: None,
+    }
+}
+
+/// Deterministic pseudo-random bytes.
+fn noise(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Splits MPEG-1 Layer III data into its frames.
+fn mp3_frames(mut data: &[u8]) -> Vec<&[u8]> {
+    const KBPS: [usize; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const RATES: [usize; 3] = [44_100, 48_000, 32_000];
+    let mut frames = Vec::new();
+    while data.len() >= 4 && data[0] == 0xff && data[1] & 0xfe == 0xfa {
+        let bitrate = KBPS[usize::from(data[2] >> 4)] * 1000;
+        let sample_rate = RATES[usize::from(data[2] >> 2 & 0x3)];
+        let padding = usize::from(data[2] >> 1 & 0x1);
+        let len = (144 * bitrate / sample_rate + padding).min(data.len());
+        let (frame, rest) = data.split_at(len);
+        frames.push(frame);
+        data = rest;
+    }
+    frames
+}
+
+/// What decoding a run of chunks gave.
+struct Report {
+    /// The frames of every chunk decoded, in order, until the first error.
+    frames: Vec<Vec<AudioData>>,
+    /// The error that closed the decoder, if any.
+    error: Option<Exception>,
+    /// The result of the flush after the last chunk.
+    flushed: Result<(), Exception>,
+    state: State,
+}
+
+/// Feeds `chunks` to a fresh mp3 decoder one at a time, collecting what each one gives, and
+/// flushes it afterwards.
+fn decode_each(chunks: Vec<Vec<u8>>) -> Report {
+    let (mut decoder, outputs) = AudioDecoder::with_channel();
+    decoder.configure(mp3_config()).unwrap();
+
+    let mut frames = Vec::new();
+    let mut error = None;
+    for (i, data) in chunks.into_iter().enumerate() {
+        let chunk = EncodedAudioChunk {
+            data: data.into(),
+            timestamp: i as i64 * 26_122,
+            duration: Some(26_122),
+            is_key: true,
+        };
+        decoder.decode(chunk).unwrap();
+        let (decoded, failed) = outcome(&decoder, &outputs);
+        if let Some(err) = failed {
+            error = Some(err);
+            break;
+        }
+        frames.push(decoded);
+    }
+    let flushed = decoder.flush().and_then(wait_settled);
+    Report {
+        frames,
+        error,
+        flushed,
+        state: decoder.state(),
+    }
+}
+
+/// Waits for the decode job of the last chunk and returns the frames it gave, or the error
+/// that closed the decoder.
+fn outcome(
+    decoder: &AudioDecoder,
+    outputs: &Receiver<CodecEvent<AudioData>>,
+) -> (Vec<AudioData>, Option<Exception>) {
+    let deadline = Instant::now() + TIMEOUT;
+    while decoder.decode_queue_size() > 0 {
+        assert!(Instant::now() < deadline, "decode job did not finish");
+        thread::sleep(Duration::from_millis(1));
+    }
+    // A failing job closes the decoder before it reports the error.
+    if decoder.state() == State::Closed {
+        let event = outputs
+            .recv_timeout(TIMEOUT)
+            .expect("closed decoder reported no error");
+        let CodecEvent::Error(err) = event else {
+            panic!("expected an error, got {event:?}");
+        };
+        return (Vec::new(), Some(err));
+    }
+    let decoded = outputs
+        .try_iter()
+        .map(|event| match event {
+            CodecEvent::Output(data) => data,
+            event => panic!("expected a frame, got {event:?}"),
+        })
+        .collect();
+    (decoded, None)
+}
+
+/// Waits for `promise`, failing the test if it does not settle.
+fn wait_settled(promise: Promise<()>) -> Result<(), Exception> {
+    let (sender, settled) = mpsc::channel();
+    thread::spawn(move || sender.send(promise.wait()));
+    settled.recv_timeout(TIMEOUT).expect("flush did not settle")
+}
+
+/// Every chunk gave frames or the one `DecodeError` that closed the decoder, and the flush
+/// afterwards settled accordingly.
+fn assert_settled(report: &Report) {
+    for frames in &report.frames {
+        assert!(
+            !frames.is_empty(),
+            "a chunk gave neither frames nor an error"
+        );
+        for data in frames {
+            assert_eq!(data.format, "f32-planar");
+            assert!(data.number_of_frames > 0);
+        }
+    }
+    match &report.error {
+        Some(err) => {
+            assert_eq!(err.kind(), ExceptionKind::DecodeError);
+            assert!(!err.message().is_empty());
+            assert_eq!(report.state, State::Closed);
+            assert!(report.flushed.is_err());
+        }
+        None => {
+            assert_eq!(report.state, State::Configured);
+            assert!(report.flushed.is_ok());
+        }
+    }
+}
+
+#[test]
+fn random_packets_are_rejected_with_a_decode_error() {
+    let chunks = (0..32).map(|i| noise(i, 64 + i as usize * 37)).collect();
+    let report = decode_each(chunks);
+
+    assert_settled(&report);
+    // Noise has no frame headers, so nothing decodes.
+    assert!(report.frames.is_empty());
+    assert!(report.error.is_some());
+}
+
+#[test]
+fn intact_frames_decode_until_a_damaged_one() {
+    let frames = mp3_frames(BEEP_MP3);
+    assert!(frames.len() > 4);
+    let chunks = frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let mut frame = frame.to_vec();
+            // Overwrite part of the fourth frame, header included.
+            if i == 3 {
+                let len = frame.len().min(48);
+                frame[..len].copy_from_slice(&noise(i as u32, len));
+            }
+            frame
+        })
+        .collect();
+    let report = decode_each(chunks);
+
+    assert_settled(&report);
+    assert_eq!(report.frames.len(), 3);
+    assert!(report.error.is_some());
+}
+
+#[test]
+fn empty_and_truncated_packets_are_rejected_with_a_decode_error() {
+    for chunk in [
+        Vec::new(),
+        vec![0xff],
+        vec![0xff, 0xfb],
+        BEEP_MP3[..3].to_vec(),
+    ] {
+        let report = decode_each(vec![chunk]);
+
+        assert_settled(&report);
+        assert!(report.error.is_some());
+    }
+}
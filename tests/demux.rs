@@ -0,0 +1,40 @@
+//! Reads the sample files with the demuxer.
+#![cfg(feature = "ffmpeg")]
+
+use wcodecs::demux::{Demuxer, EncodedChunk, TrackConfig};
+
+const BEEP_MP3: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/samples/beep.mp3");
+/// Samples in an MPEG-1 Layer III frame.
+const MP3_FRAME_SAMPLES: i64 = 1152;
+
+#[test]
+fn mp3_frames_come_out_back_to_back() {
+    let demuxer = Demuxer::open(BEEP_MP3).unwrap();
+    let [track] = demuxer.tracks() else {
+        panic!("expected one track, found {:?}", demuxer.tracks());
+    };
+    let TrackConfig::Audio(config) = &track.config else {
+        panic!("expected an audio track, found {:?}", track.config);
+    };
+    assert_eq!(config.codec, "mp3");
+    assert_eq!(config.sample_rate, 44_100);
+    assert_eq!(config.number_of_channels, 1);
+    let index = track.index;
+
+    let chunks: Vec<_> = demuxer.collect::<Result<_, _>>().unwrap();
+    assert!(chunks.len() > 10, "only {} chunks", chunks.len());
+    let frame_duration = 1_000_000 * MP3_FRAME_SAMPLES / 44_100;
+    for (i, (track, chunk)) in chunks.iter().enumerate() {
+        assert_eq!(*track, index);
+        assert!(matches!(chunk, EncodedChunk::Audio(_)));
+        assert!(chunk.is_key());
+        assert!(!chunk.data().is_empty());
+        // Rounded to the container's time base, so allow a little drift per frame.
+        let expected = i as i64 * frame_duration;
+        assert!(
+            (chunk.timestamp() - chunks[0].1.timestamp() - expected).abs() <= i as i64 + 1,
+            "chunk {i} at {} us, expected {expected} us",
+            chunk.timestamp()
+        );
+    }
+}
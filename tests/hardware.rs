@@ -0,0 +1,84 @@
+//! Checks that codecs work without a hardware device whatever their hardware acceleration
+//! preference.
+#![cfg(feature = "ffmpeg")]
+
+use std::sync::mpsc;
+
+use wcodecs::{
+    codec::{
+        HardwareAcceleration, VideoDecoder, VideoEncoder, VideoEncoderConfig,
+        VideoEncoderEncodeOptions,
+    },
+    testing::color_bars,
+};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+const FRAME_DURATION: i64 = 33_333;
+const PREFERENCES: [HardwareAcceleration; 3] = [
+    HardwareAcceleration::NoPreference,
+    HardwareAcceleration::PreferHardware,
+    HardwareAcceleration::PreferSoftware,
+];
+
+/// Encodes five frames of `codec` and decodes them again, both with `preference`, and
+/// checks that every frame comes back.
+fn check_round_trip(codec: &str, preference: HardwareAcceleration) {
+    let (output_tx, outputs) = mpsc::channel();
+    let mut encoder = VideoEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        |error| panic!("{error}"),
+    );
+    let mut config = VideoEncoderConfig::new(codec, WIDTH, HEIGHT);
+    config.hardware_acceleration = preference;
+    encoder.configure(config).unwrap();
+    for i in 0..5 {
+        encoder
+            .encode(
+                color_bars(WIDTH, HEIGHT, i * FRAME_DURATION),
+                VideoEncoderEncodeOptions::default(),
+            )
+            .unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    let chunks: Vec<_> = outputs.try_iter().collect();
+    assert_eq!(chunks.len(), 5, "{codec} {preference:?}");
+
+    let (frame_tx, frames) = mpsc::channel();
+    let mut decoder = VideoDecoder::new(
+        move |frame| {
+            let _ = frame_tx.send(frame);
+        },
+        |error| panic!("{error}"),
+    );
+    let mut config = chunks[0].1.decoder_config.clone().unwrap();
+    config.hardware_acceleration = preference;
+    decoder.configure(config).unwrap();
+    for (chunk, _) in chunks {
+        decoder.decode(chunk).unwrap();
+    }
+    decoder.flush().unwrap().wait().unwrap();
+    let timestamps: Vec<_> = frames.try_iter().map(|frame| frame.timestamp).collect();
+    assert_eq!(
+        timestamps,
+        [0, 1, 2, 3, 4].map(|i| i * FRAME_DURATION),
+        "{codec} {preference:?}"
+    );
+}
+
+#[test]
+fn every_preference_falls_back_to_software() {
+    for preference in PREFERENCES {
+        check_round_trip("avc1.42001f", preference);
+    }
+}
+
+#[test]
+#[ignore = "needs an ffmpeg built with libvpx"]
+fn every_preference_falls_back_to_software_for_vp9() {
+    for preference in PREFERENCES {
+        check_round_trip("vp09.00.10.08", preference);
+    }
+}
@@ -0,0 +1,727 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use wcodecs::{
+    codec::{
+        AudioDecoder, AudioDecoderConfig, AudioEncoder, AudioEncoderConfig, CodecEvent,
+        EncodedVideoChunk, EncodedVideoChunkMetadata, Exception, ExceptionKind, State,
+        VideoDecoder, VideoDecoderConfig, VideoEncoder, VideoEncoderConfig,
+        VideoEncoderEncodeOptions, VideoFrame,
+    },
+    core::backend::{register_backend, AudioDecoderBackend, BackendProvider, CodecBackend},
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+    testing::{color_bars, sine_wave, MockBackend},
+};
+
+/// Outputs every input as soon as it is sent.
+const MOCK: &str = "mock";
+/// Holds back the last `DELAY` outputs until flushed.
+const DELAYED_MOCK: &str = "mock-delayed";
+const DELAY: usize = 2;
+/// Panics when configured.
+const PANICKING_CONFIGURE: &str = "mock-panicking-configure";
+/// Panics on every chunk sent.
+const PANICKING_SEND: &str = "mock-panicking-send";
+
+const SAMPLE_RATE: u32 = 48_000;
+const CHANNELS: u32 = 2;
+/// 10 ms at `SAMPLE_RATE`.
+const FRAMES: u32 = 480;
+const CHUNK_DURATION: i64 = 10_000;
+
+fn register_mocks() {
+    static REGISTERED: Once = Once::new();
+    REGISTERED.call_once(|| {
+        register_backend(Arc::new(MockBackend::new(MOCK)));
+        register_backend(Arc::new(MockBackend::new(DELAYED_MOCK).with_delay(DELAY)));
+        register_backend(Arc::new(PanickingBackend));
+    });
+}
+
+/// Audio decoders that panic instead of returning an error, like a buggy codec library.
+struct PanickingBackend;
+
+impl BackendProvider for PanickingBackend {
+    fn name(&self) -> &str {
+        "panicking"
+    }
+
+    fn audio_decoder(&self, codec: &str) -> Option<Box<AudioDecoderBackend>> {
+        [PANICKING_CONFIGURE, PANICKING_SEND]
+            .contains(&codec)
+            .then(|| Box::new(PanickingDecoder) as Box<AudioDecoderBackend>)
+    }
+}
+
+struct PanickingDecoder;
+
+impl CodecBackend for PanickingDecoder {
+    type Config = AudioDecoderConfig;
+    type Input = EncodedAudioChunk;
+    type Output = AudioData;
+
+    fn configure(&mut self, config: &AudioDecoderConfig) -> Result<(), Exception> {
+        if config.codec == PANICKING_CONFIGURE {
+            panic!("configure blew up");
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, _chunk: EncodedAudioChunk) -> Result<(), Exception> {
+        panic!("send blew up");
+    }
+
+    fn receive(&mut self) -> Result<Option<AudioData>, Exception> {
+        Ok(None)
+    }
+
+    fn flush(&mut self) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+fn audio_encoder_config(codec: &str) -> AudioEncoderConfig {
+    AudioEncoderConfig {
+        codec: codec.to_string(),
+        sample_rate: SAMPLE_RATE,
+        number_of_channels: CHANNELS,
+        bitrate: None,
+        opus: None,
+        aac: None,
+    }
+}
+
+fn audio_decoder_config(codec: &str) -> AudioDecoderConfig {
+    AudioDecoderConfig {
+        codec: codec.to_string(),
+        sample_rate: SAMPLE_RATE,
+        number_of_channels: CHANNELS,
+        description: None,
+    }
+}
+
+fn video_decoder_config(codec: &str) -> VideoDecoderConfig {
+    VideoDecoderConfig {
+        codec: codec.to_string(),
+        coded_width: None,
+        coded_height: None,
+        description: None,
+        hardware_acceleration: Default::default(),
+    }
+}
+
+/// The `i`th 10 ms of a continuous sine wave.
+fn sine_chunk(i: i64) -> AudioData {
+    sine_wave(
+        440.0,
+        SAMPLE_RATE,
+        CHANNELS,
+        FRAMES,
+        (i * CHUNK_DURATION) as f64,
+    )
+}
+
+type AudioChunk = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+type VideoChunk = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+fn audio_encoder() -> (AudioEncoder, Receiver<AudioChunk>, Receiver<Exception>) {
+    register_mocks();
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let encoder = AudioEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    (encoder, outputs, errors)
+}
+
+/// An encoder whose output callback blocks until the returned sender is dropped, which keeps
+/// every job behind the first output waiting on the work queue.
+fn gated_audio_encoder() -> (AudioEncoder, Sender<()>, Receiver<Exception>) {
+    register_mocks();
+    let (gate, gate_rx) = mpsc::channel::<()>();
+    let gate_rx = Mutex::new(gate_rx);
+    let (error_tx, errors) = mpsc::channel();
+    let encoder = AudioEncoder::new(
+        move |_, _| {
+            let _ = gate_rx.lock().unwrap().recv();
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    (encoder, gate, errors)
+}
+
+/// Opens `gate` once the caller had time to reset or close the codec behind it.
+fn open_later(gate: Sender<()>) {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(gate);
+    });
+}
+
+fn audio_decoder() -> (AudioDecoder, Receiver<AudioData>, Receiver<Exception>) {
+    register_mocks();
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let decoder = AudioDecoder::new(
+        move |data| {
+            let _ = output_tx.send(data);
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    (decoder, outputs, errors)
+}
+
+fn video_encoder() -> (VideoEncoder, Receiver<VideoChunk>, Receiver<Exception>) {
+    register_mocks();
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let encoder = VideoEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    (encoder, outputs, errors)
+}
+
+fn video_decoder() -> (VideoDecoder, Receiver<VideoFrame>, Receiver<Exception>) {
+    register_mocks();
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let decoder = VideoDecoder::new(
+        move |frame| {
+            let _ = output_tx.send(frame);
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    (decoder, outputs, errors)
+}
+
+/// Polls `condition` until it holds, failing the test after a second.
+fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn next_error(errors: &Receiver<Exception>) -> Exception {
+    errors
+        .recv_timeout(Duration::from_secs(1))
+        .expect("no error reported")
+}
+
+#[test]
+fn audio_round_trip_restores_every_sample_in_order() {
+    let (mut encoder, chunks, _) = audio_encoder();
+    encoder
+        .configure(audio_encoder_config(DELAYED_MOCK))
+        .unwrap();
+    let inputs: Vec<AudioData> = (0..6).map(sine_chunk).collect();
+    for data in &inputs {
+        encoder.encode(data.clone()).unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    let chunks: Vec<AudioChunk> = chunks.try_iter().collect();
+
+    assert_eq!(chunks.len(), inputs.len());
+    for (i, (chunk, metadata)) in chunks.iter().enumerate() {
+        assert_eq!(chunk.timestamp, i as i64 * CHUNK_DURATION);
+        assert_eq!(chunk.duration, Some(CHUNK_DURATION as u64));
+        assert!(chunk.is_key);
+        assert_eq!(metadata.decoder_config.is_some(), i == 0);
+    }
+
+    let (mut decoder, outputs, _) = audio_decoder();
+    let config = chunks[0].1.decoder_config.clone().unwrap();
+    assert_eq!(config, audio_decoder_config(DELAYED_MOCK));
+    decoder.configure(config).unwrap();
+    for (chunk, _) in chunks {
+        decoder.decode(chunk).unwrap();
+    }
+    decoder.flush().unwrap().wait().unwrap();
+    let outputs: Vec<AudioData> = outputs.try_iter().collect();
+
+    assert_eq!(outputs.len(), inputs.len());
+    for (output, input) in outputs.iter().zip(&inputs) {
+        assert_eq!(output.format, input.format);
+        assert_eq!(output.number_of_frames, input.number_of_frames);
+        assert_eq!(output.timestamp, input.timestamp);
+        assert_eq!(output.duration, input.duration);
+        assert_eq!(output.data, input.data);
+    }
+}
+
+#[test]
+fn delayed_outputs_are_held_until_flush() {
+    let (mut encoder, chunks, _) = audio_encoder();
+    encoder
+        .configure(audio_encoder_config(DELAYED_MOCK))
+        .unwrap();
+    for i in 0..DELAY as i64 + 3 {
+        encoder.encode(sine_chunk(i)).unwrap();
+    }
+    wait_until(|| encoder.encode_queue_size() == 0);
+    assert_eq!(chunks.try_iter().count(), 3);
+
+    encoder.flush().unwrap().wait().unwrap();
+    assert_eq!(chunks.try_iter().count(), DELAY);
+}
+
+#[test]
+fn reconfigure_drains_outputs_of_the_previous_config_first() {
+    let (mut encoder, chunks, _) = audio_encoder();
+    encoder
+        .configure(audio_encoder_config(DELAYED_MOCK))
+        .unwrap();
+    for i in 0..2 {
+        encoder.encode(sine_chunk(i)).unwrap();
+    }
+    encoder.configure(audio_encoder_config(MOCK)).unwrap();
+    encoder.encode(sine_chunk(2)).unwrap();
+    encoder.flush().unwrap().wait().unwrap();
+    let chunks: Vec<AudioChunk> = chunks.try_iter().collect();
+
+    let timestamps: Vec<i64> = chunks.iter().map(|(chunk, _)| chunk.timestamp).collect();
+    assert_eq!(timestamps, [0, CHUNK_DURATION, 2 * CHUNK_DURATION]);
+    let codecs: Vec<Option<String>> = chunks
+        .iter()
+        .map(|(_, metadata)| metadata.decoder_config.as_ref().map(|c| c.codec.clone()))
+        .collect();
+    assert_eq!(
+        codecs,
+        [Some(DELAYED_MOCK.to_string()), None, Some(MOCK.to_string())]
+    );
+}
+
+#[test]
+fn reset_rejects_pending_flush_with_abort_error() {
+    let (mut encoder, gate, errors) = gated_audio_encoder();
+    encoder.configure(audio_encoder_config(MOCK)).unwrap();
+    encoder.encode(sine_chunk(0)).unwrap();
+    let flushed = encoder.flush().unwrap();
+    open_later(gate);
+    encoder.reset().unwrap();
+
+    assert_eq!(
+        flushed.wait().unwrap_err().kind(),
+        ExceptionKind::AbortError
+    );
+    assert_eq!(encoder.state(), State::Unconfigured);
+    assert_eq!(encoder.encode_queue_size(), 0);
+    assert_eq!(
+        encoder.encode(sine_chunk(1)).unwrap_err().kind(),
+        ExceptionKind::InvalidStateError
+    );
+    assert!(errors.try_recv().is_err());
+}
+
+#[test]
+fn output_callback_can_close_its_codec() {
+    register_mocks();
+    let encoder: Arc<Mutex<Option<AudioEncoder>>> = Arc::default();
+    let in_callback = encoder.clone();
+    let (output_tx, outputs) = mpsc::channel();
+    *encoder.lock().unwrap() = Some(AudioEncoder::new(
+        move |chunk, _| {
+            let _ = output_tx.send(chunk.timestamp);
+            if let Some(encoder) = in_callback.lock().unwrap().as_mut() {
+                encoder.close();
+            }
+        },
+        |error| panic!("{error}"),
+    ));
+    let flushed = {
+        let mut encoder = encoder.lock().unwrap();
+        let encoder = encoder.as_mut().unwrap();
+        encoder
+            .configure(audio_encoder_config(DELAYED_MOCK))
+            .unwrap();
+        for i in 0..DELAY as i64 {
+            encoder.encode(sine_chunk(i)).unwrap();
+        }
+        encoder.flush().unwrap()
+    };
+
+    // The flush drains both chunks in one job, which stops once the first one closed the
+    // encoder.
+    assert_eq!(
+        flushed.wait().unwrap_err().kind(),
+        ExceptionKind::AbortError
+    );
+    assert_eq!(outputs.try_iter().collect::<Vec<_>>(), [0]);
+    let encoder = encoder.lock().unwrap();
+    assert_eq!(encoder.as_ref().unwrap().state(), State::Closed);
+}
+
+#[test]
+fn channels_mark_flushes_after_their_outputs() {
+    register_mocks();
+    let (mut encoder, events) = AudioEncoder::with_bounded_channel(1);
+    encoder
+        .configure(audio_encoder_config(DELAYED_MOCK))
+        .unwrap();
+    let consumer = thread::spawn(move || {
+        events
+            .iter()
+            .take(6)
+            .map(|event| match event {
+                CodecEvent::Output((chunk, _)) => Some(chunk),
+                CodecEvent::Flushed => None,
+                CodecEvent::Error(error) => panic!("{error}"),
+            })
+            .collect::<Vec<_>>()
+    });
+    for i in 0..3 {
+        encoder.encode(sine_chunk(i)).unwrap();
+    }
+    encoder.flush().unwrap();
+    encoder.encode(sine_chunk(3)).unwrap();
+    encoder.flush().unwrap().wait().unwrap();
+    let events = consumer.join().unwrap();
+
+    let timestamps: Vec<_> = events
+        .iter()
+        .map(|chunk| chunk.as_ref().map(|chunk| chunk.timestamp / CHUNK_DURATION))
+        .collect();
+    assert_eq!(timestamps, [Some(0), Some(1), Some(2), None, Some(3), None]);
+
+    // Errors of the calls themselves are returned, so they do not wait for room in a full
+    // channel.
+    let (mut decoder, outputs) = AudioDecoder::with_bounded_channel(1);
+    decoder.configure(audio_decoder_config(MOCK)).unwrap();
+    decoder.decode(events[0].clone().unwrap()).unwrap();
+    wait_until(|| decoder.decode_queue_size() == 0);
+    decoder.reset().unwrap();
+    assert_eq!(
+        decoder
+            .decode(events[1].clone().unwrap())
+            .unwrap_err()
+            .kind(),
+        ExceptionKind::InvalidStateError
+    );
+    assert!(matches!(outputs.try_recv(), Ok(CodecEvent::Output(_))));
+    assert!(outputs.try_recv().is_err());
+}
+
+#[test]
+fn reset_codec_can_be_configured_again() {
+    let (mut encoder, chunks, _) = audio_encoder();
+    encoder
+        .configure(audio_encoder_config(DELAYED_MOCK))
+        .unwrap();
+    encoder.encode(sine_chunk(0)).unwrap();
+    encoder.reset().unwrap();
+    assert_eq!(chunks.try_iter().count(), 0);
+
+    encoder.configure(audio_encoder_config(MOCK)).unwrap();
+    encoder.encode(sine_chunk(1)).unwrap();
+    encoder.flush().unwrap().wait().unwrap();
+    let chunks: Vec<AudioChunk> = chunks.try_iter().collect();
+
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].0.timestamp, CHUNK_DURATION);
+    assert!(chunks[0].1.decoder_config.is_some());
+}
+
+#[test]
+fn close_rejects_pending_flush_and_later_calls() {
+    let (mut encoder, gate, errors) = gated_audio_encoder();
+    encoder.configure(audio_encoder_config(MOCK)).unwrap();
+    encoder.encode(sine_chunk(0)).unwrap();
+    let flushed = encoder.flush().unwrap();
+    open_later(gate);
+    encoder.close();
+
+    assert_eq!(
+        flushed.wait().unwrap_err().kind(),
+        ExceptionKind::AbortError
+    );
+    assert_eq!(encoder.state(), State::Closed);
+    assert_eq!(
+        encoder
+            .configure(audio_encoder_config(MOCK))
+            .unwrap_err()
+            .kind(),
+        ExceptionKind::InvalidStateError
+    );
+    assert_eq!(
+        encoder.encode(sine_chunk(1)).unwrap_err().kind(),
+        ExceptionKind::InvalidStateError
+    );
+    assert!(encoder.flush().is_err());
+    assert!(errors.try_recv().is_err());
+}
+
+#[test]
+fn invalid_config_is_a_type_error() {
+    let (mut encoder, _, _) = audio_encoder();
+    let error = encoder.configure(audio_encoder_config("")).unwrap_err();
+
+    assert_eq!(error.kind(), ExceptionKind::TypeError);
+    assert_eq!(encoder.state(), State::Unconfigured);
+}
+
+#[test]
+fn scalability_modes_other_than_l1t_are_a_type_error() {
+    let (mut encoder, _, _) = video_encoder();
+    for mode in ["L1T1", "L1T2", "L1T3"] {
+        let mut config = VideoEncoderConfig::new(MOCK, 32, 16);
+        config.scalability_mode = Some(mode.to_string());
+        assert!(encoder.is_config_supported(&config), "{mode}");
+    }
+    for mode in ["L2T1", "L1T4", "S2T1", "l1t2", ""] {
+        let mut config = VideoEncoderConfig::new(MOCK, 32, 16);
+        config.scalability_mode = Some(mode.to_string());
+        let error = encoder.configure(config).unwrap_err();
+        assert_eq!(error.kind(), ExceptionKind::TypeError, "{mode:?}");
+    }
+    assert_eq!(encoder.state(), State::Unconfigured);
+}
+
+#[test]
+fn unsupported_codec_closes_the_codec() {
+    let (mut decoder, _, errors) = audio_decoder();
+    decoder
+        .configure(audio_decoder_config("mock-unregistered"))
+        .unwrap();
+
+    assert_eq!(next_error(&errors).kind(), ExceptionKind::NotSupportedError);
+    assert_eq!(decoder.state(), State::Closed);
+}
+
+#[test]
+fn decode_error_closes_the_decoder() {
+    let (mut decoder, outputs, errors) = audio_decoder();
+    decoder.configure(audio_decoder_config(MOCK)).unwrap();
+    decoder
+        .decode(EncodedAudioChunk {
+            data: b"not a mock chunk".to_vec().into(),
+            timestamp: 0,
+            duration: None,
+            is_key: true,
+        })
+        .unwrap();
+
+    assert_eq!(next_error(&errors).kind(), ExceptionKind::DecodeError);
+    wait_until(|| decoder.state() == State::Closed);
+    assert_eq!(decoder.decode_queue_size(), 0);
+    assert!(outputs.try_recv().is_err());
+}
+
+fn any_chunk(timestamp: i64) -> EncodedAudioChunk {
+    EncodedAudioChunk {
+        data: vec![0; 16].into(),
+        timestamp,
+        duration: None,
+        is_key: true,
+    }
+}
+
+#[test]
+fn backend_panics_are_reported_as_decode_errors() {
+    let (mut decoder, outputs, errors) = audio_decoder();
+    decoder
+        .configure(audio_decoder_config(PANICKING_SEND))
+        .unwrap();
+    decoder.decode(any_chunk(0)).unwrap();
+    decoder.decode(any_chunk(10_000)).unwrap();
+    // Rejected by the panicking decode job, unless it already closed the decoder.
+    let flushed = decoder.flush();
+
+    let error = next_error(&errors);
+    assert_eq!(error.kind(), ExceptionKind::DecodeError);
+    assert!(error.message().contains("send blew up"));
+    assert!(flushed.and_then(|flushed| flushed.wait()).is_err());
+    assert_eq!(decoder.state(), State::Closed);
+    assert_eq!(decoder.decode_queue_size(), 0);
+    // One panic, one error: the second chunk was dropped with the closed decoder.
+    assert!(errors.recv_timeout(Duration::from_millis(50)).is_err());
+    assert!(outputs.try_recv().is_err());
+}
+
+#[test]
+fn configure_panics_close_the_codec_without_blocking_its_queue() {
+    let (mut decoder, _, errors) = audio_decoder();
+    decoder
+        .configure(audio_decoder_config(PANICKING_CONFIGURE))
+        .unwrap();
+    // Queued behind the blocked configure and rejected when the panic closes the decoder,
+    // unless the panic closed it first.
+    let flushed = decoder.flush();
+
+    let error = next_error(&errors);
+    assert_eq!(error.kind(), ExceptionKind::DecodeError);
+    assert!(error.message().contains("configure blew up"));
+    assert!(flushed.and_then(|flushed| flushed.wait()).is_err());
+    assert_eq!(decoder.state(), State::Closed);
+    assert_eq!(
+        decoder.flush().err().unwrap().kind(),
+        ExceptionKind::InvalidStateError
+    );
+}
+
+#[test]
+fn encode_error_closes_the_encoder() {
+    let (mut encoder, chunks, errors) = audio_encoder();
+    encoder.configure(audio_encoder_config(MOCK)).unwrap();
+    encoder
+        .encode(sine_wave(440.0, SAMPLE_RATE, CHANNELS + 1, FRAMES, 0.0))
+        .unwrap();
+    // Rejected by the failing encode job, unless it already closed the encoder.
+    let flushed = encoder.flush();
+
+    assert_eq!(next_error(&errors).kind(), ExceptionKind::EncodingError);
+    assert!(flushed.and_then(|flushed| flushed.wait()).is_err());
+    assert_eq!(encoder.state(), State::Closed);
+    assert!(chunks.try_recv().is_err());
+}
+
+#[test]
+fn video_round_trip_restores_every_frame_in_order() {
+    let (mut encoder, chunks, _) = video_encoder();
+    encoder
+        .configure(VideoEncoderConfig::new(DELAYED_MOCK, 64, 48))
+        .unwrap();
+    let inputs: Vec<VideoFrame> = (0..5)
+        .map(|i| {
+            let mut frame = color_bars(64, 48, i * 33_333);
+            frame.duration = Some(33_333);
+            frame
+        })
+        .collect();
+    for frame in &inputs {
+        encoder
+            .encode(frame.clone(), VideoEncoderEncodeOptions::default())
+            .unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    let chunks: Vec<VideoChunk> = chunks.try_iter().collect();
+
+    assert_eq!(chunks.len(), inputs.len());
+    let config = chunks[0].1.decoder_config.clone().unwrap();
+    assert_eq!(
+        (config.coded_width, config.coded_height),
+        (Some(64), Some(48))
+    );
+
+    let (mut decoder, outputs, _) = video_decoder();
+    decoder.configure(config).unwrap();
+    for (chunk, _) in chunks {
+        decoder.decode(chunk).unwrap();
+    }
+    decoder.flush().unwrap().wait().unwrap();
+    let outputs: Vec<VideoFrame> = outputs.try_iter().collect();
+
+    assert_eq!(outputs.len(), inputs.len());
+    for (output, input) in outputs.iter().zip(&inputs) {
+        assert_eq!(output.format, "I420");
+        assert_eq!(
+            (output.coded_width, output.coded_height),
+            (input.coded_width, input.coded_height)
+        );
+        assert_eq!(output.timestamp, input.timestamp);
+        assert_eq!(output.duration, input.duration);
+        assert_eq!(output.data, input.data);
+    }
+}
+
+#[test]
+fn video_encoder_places_key_frames() {
+    let (mut encoder, chunks, _) = video_encoder();
+    let mut config = VideoEncoderConfig::new(MOCK, 32, 16);
+    config.keyframe_interval = Some(3);
+    encoder.configure(config).unwrap();
+    for i in 0..8 {
+        let options = VideoEncoderEncodeOptions {
+            key_frame: i == 4,
+            ..Default::default()
+        };
+        encoder.encode(color_bars(32, 16, i), options).unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    // A flush starts over from a key frame.
+    encoder
+        .encode(color_bars(32, 16, 8), VideoEncoderEncodeOptions::default())
+        .unwrap();
+    encoder.flush().unwrap().wait().unwrap();
+
+    let keys: Vec<i64> = chunks
+        .try_iter()
+        .filter(|(chunk, _)| chunk.is_key)
+        .map(|(chunk, _)| chunk.timestamp)
+        .collect();
+    assert_eq!(keys, [0, 3, 4, 7, 8]);
+}
+
+#[test]
+fn decoding_starts_with_a_key_chunk() {
+    let (mut encoder, chunks, _) = video_encoder();
+    encoder
+        .configure(VideoEncoderConfig::new(MOCK, 32, 16))
+        .unwrap();
+    for i in 0..2 {
+        encoder
+            .encode(color_bars(32, 16, i), VideoEncoderEncodeOptions::default())
+            .unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    let (key, delta) = match chunks.try_iter().collect::<Vec<_>>().as_slice() {
+        [(key, _), (delta, _)] => (key.clone(), delta.clone()),
+        chunks => panic!("expected 2 chunks, got {}", chunks.len()),
+    };
+    assert!(key.is_key && !delta.is_key);
+
+    let (mut decoder, outputs, errors) = video_decoder();
+    decoder.configure(video_decoder_config(MOCK)).unwrap();
+    let error = decoder.decode(delta.clone()).unwrap_err();
+    assert_eq!(error.kind(), ExceptionKind::DecodeError);
+    // Returned, not reported: the decoder stays open.
+    assert!(errors.try_recv().is_err());
+
+    decoder.decode(key.clone()).unwrap();
+    decoder.decode(delta.clone()).unwrap();
+    decoder.flush().unwrap().wait().unwrap();
+    assert_eq!(outputs.try_iter().count(), 2);
+
+    // A flush requires a key chunk again.
+    assert!(decoder.decode(delta).is_err());
+    decoder.decode(key).unwrap();
+    decoder.flush().unwrap().wait().unwrap();
+    assert_eq!(outputs.try_iter().count(), 1);
+    assert_eq!(decoder.state(), State::Configured);
+}
+
+#[test]
+fn color_bars_fill_every_plane() {
+    let frame = color_bars(33, 17, 0);
+    // Odd sizes round the chroma planes up.
+    assert_eq!(frame.data.len(), 33 * 17 + 2 * 17 * 9);
+    // White on the left, black on the right.
+    assert_eq!(frame.data[0], 180);
+    assert_eq!(frame.data[32], 16);
+}
@@ -0,0 +1,294 @@
+//! Writes chunks with the muxer and reads them back with the demuxer.
+#![cfg(feature = "ffmpeg")]
+
+use std::{path::PathBuf, sync::mpsc, time::Duration};
+
+use wcodecs::{
+    codec::{
+        AudioDecoderConfig, EncodedVideoChunk, EncodedVideoChunkMetadata, LatencyMode,
+        VideoEncoder, VideoEncoderConfig, VideoEncoderEncodeOptions,
+    },
+    data::audio_data::EncodedAudioChunk,
+    demux::{Demuxer, EncodedChunk, TrackConfig},
+    mux::{CmafSegmenter, ContainerFormat, Muxer},
+    testing::color_bars,
+};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+const FRAME_DURATION: u64 = 40_000;
+/// Timestamps may be rounded to the time base the container picks.
+const TOLERANCE: i64 = 1_000;
+
+type VideoChunk = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+/// A file in the temp directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("wcodecs-{}-{name}", std::process::id())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Encodes `frames` frames of H.264 starting at `start`.
+fn encode_avc(
+    frames: i64,
+    start: i64,
+    configure: impl FnOnce(&mut VideoEncoderConfig),
+) -> Vec<VideoChunk> {
+    let (output_tx, outputs) = mpsc::channel();
+    let mut encoder = VideoEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        |error| panic!("{error}"),
+    );
+    let mut config = VideoEncoderConfig::new("avc1.64001f", WIDTH, HEIGHT);
+    config.framerate = Some(1_000_000.0 / FRAME_DURATION as f64);
+    configure(&mut config);
+    encoder.configure(config).unwrap();
+    for i in 0..frames {
+        let mut frame = color_bars(WIDTH, HEIGHT, start + i * FRAME_DURATION as i64);
+        frame.duration = Some(FRAME_DURATION);
+        encoder
+            .encode(frame, VideoEncoderEncodeOptions::default())
+            .unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+    outputs.try_iter().collect()
+}
+
+/// Every chunk in `path` with the index of its track, in file order.
+fn demux(path: &TempFile) -> (Vec<TrackConfig>, Vec<(usize, EncodedChunk)>) {
+    let demuxer = Demuxer::open(&path.0).unwrap();
+    let configs = demuxer
+        .tracks()
+        .iter()
+        .map(|track| track.config.clone())
+        .collect();
+    let chunks = demuxer.collect::<Result<_, _>>().unwrap();
+    (configs, chunks)
+}
+
+/// A 20 ms CELT frame of silence.
+fn opus_chunk(timestamp: i64) -> EncodedAudioChunk {
+    EncodedAudioChunk {
+        data: vec![0xfc, 0xff, 0xfe].into(),
+        timestamp,
+        duration: Some(20_000),
+        is_key: true,
+    }
+}
+
+/// Stereo Opus without pre-skip.
+fn opus_config() -> AudioDecoderConfig {
+    let mut opus_head = b"OpusHead".to_vec();
+    opus_head.extend_from_slice(&[1, 2, 0, 0, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+    AudioDecoderConfig {
+        codec: "opus".to_string(),
+        sample_rate: 48_000,
+        number_of_channels: 2,
+        description: Some(opus_head),
+    }
+}
+
+fn contains_box(data: &[u8], kind: &[u8; 4]) -> bool {
+    data.windows(4).any(|window| window == kind)
+}
+
+fn assert_close(actual: i64, expected: i64) {
+    assert!(
+        (actual - expected).abs() <= TOLERANCE,
+        "{actual} us, expected {expected} us"
+    );
+}
+
+#[test]
+fn b_frames_keep_their_decode_and_presentation_order() {
+    let chunks = encode_avc(20, 0, |_| {});
+    assert!(
+        chunks
+            .iter()
+            .any(|(chunk, _)| chunk.decode_timestamp < Some(chunk.timestamp)),
+        "the encoder reordered no frames"
+    );
+
+    let file = TempFile::new("b-frames.mp4");
+    let mut muxer = Muxer::create(&file.0, ContainerFormat::Mp4).unwrap();
+    let config = chunks[0].1.decoder_config.clone().unwrap();
+    let track = muxer.add_video_track(&config).unwrap();
+    for (chunk, _) in &chunks {
+        muxer.write_video(track, chunk).unwrap();
+    }
+    muxer.finish().unwrap();
+
+    let (configs, demuxed) = demux(&file);
+    assert_eq!(configs.len(), 1);
+    assert_eq!(demuxed.len(), chunks.len());
+    for ((_, demuxed), (chunk, _)) in demuxed.iter().zip(&chunks) {
+        assert_eq!(demuxed.data(), &chunk.data[..]);
+        assert_eq!(demuxed.is_key(), chunk.is_key);
+        assert_close(demuxed.timestamp(), chunk.timestamp);
+    }
+    for pair in demuxed.windows(2) {
+        assert!(pair[0].1.decode_timestamp() < pair[1].1.decode_timestamp());
+    }
+    assert!(demuxed
+        .iter()
+        .all(|(_, chunk)| chunk.decode_timestamp() <= chunk.timestamp()));
+}
+
+#[test]
+fn tracks_are_rebased_on_the_earliest_chunk_of_any_track() {
+    // The video starts half a second after the audio but is written first.
+    let video = encode_avc(10, 1_500_000, |config| {
+        config.latency_mode = LatencyMode::Realtime;
+    });
+    let audio: Vec<_> = (0..50)
+        .map(|i| opus_chunk(1_000_000 + i * 20_000))
+        .collect();
+
+    let file = TempFile::new("rebased.mp4");
+    let mut muxer = Muxer::create(&file.0, ContainerFormat::Mp4).unwrap();
+    let video_track = muxer
+        .add_video_track(video[0].1.decoder_config.as_ref().unwrap())
+        .unwrap();
+    let audio_track = muxer.add_audio_track(&opus_config()).unwrap();
+    for (chunk, _) in &video {
+        muxer.write_video(video_track, chunk).unwrap();
+    }
+    for chunk in &audio {
+        muxer.write_audio(audio_track, chunk).unwrap();
+    }
+    muxer.finish().unwrap();
+
+    let (configs, demuxed) = demux(&file);
+    let first = |audio: bool| {
+        demuxed
+            .iter()
+            .find(|(index, _)| matches!(configs[*index], TrackConfig::Audio(_)) == audio)
+            .map(|(_, chunk)| chunk.timestamp())
+            .unwrap()
+    };
+    assert_close(first(true), 0);
+    assert_close(first(false), 500_000);
+    assert_eq!(demuxed.len(), video.len() + audio.len());
+}
+
+#[test]
+fn segments_start_at_key_chunks_once_the_target_duration_is_reached() {
+    // A key chunk every 10 frames, i.e. every 400 ms.
+    let chunks = encode_avc(30, 0, |config| {
+        config.latency_mode = LatencyMode::Realtime;
+        config.keyframe_interval = Some(10);
+    });
+    for (i, (chunk, _)) in chunks.iter().enumerate() {
+        assert_eq!(chunk.is_key, i % 10 == 0, "chunk {i}");
+    }
+    let mut segmenter = CmafSegmenter::new(Duration::from_millis(500)).unwrap();
+    let config = chunks[0].1.decoder_config.clone().unwrap();
+    let track = segmenter.add_track(&TrackConfig::Video(config)).unwrap();
+    let init = segmenter.init_segment().unwrap();
+    assert_eq!(&init[4..8], b"ftyp");
+    assert!(contains_box(&init, b"moov"));
+
+    let mut segments = Vec::new();
+    for (chunk, _) in chunks {
+        segments.extend(segmenter.push(track, &EncodedChunk::Video(chunk)).unwrap());
+    }
+    segments.extend(segmenter.finish().unwrap());
+
+    // Key chunks at 0.4 s fall short of the target, so segments start at 0 and 0.8 s.
+    let timing: Vec<_> = segments
+        .iter()
+        .map(|segment| (segment.timestamp, segment.duration))
+        .collect();
+    assert_eq!(timing, [(0, 800_000), (800_000, 400_000)]);
+    assert!(segments
+        .iter()
+        .all(|segment| contains_box(&segment.data, b"moof")));
+}
+
+#[test]
+fn chunks_behind_the_segment_start_do_not_cut_a_segment() {
+    let mut segmenter = CmafSegmenter::new(Duration::from_millis(100)).unwrap();
+    let first = segmenter
+        .add_track(&TrackConfig::Audio(opus_config()))
+        .unwrap();
+    let second = segmenter
+        .add_track(&TrackConfig::Audio(opus_config()))
+        .unwrap();
+    segmenter.init_segment().unwrap();
+
+    // The first track runs 5 ms ahead and, past the first pair, is pushed first, so each
+    // segment it starts has a chunk of the second track land just before its start.
+    let mut segments = Vec::new();
+    for i in 0..20 {
+        let mut pushes = [(first, 5_000 + i * 20_000), (second, i * 20_000)];
+        if i == 0 {
+            pushes.reverse();
+        }
+        for (track, timestamp) in pushes {
+            let chunk = EncodedChunk::Audio(opus_chunk(timestamp));
+            segments.extend(segmenter.push(track, &chunk).unwrap());
+        }
+    }
+    segments.extend(segmenter.finish().unwrap());
+
+    let timing: Vec<_> = segments
+        .iter()
+        .map(|segment| (segment.timestamp, segment.duration))
+        .collect();
+    assert_eq!(
+        timing,
+        [
+            (0, 105_000),
+            (105_000, 100_000),
+            (205_000, 100_000),
+            (305_000, 100_000)
+        ]
+    );
+}
+
+/// Writes a second of Opus to `format` and checks it reads back unchanged.
+fn assert_opus_round_trips(format: ContainerFormat, name: &str) {
+    let chunks: Vec<_> = (0..50).map(|i| opus_chunk(i * 20_000)).collect();
+    let file = TempFile::new(name);
+    let mut muxer = Muxer::create(&file.0, format).unwrap();
+    let track = muxer.add_audio_track(&opus_config()).unwrap();
+    for chunk in &chunks {
+        muxer.write_audio(track, chunk).unwrap();
+    }
+    muxer.finish().unwrap();
+
+    let (configs, demuxed) = demux(&file);
+    let [TrackConfig::Audio(config)] = &configs[..] else {
+        panic!("expected one audio track, found {configs:?}");
+    };
+    assert_eq!(config.codec, "opus");
+    assert_eq!(config.sample_rate, 48_000);
+    assert_eq!(config.number_of_channels, 2);
+    assert_eq!(demuxed.len(), chunks.len());
+    for ((index, demuxed), chunk) in demuxed.iter().zip(&chunks) {
+        assert_eq!(*index, track);
+        assert_eq!(demuxed.data(), &chunk.data[..]);
+        assert_close(demuxed.timestamp(), chunk.timestamp);
+    }
+}
+
+#[test]
+fn opus_round_trips_through_webm() {
+    assert_opus_round_trips(ContainerFormat::WebM, "opus.webm");
+}
+
+#[test]
+fn opus_round_trips_through_ogg() {
+    assert_opus_round_trips(ContainerFormat::Ogg, "opus.ogg");
+}
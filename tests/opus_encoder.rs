@@ -0,0 +1,129 @@
+//! Checks that the Opus encoder config reaches libopus.
+#![cfg(feature = "ffmpeg")]
+
+use std::{sync::mpsc, time::Duration};
+
+use wcodecs::{
+    codec::{AudioEncoder, AudioEncoderConfig, Exception, OpusApplication, OpusEncoderConfig},
+    testing::sine_wave,
+};
+
+const SAMPLE_RATE: u32 = 48_000;
+/// 20 ms at `SAMPLE_RATE`.
+const FRAMES: u32 = 960;
+
+/// Encodes a second of mono audio, a 440 Hz tone or silence, and returns every chunk's
+/// bytes, or the error that closed the encoder.
+fn encode(opus: OpusEncoderConfig, silence: bool) -> Result<Vec<Vec<u8>>, Exception> {
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let mut encoder = AudioEncoder::new(
+        move |chunk, _| {
+            let _ = output_tx.send(chunk.data.to_vec());
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    encoder
+        .configure(AudioEncoderConfig {
+            codec: "opus".to_string(),
+            sample_rate: SAMPLE_RATE,
+            number_of_channels: 1,
+            bitrate: Some(32_000),
+            opus: Some(opus),
+            aac: None,
+        })
+        .unwrap();
+    let frequency = if silence { 0.0 } else { 440.0 };
+    let encoded = (0..50)
+        .try_for_each(|i| {
+            let timestamp = i as f64 * 20_000.0;
+            encoder.encode(sine_wave(frequency, SAMPLE_RATE, 1, FRAMES, timestamp))
+        })
+        .and_then(|()| encoder.flush())
+        .and_then(|flushed| flushed.wait());
+    if encoded.is_err() {
+        return Err(errors
+            .recv_timeout(Duration::from_secs(1))
+            .expect("a failed encoder reports an error"));
+    }
+    Ok(outputs.try_iter().collect())
+}
+
+fn voip() -> OpusEncoderConfig {
+    OpusEncoderConfig {
+        application: OpusApplication::Voip,
+        ..Default::default()
+    }
+}
+
+fn total_size(chunks: &[Vec<u8>]) -> usize {
+    chunks.iter().map(Vec::len).sum()
+}
+
+#[test]
+fn packet_loss_and_fec_change_the_encoded_packets() {
+    let plain = encode(voip(), false).unwrap();
+    let lossy = encode(
+        OpusEncoderConfig {
+            packetlossperc: 30,
+            ..voip()
+        },
+        false,
+    )
+    .unwrap();
+    let with_fec = encode(
+        OpusEncoderConfig {
+            packetlossperc: 30,
+            useinbandfec: true,
+            ..voip()
+        },
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(plain.len(), lossy.len());
+    assert_ne!(plain, lossy);
+    assert_ne!(lossy, with_fec);
+}
+
+#[test]
+fn dtx_shrinks_silence() {
+    let plain = encode(voip(), true).unwrap();
+    let dtx = encode(
+        OpusEncoderConfig {
+            usedtx: true,
+            ..voip()
+        },
+        true,
+    )
+    .unwrap();
+    assert!(
+        total_size(&dtx) < total_size(&plain),
+        "{} bytes with DTX, {} without",
+        total_size(&dtx),
+        total_size(&plain)
+    );
+}
+
+#[test]
+fn lowdelay_application_encodes_only_celt() {
+    let chunks = encode(
+        OpusEncoderConfig {
+            application: OpusApplication::Lowdelay,
+            ..Default::default()
+        },
+        false,
+    )
+    .unwrap();
+    // The configuration number in the top five bits of the TOC byte is 16 to 31 for CELT.
+    assert!(!chunks.is_empty());
+    for (i, chunk) in chunks.iter().enumerate() {
+        assert!(
+            chunk[0] >> 3 >= 16,
+            "chunk {i} has TOC byte {:#04x}",
+            chunk[0]
+        );
+    }
+}
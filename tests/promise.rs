@@ -0,0 +1,55 @@
+use std::{thread, time::Duration};
+
+use wcodecs::{
+    codec::{Exception, ExceptionKind},
+    core::promise::Promise,
+    testing::block_on,
+};
+
+#[test]
+fn every_clone_sees_the_result() {
+    let promise = Promise::new();
+    let clone = promise.clone();
+    promise.resolve(7);
+
+    assert_eq!(promise.clone().wait().unwrap(), 7);
+    assert_eq!(clone.clone().wait().unwrap(), 7);
+    assert_eq!(block_on(clone).unwrap(), 7);
+    assert_eq!(block_on(promise).unwrap(), 7);
+}
+
+#[test]
+fn only_the_first_settlement_counts() {
+    let promise = Promise::new();
+    promise.reject(Exception::new(ExceptionKind::AbortError, "aborted"));
+    promise.resolve(());
+
+    assert_eq!(
+        promise.clone().wait().unwrap_err().kind(),
+        ExceptionKind::AbortError
+    );
+    assert!(promise.is_settled());
+}
+
+#[test]
+fn waiters_on_several_clones_are_all_woken() {
+    let promise = Promise::<u32>::new();
+    let waiters: Vec<_> = (0..2)
+        .map(|i| {
+            let clone = promise.clone();
+            thread::spawn(move || {
+                if i == 0 {
+                    clone.wait()
+                } else {
+                    block_on(clone)
+                }
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(20));
+    promise.resolve(3);
+
+    for waiter in waiters {
+        assert_eq!(waiter.join().unwrap().unwrap(), 3);
+    }
+}
@@ -0,0 +1,305 @@
+//! Encodes generated signals with every codec this build supports, decodes them back and
+//! checks what comes out. Each case only runs in builds with a backend for its codec, and
+//! fails if that backend does not support it. The cases for codecs ffmpeg may be built
+//! without are ignored; `cargo test -- --include-ignored` runs them.
+
+use std::{
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use wcodecs::{
+    codec::{AudioDecoder, AudioEncoder, AudioEncoderConfig, CodecEvent, Exception},
+    core::pcm,
+    data::audio_data::{AudioData, EncodedAudioChunk, EncodedAudioChunkMetadata},
+    testing::{output_delay, sine_wave, snr},
+};
+
+const SAMPLE_RATE: u32 = 48_000;
+const CHANNELS: u32 = 2;
+/// 20 ms at `SAMPLE_RATE`.
+const FRAMES: u32 = 960;
+const AUDIO_CHUNKS: u32 = 25;
+/// The most priming samples an encoder may prepend, e.g. 1024 for AAC.
+const MAX_PRIMING: usize = 2048;
+/// The most padding an encoder may append to complete its last frame.
+const MAX_PADDING: u32 = 4800;
+/// The samples the output delay is measured over.
+const DELAY_WINDOW: usize = 2400;
+
+type AudioChunk = (EncodedAudioChunk, EncodedAudioChunkMetadata);
+
+/// Waits for the error that closed a codec.
+fn closing_error(errors: &Receiver<Exception>) -> Exception {
+    errors
+        .recv_timeout(Duration::from_secs(1))
+        .expect("a failed codec reports an error")
+}
+
+fn audio_inputs() -> Vec<AudioData> {
+    (0..AUDIO_CHUNKS)
+        .map(|i| {
+            let timestamp = i as f64 * FRAMES as f64 * 1_000_000.0 / SAMPLE_RATE as f64;
+            sine_wave(440.0, SAMPLE_RATE, CHANNELS, FRAMES, timestamp)
+        })
+        .collect()
+}
+
+/// Encodes `inputs` and flushes.
+fn encode_audio(codec: &str, inputs: &[AudioData]) -> Vec<AudioChunk> {
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let mut encoder = AudioEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    encoder
+        .configure(AudioEncoderConfig {
+            codec: codec.to_string(),
+            sample_rate: SAMPLE_RATE,
+            number_of_channels: CHANNELS,
+            bitrate: None,
+            opus: None,
+            aac: None,
+        })
+        .unwrap();
+    let encoded = inputs
+        .iter()
+        .try_for_each(|data| encoder.encode(data.clone()))
+        .and_then(|()| encoder.flush())
+        .and_then(|flushed| flushed.wait());
+    if encoded.is_err() {
+        panic!("{codec}: {}", closing_error(&errors));
+    }
+    outputs.try_iter().collect()
+}
+
+fn decode_audio(codec: &str, chunks: Vec<AudioChunk>) -> Vec<AudioData> {
+    let (mut decoder, outputs) = AudioDecoder::with_channel();
+    let config = chunks[0].1.decoder_config.clone().unwrap();
+    decoder.configure(config).unwrap();
+    for (chunk, _) in chunks {
+        decoder.decode(chunk).unwrap();
+    }
+    decoder.flush().unwrap();
+    let mut decoded = Vec::new();
+    for event in outputs.iter() {
+        match event {
+            CodecEvent::Output(data) => decoded.push(data),
+            CodecEvent::Flushed => return decoded,
+            CodecEvent::Error(e) => panic!("{codec}: {e}"),
+        }
+    }
+    panic!("{codec}: the decoder was dropped before its flush completed")
+}
+
+/// Round-trips the sine waves through `codec` and checks the timing of the chunks and of the
+/// decoded audio, and that every channel is decoded with at least `min_snr` dB.
+fn check_audio_round_trip(codec: &str, min_snr: f64) {
+    let inputs = audio_inputs();
+    let chunks = encode_audio(codec, &inputs);
+    assert!(!chunks.is_empty(), "{codec}: no chunks");
+    assert!(chunks[0].1.decoder_config.is_some(), "{codec}");
+    for pair in chunks.windows(2) {
+        assert!(pair[0].0.timestamp < pair[1].0.timestamp, "{codec}");
+    }
+    assert!(chunks.iter().all(|(chunk, _)| chunk.duration.is_some()));
+
+    let outputs = decode_audio(codec, chunks);
+    assert!(!outputs.is_empty(), "{codec}: no audio");
+    for pair in outputs.windows(2) {
+        let gap = pair[1].timestamp - (pair[0].timestamp + pair[0].duration);
+        assert!(gap.abs() < 1_000.0, "{codec}: {gap} us between outputs");
+    }
+    let input_frames: u32 = inputs.iter().map(|data| data.number_of_frames).sum();
+    let output_frames: u32 = outputs.iter().map(|data| data.number_of_frames).sum();
+    assert!(
+        (input_frames..=input_frames + MAX_PRIMING as u32 + MAX_PADDING).contains(&output_frames),
+        "{codec}: decoded {output_frames} frames of {input_frames}"
+    );
+
+    let reference = planes(&inputs);
+    let decoded = planes(&outputs);
+    assert_eq!(decoded.len(), reference.len(), "{codec}");
+    let delay = output_delay(&reference[0][..DELAY_WINDOW], &decoded[0], MAX_PRIMING);
+    for (ch, (reference, decoded)) in reference.iter().zip(&decoded).enumerate() {
+        let snr = snr(reference, &decoded[delay..]);
+        assert!(snr >= min_snr, "{codec}: channel {ch} at {snr:.1} dB");
+    }
+}
+
+/// The samples of every channel, concatenated over `data`.
+fn planes(data: &[AudioData]) -> Vec<Vec<f32>> {
+    let mut planes: Vec<Vec<f32>> = Vec::new();
+    for data in data {
+        let data_planes = pcm::f32_planes(data).unwrap();
+        planes.resize(data_planes.len(), Vec::new());
+        for (plane, samples) in planes.iter_mut().zip(data_planes) {
+            plane.extend(samples);
+        }
+    }
+    planes
+}
+
+#[test]
+fn pcm_round_trip() {
+    check_audio_round_trip("pcm-f32", f64::INFINITY);
+    check_audio_round_trip("pcm-s32", 120.0);
+    check_audio_round_trip("pcm-s24", 120.0);
+    check_audio_round_trip("pcm-s16", 85.0);
+    check_audio_round_trip("pcm-u8", 35.0);
+}
+
+#[test]
+fn g711_round_trip() {
+    check_audio_round_trip("ulaw", 30.0);
+    check_audio_round_trip("alaw", 30.0);
+}
+
+#[test]
+#[cfg(any(feature = "ffmpeg", feature = "pure-rust"))]
+fn flac_round_trip() {
+    // Encoded at 16 bits.
+    check_audio_round_trip("flac", 85.0);
+}
+
+// Perceptual codecs keep what is audible rather than the waveform, so their SNR is low.
+
+#[test]
+#[cfg(feature = "ffmpeg")]
+fn opus_round_trip() {
+    check_audio_round_trip("opus", 10.0);
+}
+
+#[test]
+#[cfg(feature = "ffmpeg")]
+fn aac_round_trip() {
+    check_audio_round_trip("mp4a.40.2", 10.0);
+}
+
+/// The video codecs, which only ffmpeg backs.
+#[cfg(feature = "ffmpeg")]
+mod video {
+    use wcodecs::{
+        codec::{
+            EncodedVideoChunk, EncodedVideoChunkMetadata, VideoDecoder, VideoEncoder,
+            VideoEncoderConfig, VideoEncoderEncodeOptions, VideoFrame,
+        },
+        testing::{color_bars, psnr},
+    };
+
+    use super::*;
+
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 96;
+    const VIDEO_FRAMES: i64 = 10;
+    const FRAME_DURATION: u64 = 33_333;
+    type VideoChunk = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+    fn video_inputs() -> Vec<VideoFrame> {
+        (0..VIDEO_FRAMES)
+            .map(|i| {
+                let mut frame = color_bars(WIDTH, HEIGHT, i * FRAME_DURATION as i64);
+                frame.duration = Some(FRAME_DURATION);
+                frame
+            })
+            .collect()
+    }
+
+    /// Encodes `inputs` and flushes.
+    fn encode_video(codec: &str, inputs: &[VideoFrame]) -> Vec<VideoChunk> {
+        let (output_tx, outputs) = mpsc::channel();
+        let (error_tx, errors) = mpsc::channel();
+        let mut encoder = VideoEncoder::new(
+            move |chunk, metadata| {
+                let _ = output_tx.send((chunk, metadata));
+            },
+            move |error| {
+                let _ = error_tx.send(error);
+            },
+        );
+        let mut config = VideoEncoderConfig::new(codec, WIDTH, HEIGHT);
+        config.bitrate = Some(1_000_000);
+        config.framerate = Some(1_000_000.0 / FRAME_DURATION as f64);
+        encoder.configure(config).unwrap();
+        let encoded = inputs
+            .iter()
+            .try_for_each(|frame| {
+                encoder.encode(frame.clone(), VideoEncoderEncodeOptions::default())
+            })
+            .and_then(|()| encoder.flush())
+            .and_then(|flushed| flushed.wait());
+        if encoded.is_err() {
+            panic!("{codec}: {}", closing_error(&errors));
+        }
+        outputs.try_iter().collect()
+    }
+
+    fn decode_video(codec: &str, chunks: Vec<VideoChunk>) -> Vec<VideoFrame> {
+        let (output_tx, outputs) = mpsc::channel();
+        let mut decoder = VideoDecoder::new(
+            move |frame| {
+                let _ = output_tx.send(frame);
+            },
+            move |error| panic!("{error}"),
+        );
+        let config = chunks[0].1.decoder_config.clone().unwrap();
+        decoder.configure(config).unwrap();
+        for (chunk, _) in chunks {
+            decoder.decode(chunk).unwrap();
+        }
+        decoder
+            .flush()
+            .unwrap()
+            .wait()
+            .unwrap_or_else(|e| panic!("{codec}: {e}"));
+        outputs.try_iter().collect()
+    }
+
+    /// Round-trips the colour bars through `codec` and checks that every frame comes back in
+    /// order with its timestamp and duration, at `min_psnr` dB or better.
+    fn check_video_round_trip(codec: &str, min_psnr: f64) {
+        let inputs = video_inputs();
+        let chunks = encode_video(codec, &inputs);
+        assert_eq!(chunks.len(), inputs.len(), "{codec}");
+        assert!(chunks[0].0.is_key, "{codec}");
+        assert!(chunks[0].1.decoder_config.is_some(), "{codec}");
+
+        let outputs = decode_video(codec, chunks);
+        assert_eq!(outputs.len(), inputs.len(), "{codec}");
+        for (i, (output, input)) in outputs.iter().zip(&inputs).enumerate() {
+            assert_eq!(output.timestamp, input.timestamp, "{codec}: frame {i}");
+            assert_eq!(output.duration, input.duration, "{codec}: frame {i}");
+            let psnr = psnr(input, output).unwrap();
+            assert!(psnr >= min_psnr, "{codec}: frame {i} at {psnr:.1} dB");
+        }
+    }
+
+    // VP8, VP9 and AV1 come from libraries ffmpeg may be built without.
+    #[test]
+    #[ignore = "needs an ffmpeg built with libvpx"]
+    fn vp8_round_trip() {
+        check_video_round_trip("vp8", 30.0);
+    }
+
+    #[test]
+    #[ignore = "needs an ffmpeg built with libvpx"]
+    fn vp9_round_trip() {
+        check_video_round_trip("vp09.00.10.08", 30.0);
+    }
+
+    #[test]
+    #[ignore = "needs an ffmpeg built with libaom or SVT-AV1"]
+    fn av1_round_trip() {
+        check_video_round_trip("av01.0.04M.08", 30.0);
+    }
+
+    #[test]
+    fn h264_round_trip() {
+        check_video_round_trip("avc1.42001f", 30.0);
+    }
+}
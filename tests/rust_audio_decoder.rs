@@ -0,0 +1,277 @@
+//! Decodes MP3 and Vorbis with the symphonia backend of pure-Rust builds.
+#![cfg(feature = "pure-rust")]
+
+use wcodecs::{
+    codec::AudioDecoderConfig,
+    core::{backend::CodecBackend, pcm, rust_audio_decoder::RustAudioDecoder},
+    data::audio_data::{AudioData, EncodedAudioChunk},
+};
+
+const BEEP_MP3: &[u8] = include_bytes!("../examples/samples/beep.mp3");
+/// The samples in each MPEG-1 Layer III frame.
+const MP3_FRAME_SAMPLES: u32 = 1152;
+
+/// The block size of the generated Vorbis stream, whose packets each add half a block.
+const VORBIS_BLOCK_SIZE: u32 = 256;
+const VORBIS_SAMPLE_RATE: u32 = 48_000;
+
+/// Splits MPEG-1 Layer III data into its frames.
+fn mp3_frames(mut data: &[u8]) -> Vec<&[u8]> {
+    const KBPS: [usize; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const RATES: [usize; 3] = [44_100, 48_000, 32_000];
+    let mut frames = Vec::new();
+    while data.len() >= 4 && data[0] == 0xff && data[1] & 0xfe == 0xfa {
+        let bitrate = KBPS[usize::from(data[2] >> 4)] * 1000;
+        let sample_rate = RATES[usize::from(data[2] >> 2 & 0x3)];
+        let padding = usize::from(data[2] >> 1 & 0x1);
+        let len = (144 * bitrate / sample_rate + padding).min(data.len());
+        let (frame, rest) = data.split_at(len);
+        frames.push(frame);
+        data = rest;
+    }
+    frames
+}
+
+/// Packs values least significant bit first, as Vorbis packets are.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: usize) {
+        for i in 0..bits {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (self.bits % 8);
+            self.bits += 1;
+        }
+    }
+
+    fn header(packet_type: u32) -> Self {
+        let mut writer = Self::default();
+        writer.write(packet_type, 8);
+        for &byte in b"vorbis" {
+            writer.write(byte.into(), 8);
+        }
+        writer
+    }
+}
+
+/// The identification, comment and setup headers of a mono Vorbis stream with one short
+/// block mode. Its floor is a line between two posts and its residue reads a 1-bit entry,
+/// 0.0 or 1.0, for every spectral line.
+fn vorbis_headers() -> [Vec<u8>; 3] {
+    let mut identification = BitWriter::header(1);
+    identification.write(0, 32);
+    identification.write(1, 8);
+    identification.write(VORBIS_SAMPLE_RATE, 32);
+    identification.write(0, 32);
+    identification.write(0, 32);
+    identification.write(0, 32);
+    let exponent = VORBIS_BLOCK_SIZE.trailing_zeros();
+    identification.write(exponent, 4);
+    identification.write(exponent, 4);
+    identification.write(1, 1);
+
+    let mut comment = BitWriter::header(3);
+    comment.write(0, 32);
+    comment.write(0, 32);
+    comment.write(1, 1);
+
+    let mut setup = BitWriter::header(5);
+    // Two codebooks with one dimension and two entries of length 1: the first is scalar,
+    // for the residue classes, and the second has the values 0.0 and 1.0.
+    setup.write(1, 8);
+    for lookup in [0, 1] {
+        setup.write(0x564342, 24);
+        setup.write(1, 16);
+        setup.write(2, 24);
+        setup.write(0, 1);
+        setup.write(0, 1);
+        setup.write(0, 5);
+        setup.write(0, 5);
+        setup.write(lookup, 4);
+        if lookup == 1 {
+            // A minimum of 0.0 and a step of 1.0, as a Vorbis float: a mantissa of 1 and an
+            // exponent biased by 788. Then the 1-bit multiplier of each entry.
+            setup.write(0, 32);
+            setup.write(788 << 21 | 1, 32);
+            setup.write(0, 4);
+            setup.write(0, 1);
+            setup.write(0, 1);
+            setup.write(1, 1);
+        }
+    }
+    // One unused time domain transform.
+    setup.write(0, 6);
+    setup.write(0, 16);
+    // One floor of type 1 without partitions, whose two posts span the half block.
+    setup.write(0, 6);
+    setup.write(1, 16);
+    setup.write(0, 5);
+    setup.write(0, 2);
+    setup.write((VORBIS_BLOCK_SIZE / 2).trailing_zeros(), 4);
+    // One residue of type 1 over the half block, in partitions of 32 lines of one class
+    // that the first codebook classifies and the second decodes in the first pass.
+    setup.write(0, 6);
+    setup.write(1, 16);
+    setup.write(0, 24);
+    setup.write(VORBIS_BLOCK_SIZE / 2, 24);
+    setup.write(31, 24);
+    setup.write(0, 6);
+    setup.write(0, 8);
+    setup.write(1, 3);
+    setup.write(0, 1);
+    setup.write(1, 8);
+    // One mapping of the floor and residue, without coupling.
+    setup.write(0, 6);
+    setup.write(0, 16);
+    setup.write(0, 1);
+    setup.write(0, 1);
+    setup.write(0, 2);
+    setup.write(0, 8);
+    setup.write(0, 8);
+    setup.write(0, 8);
+    // One short block mode.
+    setup.write(0, 6);
+    setup.write(0, 1);
+    setup.write(0, 16);
+    setup.write(0, 16);
+    setup.write(0, 8);
+    setup.write(1, 1);
+
+    [identification.bytes, comment.bytes, setup.bytes]
+}
+
+/// An audio packet whose floor sits at `level`, out of 255, and whose residue is 1.0 in
+/// every spectral line.
+fn vorbis_packet(level: u32) -> Vec<u8> {
+    let mut packet = BitWriter::default();
+    packet.write(0, 1);
+    // Floor: nonzero, with both posts at `level`.
+    packet.write(1, 1);
+    packet.write(level, 8);
+    packet.write(level, 8);
+    // Residue: each partition's class, then its lines, all the entry for 1.0.
+    for _ in 0..VORBIS_BLOCK_SIZE / 2 / 32 {
+        packet.write(0, 1);
+        packet.write(u32::MAX, 32);
+    }
+    packet.bytes
+}
+
+/// The Xiph-laced `description` of `headers`.
+fn xiph_lacing(headers: &[Vec<u8>]) -> Vec<u8> {
+    let (last, rest) = headers.split_last().unwrap();
+    let mut laced = vec![rest.len() as u8];
+    for header in rest {
+        laced.extend(std::iter::repeat_n(255, header.len() / 255));
+        laced.push((header.len() % 255) as u8);
+    }
+    for header in rest {
+        laced.extend_from_slice(header);
+    }
+    laced.extend_from_slice(last);
+    laced
+}
+
+/// Decodes `chunks` one after another and returns everything decoded.
+fn decode(config: AudioDecoderConfig, chunks: Vec<(i64, Vec<u8>)>) -> Vec<AudioData> {
+    let mut decoder = RustAudioDecoder::default();
+    decoder.configure(&config).unwrap();
+    let mut outputs = Vec::new();
+    for (timestamp, data) in chunks {
+        decoder
+            .send(EncodedAudioChunk {
+                data: data.into(),
+                timestamp,
+                duration: None,
+                is_key: true,
+            })
+            .unwrap();
+        while let Some(output) = decoder.receive().unwrap() {
+            outputs.push(output);
+        }
+    }
+    decoder.flush().unwrap();
+    assert!(decoder.receive().unwrap().is_none());
+    outputs
+}
+
+fn energy(data: &AudioData) -> f64 {
+    pcm::f32_planes(data)
+        .unwrap()
+        .iter()
+        .flatten()
+        .map(|&sample| f64::from(sample).powi(2))
+        .sum()
+}
+
+#[test]
+fn decodes_mp3_frames() {
+    let frames = mp3_frames(BEEP_MP3);
+    assert!(frames.len() > 10, "{} frames", frames.len());
+    let frame_duration = 1_000_000 * i64::from(MP3_FRAME_SAMPLES) / 44_100;
+    let chunks = frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| (i as i64 * frame_duration, frame.to_vec()))
+        .collect();
+    let outputs = decode(
+        AudioDecoderConfig {
+            codec: "mp3".to_string(),
+            sample_rate: 44_100,
+            number_of_channels: 1,
+            description: None,
+        },
+        chunks,
+    );
+
+    assert_eq!(outputs.len(), frames.len());
+    for (i, output) in outputs.iter().enumerate() {
+        assert_eq!(output.format, "f32-planar");
+        assert_eq!(output.sample_rate, 44_100.0);
+        assert_eq!(output.number_of_channels, 1);
+        assert_eq!(output.number_of_frames, MP3_FRAME_SAMPLES);
+        assert_eq!(output.timestamp, (i as i64 * frame_duration) as f64);
+    }
+    assert!(outputs.iter().any(|output| energy(output) > 1.0));
+}
+
+#[test]
+fn decodes_vorbis_packets_after_the_headers_in_the_description() {
+    let headers = vorbis_headers();
+    let config = AudioDecoderConfig {
+        codec: "vorbis".to_string(),
+        sample_rate: VORBIS_SAMPLE_RATE,
+        number_of_channels: 1,
+        description: Some(xiph_lacing(&headers)),
+    };
+    let packet_duration = 1_000_000 * i64::from(VORBIS_BLOCK_SIZE / 2) / 48_000;
+    let levels = [200, 200, 200, 0, 0];
+    let chunks = levels
+        .iter()
+        .enumerate()
+        .map(|(i, &level)| (i as i64 * packet_duration, vorbis_packet(level)))
+        .collect();
+    let outputs = decode(config, chunks);
+
+    // The first packet only primes the overlap with the next one.
+    assert_eq!(outputs.len(), levels.len() - 1);
+    for (i, output) in outputs.iter().enumerate() {
+        assert_eq!(output.sample_rate, f64::from(VORBIS_SAMPLE_RATE));
+        assert_eq!(output.number_of_channels, 1);
+        assert_eq!(output.number_of_frames, VORBIS_BLOCK_SIZE / 2);
+        assert_eq!(output.timestamp, ((i + 1) as i64 * packet_duration) as f64);
+    }
+    // The floor scales the spectrum, so the quieter packets give out quieter audio.
+    let energies: Vec<_> = outputs.iter().map(energy).collect();
+    assert!(energies[0] > 0.0, "{energies:?}");
+    assert!(energies[1] > energies[3] * 100.0, "{energies:?}");
+}
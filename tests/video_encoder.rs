@@ -0,0 +1,191 @@
+//! Checks how the ffmpeg video encoders apply the config and per-frame options.
+#![cfg(feature = "ffmpeg")]
+
+use std::{sync::mpsc, time::Duration};
+
+use wcodecs::{
+    codec::{
+        EncodedVideoChunk, EncodedVideoChunkMetadata, Exception, ExceptionKind, LatencyMode,
+        QuantizerEncodeOptions, VideoEncoder, VideoEncoderBitrateMode, VideoEncoderConfig,
+        VideoEncoderEncodeOptions,
+    },
+    testing::color_bars,
+};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+const FRAME_DURATION: i64 = 33_333;
+
+type VideoChunk = (EncodedVideoChunk, EncodedVideoChunkMetadata);
+
+/// Encodes a frame with each of `options` and flushes, or returns the error that closed
+/// the encoder.
+fn encode(
+    config: VideoEncoderConfig,
+    options: &[VideoEncoderEncodeOptions],
+) -> Result<Vec<VideoChunk>, Exception> {
+    let (output_tx, outputs) = mpsc::channel();
+    let (error_tx, errors) = mpsc::channel();
+    let mut encoder = VideoEncoder::new(
+        move |chunk, metadata| {
+            let _ = output_tx.send((chunk, metadata));
+        },
+        move |error| {
+            let _ = error_tx.send(error);
+        },
+    );
+    encoder.configure(config).unwrap();
+    let encoded = options
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, options)| {
+            let frame = color_bars(WIDTH, HEIGHT, i as i64 * FRAME_DURATION);
+            encoder.encode(frame, options.clone())
+        })
+        .and_then(|()| encoder.flush())
+        .and_then(|flushed| flushed.wait());
+    if encoded.is_err() {
+        return Err(errors
+            .recv_timeout(Duration::from_secs(1))
+            .expect("a failed encoder reports an error"));
+    }
+    Ok(outputs.try_iter().collect())
+}
+
+/// Options that set `quantizer` for every codec that takes one.
+fn with_quantizer(quantizer: u32) -> VideoEncoderEncodeOptions {
+    let options = Some(QuantizerEncodeOptions {
+        quantizer: Some(quantizer),
+    });
+    VideoEncoderEncodeOptions {
+        vp9: options.clone(),
+        av1: options.clone(),
+        avc: options,
+        ..Default::default()
+    }
+}
+
+fn quantizer_config(codec: &str) -> VideoEncoderConfig {
+    let mut config = VideoEncoderConfig::new(codec, WIDTH, HEIGHT);
+    config.bitrate_mode = VideoEncoderBitrateMode::Quantizer;
+    // Without a lookahead, each frame is encoded before the next one sets its quantizer.
+    config.latency_mode = LatencyMode::Realtime;
+    config
+}
+
+fn total_size(chunks: &[VideoChunk]) -> usize {
+    chunks.iter().map(|(chunk, _)| chunk.data.len()).sum()
+}
+
+/// The mean size of the delta chunks among `chunks`.
+fn delta_size(chunks: &[VideoChunk]) -> usize {
+    let deltas: Vec<_> = chunks.iter().filter(|(chunk, _)| !chunk.is_key).collect();
+    deltas
+        .iter()
+        .map(|(chunk, _)| chunk.data.len())
+        .sum::<usize>()
+        / deltas.len().max(1)
+}
+
+/// Checks that a fine quantizer gives larger chunks than a coarse one.
+fn check_quantizer_sets_the_chunk_size(codec: &str) {
+    let fine = encode(quantizer_config(codec), &vec![with_quantizer(10); 10]).unwrap();
+    let coarse = encode(quantizer_config(codec), &vec![with_quantizer(50); 10]).unwrap();
+    assert_eq!(fine.len(), 10, "{codec}");
+    assert_eq!(coarse.len(), 10, "{codec}");
+    assert!(
+        total_size(&fine) > 2 * total_size(&coarse),
+        "{codec}: {} bytes at 10, {} at 50",
+        total_size(&fine),
+        total_size(&coarse)
+    );
+}
+
+/// Checks that an encoder which only takes the quantizer when opened rejects a change
+/// mid-stream, as reopening it would start over with a key frame.
+fn check_quantizer_change_is_not_supported(codec: &str) {
+    let mut options = vec![with_quantizer(10); 5];
+    options.extend(vec![with_quantizer(50); 5]);
+    let error = encode(quantizer_config(codec), &options).unwrap_err();
+    assert_eq!(error.kind(), ExceptionKind::NotSupportedError, "{codec}");
+}
+
+#[test]
+fn the_quantizer_of_each_frame_sets_the_chunk_size() {
+    check_quantizer_sets_the_chunk_size("avc1.42001f");
+}
+
+// VP9 and AV1 come from libraries ffmpeg may be built without.
+#[test]
+#[ignore = "needs an ffmpeg built with libvpx"]
+fn the_quantizer_sets_the_chunk_size_for_vp9() {
+    check_quantizer_sets_the_chunk_size("vp09.00.10.08");
+    check_quantizer_change_is_not_supported("vp09.00.10.08");
+}
+
+#[test]
+#[ignore = "needs an ffmpeg built with libaom or SVT-AV1"]
+fn the_quantizer_sets_the_chunk_size_for_av1() {
+    check_quantizer_sets_the_chunk_size("av01.0.04M.08");
+    check_quantizer_change_is_not_supported("av01.0.04M.08");
+}
+
+#[test]
+fn switching_the_quantizer_mid_stream_adds_no_key_frames() {
+    let mut options = Vec::new();
+    for quantizer in [10, 50, 20, 40] {
+        options.extend(vec![with_quantizer(quantizer); 5]);
+    }
+    let chunks = encode(quantizer_config("avc1.42001f"), &options).unwrap();
+    assert_eq!(chunks.len(), options.len());
+    let keys: Vec<_> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, (chunk, _))| chunk.is_key)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(keys, [0]);
+
+    // Each run of frames is encoded with its own quantizer.
+    let sizes: Vec<_> = chunks.chunks(5).map(delta_size).collect();
+    assert!(
+        sizes[0] > sizes[1] && sizes[1] < sizes[2] && sizes[2] > sizes[3],
+        "mean delta chunk sizes {sizes:?}"
+    );
+}
+
+#[test]
+fn frames_sharing_a_timestamp_each_get_a_chunk() {
+    let (output_tx, outputs) = mpsc::channel();
+    let mut encoder = VideoEncoder::new(
+        move |chunk: EncodedVideoChunk, _| {
+            let _ = output_tx.send(chunk);
+        },
+        |error| panic!("{error}"),
+    );
+    let mut config = VideoEncoderConfig::new("avc1.42001f", WIDTH, HEIGHT);
+    config.latency_mode = LatencyMode::Realtime;
+    encoder.configure(config).unwrap();
+    for (i, timestamp) in [0, 0, FRAME_DURATION].into_iter().enumerate() {
+        let mut frame = color_bars(WIDTH, HEIGHT, timestamp);
+        frame.duration = Some(10_000 * (i as u64 + 1));
+        encoder
+            .encode(frame, VideoEncoderEncodeOptions::default())
+            .unwrap();
+    }
+    encoder.flush().unwrap().wait().unwrap();
+
+    let chunks: Vec<_> = outputs.try_iter().collect();
+    let timing: Vec<_> = chunks
+        .iter()
+        .map(|chunk| (chunk.timestamp, chunk.duration))
+        .collect();
+    assert_eq!(
+        timing,
+        [
+            (0, Some(10_000)),
+            (0, Some(20_000)),
+            (FRAME_DURATION, Some(30_000))
+        ]
+    );
+}
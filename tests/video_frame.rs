@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+
+use wcodecs::{
+    codec::{
+        ExceptionKind, VideoEncoder, VideoEncoderEncodeOptions, VideoFrame, VideoFrameBufferInit,
+    },
+    testing::{color_bars, sine_wave},
+};
+
+fn i420_init(data: Cow<'_, [u8]>, transfer: bool) -> VideoFrameBufferInit<'_> {
+    VideoFrameBufferInit {
+        format: "I420".to_string(),
+        coded_width: 4,
+        coded_height: 2,
+        timestamp: 40_000,
+        duration: Some(40_000),
+        data,
+        transfer,
+    }
+}
+
+#[test]
+fn clones_share_the_buffer() {
+    let frame = color_bars(64, 48, 0);
+    let clone = frame.clone();
+    assert_eq!(clone.data.as_ptr(), frame.data.as_ptr());
+
+    let data = sine_wave(440.0, 48_000, 2, 480, 0.0);
+    assert_eq!(data.clone().data.as_ptr(), data.data.as_ptr());
+}
+
+#[test]
+fn close_releases_only_its_own_reference() {
+    let mut frame = color_bars(64, 48, 0);
+    let clone = frame.clone();
+    frame.close();
+
+    assert!(frame.is_closed());
+    assert!(frame.data.is_empty());
+    assert_eq!((frame.coded_width, frame.coded_height), (0, 0));
+    assert!(!clone.is_closed());
+    assert_eq!(clone.data.len(), 64 * 48 * 3 / 2);
+
+    let mut data = sine_wave(440.0, 48_000, 2, 480, 0.0);
+    data.close();
+    assert!(data.is_closed());
+    assert_eq!(
+        data.as_f32_planes().unwrap_err().kind(),
+        ExceptionKind::InvalidStateError
+    );
+}
+
+#[test]
+fn encoders_reject_closed_frames() {
+    // Closed frames are rejected before the encoder's state is checked.
+    let mut encoder = VideoEncoder::new(|_, _| {}, |_| {});
+    let mut frame = color_bars(64, 48, 0);
+    frame.close();
+
+    let error = encoder
+        .encode(frame, VideoEncoderEncodeOptions::default())
+        .unwrap_err();
+    assert_eq!(error.kind(), ExceptionKind::TypeError);
+}
+
+#[test]
+fn try_new_copies_borrowed_pixels() {
+    let pixels: Vec<u8> = (0..12).collect();
+    let frame = VideoFrame::try_new(i420_init(Cow::Borrowed(&pixels), false)).unwrap();
+
+    assert_ne!(frame.data.as_ptr(), pixels.as_ptr());
+    assert_eq!(frame.data, pixels);
+    assert_eq!(frame.timestamp, 40_000);
+    assert_eq!(frame.duration, Some(40_000));
+}
+
+#[test]
+fn try_new_takes_over_transferred_buffers() {
+    // Trailing bytes are dropped without reallocating.
+    let pixels = vec![128; 16];
+    let pointer = pixels.as_ptr();
+    let frame = VideoFrame::try_new(i420_init(Cow::Owned(pixels), true)).unwrap();
+
+    assert_eq!(frame.data.as_ptr(), pointer);
+    assert_eq!(frame.data.len(), 12);
+}
+
+#[test]
+fn try_new_rejects_invalid_inits() {
+    let pixels = vec![0; 12];
+    let kind = |init: VideoFrameBufferInit| VideoFrame::try_new(init).unwrap_err().kind();
+
+    let mut init = i420_init(Cow::Borrowed(&pixels), false);
+    init.format = "YUV9".to_string();
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+
+    let mut init = i420_init(Cow::Borrowed(&pixels), false);
+    init.coded_height = 0;
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+
+    let init = i420_init(Cow::Borrowed(&pixels[..11]), false);
+    assert_eq!(kind(init), ExceptionKind::TypeError);
+}